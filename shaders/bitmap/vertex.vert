@@ -0,0 +1,27 @@
+const ivec2 uvs[6] = ivec2[6](
+    ivec2(0, 0),
+    ivec2(1, 0),
+    ivec2(1, 1),
+
+    ivec2(0, 1),
+    ivec2(0, 0),
+    ivec2(1, 1)
+);
+out vec2 uv;
+uniform float zoom;
+
+uniform int layer;
+
+void main() {
+    ivec2 corner = uvs[gl_VertexID % 6];
+
+    // the bitmap covers the whole screen, row 0 of the texture is the top of the screen
+    uv = vec2(corner);
+
+    gl_Position = vec4(0.0, 0.0, float(layer)/255.0, 1.0);
+    gl_Position.x = float(corner.x) * 2.0 - 1.0;
+    gl_Position.y = float(corner.y) * -2.0 + 1.0;
+
+    gl_Position.x *= zoom;
+    gl_Position.y *= zoom;
+}
@@ -0,0 +1,41 @@
+precision mediump float;
+                
+out vec4 FragColor;
+in vec2 uv;
+
+uniform sampler2D tex;
+
+uniform int screen_px_x;
+uniform int screen_px_y;
+
+// 0: none, 1: mosaic, 2: tint, 3: fade, 4: scanlines
+uniform int mode;
+uniform vec4 params;
+
+void main() {
+    ivec2 size = ivec2(screen_px_x, screen_px_y);
+    ivec2 pixel = clamp(ivec2(uv * vec2(size)), ivec2(0, 0), size - 1);
+    // scanlines are counted from the top of the screen
+    int line = size.y - 1 - pixel.y;
+
+    if (mode == 1) {
+        int block = max(int(params.x), 1);
+        pixel.x = pixel.x / block * block;
+        pixel.y = size.y - 1 - line / block * block;
+    }
+
+    // colors are premultiplied
+    vec4 color = texelFetch(tex, pixel, 0);
+
+    if (mode == 2) {
+        color.rgb = mix(color.rgb, params.rgb * color.a, params.a);
+    } else if (mode == 3) {
+        color = mix(color, vec4(0.0, 0.0, 0.0, 1.0), clamp(params.x, 0.0, 1.0));
+    } else if (mode == 4) {
+        if (line % 2 == 1) {
+            color.rgb *= 1.0 - clamp(params.x, 0.0, 1.0);
+        }
+    }
+
+    FragColor = color;
+}
@@ -0,0 +1,23 @@
+const ivec2 uvs[6] = ivec2[6](
+    ivec2(0, 0),
+    ivec2(1, 0),
+    ivec2(1, 1),
+
+    ivec2(0, 1),
+    ivec2(0, 0),
+    ivec2(1, 1)
+);
+out vec2 uv;
+uniform float zoom;
+
+void main() {
+    ivec2 corner = uvs[gl_VertexID % 6];
+
+    // framebuffer textures start at the bottom of the screen, same as clip space
+    uv = vec2(corner);
+
+    gl_Position = vec4(vec2(corner) * 2.0 - 1.0, 0.0, 1.0);
+
+    gl_Position.x *= zoom;
+    gl_Position.y *= zoom;
+}
@@ -1,12 +1,68 @@
 precision mediump float;
                 
 out vec4 FragColor;
-in vec2 uv;
+in highp vec2 uv;
+flat in int palette;
+flat in int blend;
+flat in int transformed;
+flat in ivec4 sheet_rect;
+flat in int orientation;
+in highp vec2 local;
+flat in int instance;
+in highp float line;
 
 uniform sampler2D tex;
+// one palette per row, used when the texture holds indices
+uniform sampler2D palettes;
+uniform int indexed;
+// whether each sprite is drawn on each screen line, one row per sprite, see SpriteLines
+uniform sampler2D sprite_lines;
+uniform int limited;
+
+// same as texel in software.rs: mirror, then rotate the sheet area clockwise
+ivec2 texel(ivec2 pos, ivec2 size) {
+    if ((orientation & 1) != 0) {
+        pos.x = size.x - 1 - pos.x;
+    }
+    if ((orientation & 2) != 0) {
+        pos.y = size.y - 1 - pos.y;
+    }
+    int rotate = (orientation >> 2) & 3;
+    for (int i = 0; i < rotate; i++) {
+        pos = ivec2(pos.y, size.x - 1 - pos.x);
+        size = size.yx;
+    }
+    return pos;
+}
 
 void main() {
-    FragColor = texture(tex, uv);
+    if (limited != 0) {
+        int y = int(floor(line));
+        if (y < 0 || y >= textureSize(sprite_lines, 0).x
+            || texelFetch(sprite_lines, ivec2(y, instance), 0).r < 0.5) {
+            discard;
+        }
+    }
+    if (transformed != 0) {
+        ivec2 pos = ivec2(floor(local));
+        if (any(lessThan(pos, ivec2(0))) || any(greaterThanEqual(pos, sheet_rect.zw))) {
+            discard;
+        }
+        // textures repeat
+        ivec2 sheet_size = textureSize(tex, 0);
+        FragColor = texelFetch(tex, (sheet_rect.xy + texel(pos, sheet_rect.zw)) % sheet_size, 0);
+    } else {
+        FragColor = texture(tex, uv);
+    }
+    if (indexed != 0) {
+        int index = int(FragColor.r * 255.0 + 0.5);
+        FragColor = texelFetch(palettes, ivec2(index, palette % textureSize(palettes, 0).y), 0);
+    }
+    // in whole steps like software.rs
+    if (blend != 255) {
+        highp int alpha = int(FragColor.a * 255.0 + 0.5);
+        FragColor.a = float((alpha * blend + 127) / 255) / 255.0;
+    }
     // FragColor.y *= 0.5;
     // FragColor.z *= 0.5;
 }
\ No newline at end of file
@@ -17,8 +17,25 @@ const ivec2 uvs[6] = ivec2[6](
     ivec2(1, 1)
 );
 out vec2 uv;
+flat out int palette;
+// Sprite::blend, 255 is opaque
+flat out int blend;
+// affine sprites are found per fragment instead, see SpriteAttributes::AFFINE
+flat out int transformed;
+// the sprite's pixel at the corner of the sheet area, its width and height on screen
+flat out ivec4 sheet_rect;
+// flips and rotation, applied in the fragment shader for affine sprites
+flat out int orientation;
+// pixel inside the sprite before it's flipped and rotated
+out vec2 local;
+// for SpriteMapContext::limits, which sprite this is and the screen line
+flat out int instance;
+out float line;
 uniform float zoom;
 
+// matrices of SpriteMapContext::affine
+uniform vec4 affine[32];
+
 uniform int map_width; 
 uniform int map_height;
 
@@ -28,6 +45,12 @@ uniform int screen_px_y;
 uniform int pan_x;
 uniform int pan_y;
 
+// only tiles/sprites whose layer matches are drawn, see RetroGraphics::composite
+uniform int priority;
+
+// which of the records below is enabled, see SpriteFormat
+uniform int compact;
+
 
 
 
@@ -36,8 +59,13 @@ struct Sprite
 {
     int pos;
     int attributes;
+    int flags;
 };
+// CompactSprite, x | y << 16 and tx | ty << 8 | layer << 16 | attributes << 24
 layout (location = 2) in ivec2 spriteData;
+// Sprite, x, y, tx | ty << 16, layer | palette << 8 | blend << 16 and then the attributes
+layout (location = 3) in ivec4 extendedData;
+layout (location = 4) in int extendedAttributes;
 
 // layout(std430, binding = 2) buffer spriteBuf
 // {
@@ -48,27 +76,49 @@ void main() {
 
 
     Sprite sprite;
-    sprite.pos = spriteData.x;
-    sprite.attributes = spriteData.y;
+    int sprite_x;
+    int sprite_y;
+    int uv_x;
+    int uv_y;
+    int layer;
+    if (compact != 0) {
+        sprite.pos = spriteData.x;
+        sprite.attributes = spriteData.y;
+        // the same bits as the low byte of SpriteAttributes
+        sprite.flags = (spriteData.y >> 24) & 0xFF;
+
+        sprite_x = sprite.pos & 0xFFFF;
+        sprite_y = (sprite.pos >> 16) & 0xFFFF;
 
-    int sprite_x = sprite.pos & 0xFFFF;
-    int sprite_y = (sprite.pos >> 16) & 0xFFFF;
+        uv_x = ((sprite.attributes) & 0xFF)*8;
+        uv_y = ((sprite.attributes>>8) & 0xFF)*8;
 
+        layer = (sprite.attributes>>16) & 0xFF;
+        palette = 0;
+        blend = 255;
+    } else {
+        sprite.flags = extendedAttributes;
 
-    int uv_x = ((sprite.attributes) & 0xFF)*8;
-    int uv_y = ((sprite.attributes>>8) & 0xFF)*8;
+        sprite_x = extendedData.x;
+        sprite_y = extendedData.y;
 
-    int layer = (sprite.attributes>>16) & 0xFF;
+        uv_x = (extendedData.z & 0xFFFF)*8;
+        uv_y = ((extendedData.z>>16) & 0xFFFF)*8;
 
+        layer = extendedData.w & 0xFF;
+        palette = (extendedData.w>>8) & 0xFF;
+        blend = (extendedData.w>>16) & 0xFF;
+    }
 
-    int flip_h = (sprite.attributes>>(24+0)) & 1;
-    int flip_v = (sprite.attributes>>(24+1)) & 1;
 
-    int rotate = (sprite.attributes>>(24+2)) & 3;
+    int flip_h = (sprite.flags>>0) & 1;
+    int flip_v = (sprite.flags>>1) & 1;
+
+    int rotate = (sprite.flags>>2) & 3;
 
     // size in px (8, 16, 24, 32)
-    int x_size = ((sprite.attributes>>(24+4)) & 3)*8 + 8;
-    int y_size = ((sprite.attributes>>(24+6)) & 3)*8 + 8;
+    int x_size = ((sprite.flags>>4) & 3)*8 + 8;
+    int y_size = ((sprite.flags>>6) & 3)*8 + 8;
 
 
 
@@ -107,11 +157,37 @@ void main() {
     pos.x += -pan_x + sprite_x;
     pos.y += -pan_y + sprite_y;
 
+    transformed = (sprite.flags>>12) & 1;
+    int double_size = (sprite.flags>>13) & 1;
+    int affine_index = (sprite.flags>>14) & 31;
+
+    ivec2 size = ivec2(y_size * rbit + x_size * (rbit^1), x_size * rbit + y_size * (rbit^1));
+    sheet_rect = ivec4(uv_x, uv_y, size);
+    orientation = sprite.flags & 15;
+    local = vec2(0.0);
+    if (transformed != 0) {
+        // the corners of the bounds, centered on the sprite
+        ivec2 bounds = size * (double_size + 1);
+        ivec2 corner = uvs[gl_VertexID % 6];
+        vec4 m = affine[affine_index];
+        vec2 offset = (vec2(corner) - 0.5) * vec2(bounds);
+        local = vec2(m.x * offset.x + m.y * offset.y, m.z * offset.x + m.w * offset.y) + vec2(size) * 0.5;
+        pos = corner * bounds + ivec2(sprite_x - pan_x, sprite_y - pan_y);
+    }
+
  
+    instance = gl_InstanceID;
+    line = float(pos.y);
+
     gl_Position = vec4(0.0, 0.0,  float(layer)/255.0, 1.0);
     gl_Position.x = float(pos.x) * 2.0/float(screen_px_x) - 1.0;
     gl_Position.y = float(pos.y) * -2.0/float(screen_px_y) + 1.0;
 
     gl_Position.x *= zoom;
     gl_Position.y *= zoom;
+
+    // pushed outside the clip volume
+    if (layer != priority) {
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+    }
 }
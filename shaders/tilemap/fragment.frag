@@ -2,11 +2,19 @@ precision mediump float;
                 
 out vec4 FragColor;
 in vec2 uv;
+flat in int palette;
 
 uniform sampler2D tex;
+// one palette per row, used when the texture holds indices
+uniform sampler2D palettes;
+uniform int indexed;
 
 void main() {
     FragColor = texture(tex, uv);
+    if (indexed != 0) {
+        int index = int(FragColor.r * 255.0 + 0.5);
+        FragColor = texelFetch(palettes, ivec2(index, palette), 0);
+    }
     // FragColor.y *= 0.5;
     // FragColor.z *= 0.5;
 }
\ No newline at end of file
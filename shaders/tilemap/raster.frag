@@ -0,0 +1,125 @@
+precision highp float;
+precision highp int;
+precision highp sampler2D;
+precision highp usampler2D;
+precision highp isampler2D;
+
+// Draws a tilemap with the effects of its Raster, one fragment per screen pixel. Must match
+// what shaders/tilemap/vertex.vert draws when there are none.
+
+out vec4 FragColor;
+in vec2 screen_pos;
+
+uniform sampler2D tex;
+// one palette per row, used when the texture holds indices
+uniform sampler2D palettes;
+uniform int indexed;
+
+// one texel per tile: x, y, layer and attributes
+uniform usampler2D tiles;
+// pan_x, pan_y added on each scanline, lines past the end have none
+uniform isampler2D scanlines;
+uniform int scanline_count;
+
+// matrix and center of each line in two rows, lines past the end use the uniforms below
+uniform sampler2D affine_lines;
+uniform int affine_count;
+// see Affine in tilemap.rs, used when transformed isn't 0
+uniform int transformed;
+uniform vec4 affine_matrix;
+uniform vec2 affine_center;
+
+// 0 wraps, 1 clamps, 2 fills with fill_tile, 3 is transparent, see EdgeMode
+uniform int edge;
+uniform uvec4 fill_tile;
+
+uniform int tiles_x;
+uniform int tiles_y;
+
+// pixels per tile, see TileMap::tile_width
+uniform int tile_width;
+uniform int tile_height;
+
+uniform int pan_x;
+uniform int pan_y;
+
+// only tiles whose layer matches are drawn, see RetroGraphics::composite
+uniform int priority;
+
+// / and % round towards 0 and aren't defined for negative numbers
+int floor_div(int a, int b) {
+    return a >= 0 ? a / b : -((-a - 1) / b) - 1;
+}
+
+int floor_mod(int a, int b) {
+    return a - floor_div(a, b) * b;
+}
+
+ivec2 apply(vec4 matrix, vec2 center, ivec2 pos) {
+    vec2 rel = vec2(pos) - center;
+    return ivec2(floor(vec2(matrix.x * rel.x + matrix.y * rel.y, matrix.z * rel.x + matrix.w * rel.y) + center));
+}
+
+void main() {
+    ivec2 pixel = ivec2(floor(screen_pos));
+    ivec2 map_px = pixel + ivec2(pan_x, pan_y);
+    if (pixel.y < scanline_count) {
+        map_px += texelFetch(scanlines, ivec2(pixel.y, 0), 0).xy;
+    }
+    if (pixel.y < affine_count) {
+        vec4 matrix = texelFetch(affine_lines, ivec2(pixel.y, 0), 0);
+        vec2 center = texelFetch(affine_lines, ivec2(pixel.y, 1), 0).xy;
+        map_px = apply(matrix, center, map_px);
+    } else if (transformed != 0) {
+        map_px = apply(affine_matrix, affine_center, map_px);
+    }
+
+    ivec2 tile_size = ivec2(tile_width, tile_height);
+    ivec2 tile_pos = ivec2(floor_div(map_px.x, tile_width), floor_div(map_px.y, tile_height));
+    ivec2 in_tile = ivec2(floor_mod(map_px.x, tile_width), floor_mod(map_px.y, tile_height));
+    ivec2 tiles_size = ivec2(tiles_x, tiles_y);
+    bool outside = any(lessThan(tile_pos, ivec2(0))) || any(greaterThanEqual(tile_pos, tiles_size));
+
+    uvec4 tile;
+    if (edge == 0) {
+        tile = texelFetch(tiles, ivec2(floor_mod(tile_pos.x, tiles_x), floor_mod(tile_pos.y, tiles_y)), 0);
+    } else if (edge == 1) {
+        tile = texelFetch(tiles, clamp(tile_pos, ivec2(0), tiles_size - 1), 0);
+    } else if (!outside) {
+        tile = texelFetch(tiles, tile_pos, 0);
+    } else if (edge == 2) {
+        tile = fill_tile;
+    } else {
+        discard;
+    }
+    int layer = int(tile.z & 255u);
+    int attributes = int(tile.w);
+    if (layer != priority || ((attributes >> 8) & 1) != 0) {
+        discard;
+    }
+
+    // same as texel in software.rs: mirror, then rotate the sheet area clockwise
+    ivec2 size = tile_size;
+    if ((attributes & 1) != 0) {
+        in_tile.x = size.x - 1 - in_tile.x;
+    }
+    if ((attributes & 2) != 0) {
+        in_tile.y = size.y - 1 - in_tile.y;
+    }
+    int rotate = (attributes >> 2) & 3;
+    for (int i = 0; i < rotate; i++) {
+        in_tile = ivec2(in_tile.y, size.x - 1 - in_tile.x);
+        size = size.yx;
+    }
+    int palette = (attributes >> 4) & 15;
+
+    // textures repeat
+    ivec2 sheet_size = textureSize(tex, 0);
+    ivec2 texel = (ivec2(tile.xy) * tile_size + in_tile) % sheet_size;
+
+    FragColor = texelFetch(tex, texel, 0);
+    if (indexed != 0) {
+        int index = int(FragColor.r * 255.0 + 0.5);
+        FragColor = texelFetch(palettes, ivec2(index, palette), 0);
+    }
+}
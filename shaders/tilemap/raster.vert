@@ -0,0 +1,26 @@
+const ivec2 corners[6] = ivec2[6](
+    ivec2(0, 0),
+    ivec2(1, 0),
+    ivec2(1, 1),
+
+    ivec2(0, 1),
+    ivec2(0, 0),
+    ivec2(1, 1)
+);
+// screen pixel coordinates, the top left of the screen is 0, 0
+out vec2 screen_pos;
+uniform float zoom;
+
+uniform int screen_px_x;
+uniform int screen_px_y;
+
+void main() {
+    ivec2 corner = corners[gl_VertexID % 6];
+
+    screen_pos = vec2(float(corner.x * screen_px_x), float((1 - corner.y) * screen_px_y));
+
+    gl_Position = vec4(vec2(corner) * 2.0 - 1.0, 0.0, 1.0);
+
+    gl_Position.x *= zoom;
+    gl_Position.y *= zoom;
+}
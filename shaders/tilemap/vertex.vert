@@ -17,20 +17,31 @@ const ivec2 uvs[6] = ivec2[](
     ivec2(1, 1)
 );
 out vec2 uv;
+flat out int palette;
 uniform float zoom;
 
 uniform int map_width; 
 uniform int map_height;
 
+uniform int screen_px_x;
+uniform int screen_px_y;
+
 uniform int tiles_vis_x;
 uniform int tiles_vis_y;
 
 uniform int tiles_x;
 uniform int tiles_y;
 
+// pixels per tile, see TileMap::tile_width
+uniform int tile_width;
+uniform int tile_height;
+
 uniform int pan_x;
 uniform int pan_y;
 
+// only tiles/sprites whose layer matches are drawn, see RetroGraphics::composite
+uniform int priority;
+
 
 struct Tile
 {
@@ -60,6 +71,8 @@ void main() {
     int flip_v = (tile.attributes>>16) & 2;
 
     int rotate = (tile.attributes>>18) & 3;
+    palette = (tile.attributes>>20) & 15;
+    int hidden = (tile.attributes>>24) & 1;
 
 
     int index = gl_VertexID;
@@ -71,10 +84,12 @@ void main() {
     uv.x = float(tuv.x) * -4.20e-07 + float(1 - tuv.x) * 4.20e-07;
     uv.y = float(tuv.y) * -4.20e-07 + float(1 - tuv.y) * 4.20e-07;
 
-    tuv.x *= 8;
-    tuv.y *= 8;
-    tuv.x += (tile.pos & 0xFFFF) * 8;
-    tuv.y += ((tile.pos >> 16) & 0xFFFF) * 8;
+    // turned a quarter the tile reads a tile_height by tile_width area
+    int rbit = rotate & 1;
+    tuv.x *= tile_height * rbit + tile_width * (rbit^1);
+    tuv.y *= tile_width * rbit + tile_height * (rbit^1);
+    tuv.x += (tile.pos & 0xFFFF) * tile_width;
+    tuv.y += ((tile.pos >> 16) & 0xFFFF) * tile_height;
 
     uv.x += float(tuv.x) / float(map_width);
     uv.y += float(tuv.y) / float(map_height);
@@ -83,22 +98,25 @@ void main() {
     index += index == 5 ? 1 : 0;
     index += rotate;
     index %= 4;
-    gl_Position = vec4(verts[index],  float(layer)/255.0, 1.0);
+    // corner of the tile in 0..1, flips mirror it inside the tile
+    vec2 corner = verts[index];
+    corner.x *= float(-(flip_h-1));
+    corner.y *= float(-(flip_v-1));
+    corner = (corner + 1.0) * 0.5;
 
-    gl_Position.x *= float(-(flip_h-1));
-    gl_Position.y *= float(-(flip_v-1));
+    // one tile pixel is one screen pixel
+    float pos_x = (float(tile_x) + corner.x) * float(tile_width) - float(pan_x%tile_width);
+    float pos_y = (float(tile_y) + corner.y) * float(tile_height) - float(pan_y%tile_height);
 
-
-    gl_Position.x /= float(tiles_vis_x);
-    gl_Position.x -= 1.0 - 1.0/float(tiles_vis_x);
-    gl_Position.x += float(tile_x) * 2.0/float(tiles_vis_x);
-    gl_Position.x -= float(pan_x%8)/8.0 * 2.0/float(tiles_vis_x);
-
-    gl_Position.y /= float(-tiles_vis_y);
-    gl_Position.y += 1.0 - 1.0/float(tiles_vis_y);
-    gl_Position.y -= float(tile_y) * 2.0/float(tiles_vis_y);
-    gl_Position.y += float(pan_y%8)/8.0 * 2.0/float(tiles_vis_y);
+    gl_Position = vec4(0.0, 0.0,  float(layer)/255.0, 1.0);
+    gl_Position.x = pos_x * 2.0/float(screen_px_x) - 1.0;
+    gl_Position.y = pos_y * -2.0/float(screen_px_y) + 1.0;
 
     gl_Position.x *= zoom;
     gl_Position.y *= zoom;
+
+    // pushed outside the clip volume
+    if (layer != priority || hidden != 0) {
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+    }
 }
@@ -0,0 +1,180 @@
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use crate::{
+    sprites::{Sprite, SpriteAttributes},
+    OutOfRange,
+};
+
+/// Frames a sprite steps through, played by [`crate::sprites::SpriteMapContext::play`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpriteClip {
+    pub frames: Vec<SpriteFrame>,
+}
+
+/// What a sprite looks like for one step of a clip, everything else about it is left alone.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpriteFrame {
+    pub tx: u16,
+    pub ty: u16,
+    /// Stored as (size / 8) - 1 like `SpriteAttributes::XSIZE`, files with bigger sizes are
+    /// rejected and bigger sizes set in code are drawn 32 pixels big.
+    #[serde(deserialize_with = "size")]
+    pub x_size: u32,
+    #[serde(deserialize_with = "size")]
+    pub y_size: u32,
+    pub flip_h: bool,
+    pub flip_v: bool,
+    /// Seconds it's shown for.
+    pub duration: f32,
+    /// Pixels the sprite is moved by while it's shown, so frames of different sizes line up.
+    pub offset_x: i32,
+    pub offset_y: i32,
+}
+
+fn size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let size = u32::deserialize(deserializer)?;
+    OutOfRange::check(size, SpriteAttributes::XSIZE.max_value(), "size").map_err(de::Error::custom)
+}
+
+impl SpriteFrame {
+    fn apply(&self, sprite: &mut Sprite) {
+        sprite.tx = self.tx;
+        sprite.ty = self.ty;
+        sprite
+            .attribute
+            .set(
+                SpriteAttributes::XSIZE,
+                self.x_size.min(SpriteAttributes::XSIZE.max_value()),
+            )
+            .set(
+                SpriteAttributes::YSIZE,
+                self.y_size.min(SpriteAttributes::YSIZE.max_value()),
+            )
+            .set(SpriteAttributes::HORIZONTAL, self.flip_h)
+            .set(SpriteAttributes::VERTICAL, self.flip_v);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlaybackMode {
+    /// Back to the first frame after the last.
+    #[default]
+    Loop,
+    /// Forwards then backwards, the first and last frames aren't repeated.
+    PingPong,
+    /// Stops on the last frame.
+    Once,
+}
+
+/// Where a sprite is in a clip.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpritePlayback {
+    /// Name of the clip in `SpriteMapContext::clips`.
+    pub clip: String,
+    pub mode: PlaybackMode,
+    frame: usize,
+    /// Seconds into the current frame.
+    time: f64,
+    backwards: bool,
+    finished: bool,
+    /// Offset of the frame last applied, taken back before the next one's is added.
+    pub(crate) offset: [i32; 2],
+}
+
+impl SpritePlayback {
+    pub fn new(clip: impl Into<String>, mode: PlaybackMode) -> Self {
+        Self {
+            clip: clip.into(),
+            mode,
+            frame: 0,
+            time: 0.0,
+            backwards: false,
+            finished: false,
+            offset: [0, 0],
+        }
+    }
+
+    /// Index of the frame it's showing.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    /// Whether a [`PlaybackMode::Once`] clip reached the end of its last frame.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Moves `dt` seconds on, true when that changed the frame.
+    pub fn advance(&mut self, clip: &SpriteClip, dt: f64) -> bool {
+        let total: f64 = clip
+            .frames
+            .iter()
+            .map(|frame| frame.duration.max(0.0) as f64)
+            .sum();
+        // nothing to step through, also keeps frames without durations from spinning forever
+        if self.finished || total <= 0.0 {
+            return false;
+        }
+        let start = self.frame;
+        let len = clip.frames.len();
+        self.frame = self.frame.min(len - 1);
+        self.time += dt.max(0.0);
+
+        // whole cycles end up where they started, so at most one is stepped through
+        let cycle = match self.mode {
+            PlaybackMode::Loop => total,
+            PlaybackMode::PingPong if len > 1 => {
+                let first = clip.frames[0].duration.max(0.0) as f64;
+                let last = clip.frames[len - 1].duration.max(0.0) as f64;
+                2.0 * total - first - last
+            }
+            PlaybackMode::PingPong => total,
+            PlaybackMode::Once => f64::INFINITY,
+        };
+        if self.time >= cycle {
+            self.time = self.time.rem_euclid(cycle);
+        }
+        while !self.finished {
+            let duration = clip.frames[self.frame].duration.max(0.0) as f64;
+            if self.time < duration {
+                break;
+            }
+            self.time -= duration;
+            self.step(clip.frames.len());
+        }
+        self.frame != start
+    }
+
+    fn step(&mut self, len: usize) {
+        match self.mode {
+            PlaybackMode::Loop => self.frame = (self.frame + 1) % len,
+            PlaybackMode::Once if self.frame + 1 < len => self.frame += 1,
+            PlaybackMode::Once => self.finished = true,
+            PlaybackMode::PingPong if len > 1 => {
+                if self.frame == 0 {
+                    self.backwards = false;
+                } else if self.frame + 1 == len {
+                    self.backwards = true;
+                }
+                match self.backwards {
+                    true => self.frame -= 1,
+                    false => self.frame += 1,
+                }
+            }
+            PlaybackMode::PingPong => {}
+        }
+    }
+
+    /// Makes `sprite` look like the current frame and moves it by the frame's offset.
+    pub fn apply(&mut self, clip: &SpriteClip, sprite: &mut Sprite) {
+        let Some(frame) = clip.frames.get(self.frame) else {
+            return;
+        };
+        frame.apply(sprite);
+        let [x, y] = self.offset;
+        sprite.x += frame.offset_x - x;
+        sprite.y += frame.offset_y - y;
+        self.offset = [frame.offset_x, frame.offset_y];
+    }
+}
@@ -0,0 +1,401 @@
+//! Imports sprite sheets exported from [Aseprite](https://www.aseprite.org/) as JSON, with
+//! frames either as a hash or as an array.
+//!
+//! Every frame becomes a [`SpriteFrame`], so frames have to sit on the 8 pixel grid of the
+//! sheet and be 8, 16, 24 or 32 pixels wide and high. Trimmed frames are offset by how much
+//! was trimmed off their top left. Every tag becomes a [`SpriteClip`] named after it, with
+//! the frames of reverse tags reversed. Slices are kept as they are for the game to use.
+//! Rotated frames aren't supported.
+
+use std::collections::BTreeMap;
+
+use serde::{de, Deserialize};
+
+use crate::{
+    animation::{PlaybackMode, SpriteClip, SpriteFrame},
+    resources::{ResourceManager, TextureHandle},
+};
+
+/// The parts of an Aseprite sheet the importer uses.
+pub struct AsepriteSheet {
+    /// Path of the sheet image, relative to the JSON file.
+    pub image: Option<String>,
+    pub frames: Vec<AsepriteFrame>,
+    pub tags: Vec<AsepriteTag>,
+    pub slices: Vec<AsepriteSlice>,
+}
+
+pub struct AsepriteFrame {
+    /// The frame's key in the hash layout or its `filename` in the array layout.
+    pub name: String,
+    /// Where it is in the sheet, in pixels.
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Where it is within the untrimmed frame.
+    pub offset_x: i32,
+    pub offset_y: i32,
+    /// Milliseconds.
+    pub duration: u32,
+}
+
+pub struct AsepriteTag {
+    pub name: String,
+    /// First and last frame, both included.
+    pub from: usize,
+    pub to: usize,
+    pub direction: AsepriteDirection,
+    /// How many times it plays, forever when it's `None`.
+    pub repeat: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum AsepriteDirection {
+    #[serde(rename = "forward")]
+    Forward,
+    #[serde(rename = "reverse")]
+    Reverse,
+    #[serde(rename = "pingpong")]
+    PingPong,
+    #[serde(rename = "pingpong_reverse")]
+    PingPongReverse,
+}
+
+/// A named rectangle, like a hitbox, that can change from frame to frame.
+pub struct AsepriteSlice {
+    pub name: String,
+    /// In frame order, each one holds until the next.
+    pub keys: Vec<AsepriteSliceKey>,
+}
+
+pub struct AsepriteSliceKey {
+    pub frame: usize,
+    /// Relative to the untrimmed frame.
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub pivot: Option<(i32, i32)>,
+}
+
+#[derive(Debug)]
+pub enum AsepriteError {
+    Json(serde_json::Error),
+    Image(image::ImageError),
+    /// A frame can't be drawn as one sprite.
+    BadFrame(String),
+    /// A tag uses frames that don't exist.
+    BadTag(String),
+    Unsupported(String),
+}
+
+impl std::fmt::Display for AsepriteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AsepriteError::Json(err) => write!(f, "{err}"),
+            AsepriteError::Image(err) => write!(f, "{err}"),
+            AsepriteError::BadFrame(what) => write!(f, "bad frame: {what}"),
+            AsepriteError::BadTag(name) => write!(f, "tag {name} uses missing frames"),
+            AsepriteError::Unsupported(what) => write!(f, "unsupported: {what}"),
+        }
+    }
+}
+
+impl std::error::Error for AsepriteError {}
+
+impl From<serde_json::Error> for AsepriteError {
+    fn from(err: serde_json::Error) -> Self {
+        AsepriteError::Json(err)
+    }
+}
+
+impl From<image::ImageError> for AsepriteError {
+    fn from(err: image::ImageError) -> Self {
+        AsepriteError::Image(err)
+    }
+}
+
+impl AsepriteTag {
+    /// The playback mode closest to how Aseprite plays it.
+    pub fn mode(&self) -> PlaybackMode {
+        match (self.direction, self.repeat) {
+            (AsepriteDirection::PingPong | AsepriteDirection::PingPongReverse, _) => {
+                PlaybackMode::PingPong
+            }
+            (_, Some(1)) => PlaybackMode::Once,
+            _ => PlaybackMode::Loop,
+        }
+    }
+}
+
+impl AsepriteSlice {
+    /// The key that holds on `frame`, none before the first one.
+    pub fn key(&self, frame: usize) -> Option<&AsepriteSliceKey> {
+        self.keys.iter().rev().find(|key| key.frame <= frame)
+    }
+}
+
+impl AsepriteSheet {
+    pub fn parse(bytes: &[u8]) -> Result<Self, AsepriteError> {
+        let json: JsonSheet = serde_json::from_slice(bytes)?;
+        let frames = match json.frames {
+            JsonFrames::Array(frames) => frames,
+            JsonFrames::Hash(FrameHash(frames)) => frames,
+        };
+        if let Some(frame) = frames.iter().find(|frame| frame.rotated) {
+            return Err(AsepriteError::Unsupported(format!(
+                "rotated frame {}",
+                frame.filename
+            )));
+        }
+        let tags = json
+            .meta
+            .frame_tags
+            .into_iter()
+            .map(|tag| AsepriteTag {
+                name: tag.name,
+                from: tag.from,
+                to: tag.to,
+                direction: tag.direction,
+                repeat: tag.repeat.and_then(|repeat| repeat.trim().parse().ok()),
+            })
+            .collect();
+        let slices = json
+            .meta
+            .slices
+            .into_iter()
+            .map(|slice| AsepriteSlice {
+                name: slice.name,
+                keys: slice
+                    .keys
+                    .into_iter()
+                    .map(|key| AsepriteSliceKey {
+                        frame: key.frame,
+                        x: key.bounds.x,
+                        y: key.bounds.y,
+                        width: key.bounds.w,
+                        height: key.bounds.h,
+                        pivot: key.pivot.map(|pivot| (pivot.x, pivot.y)),
+                    })
+                    .collect(),
+            })
+            .collect();
+        Ok(Self {
+            image: json.meta.image,
+            frames: frames
+                .into_iter()
+                .map(|frame| AsepriteFrame {
+                    name: frame.filename,
+                    x: frame.frame.x,
+                    y: frame.frame.y,
+                    width: frame.frame.w,
+                    height: frame.frame.h,
+                    offset_x: frame.sprite_source_size.map_or(0, |source| source.x),
+                    offset_y: frame.sprite_source_size.map_or(0, |source| source.y),
+                    duration: frame.duration,
+                })
+                .collect(),
+            tags,
+            slices,
+        })
+    }
+
+    /// Every frame in order.
+    pub fn sprite_frames(&self) -> Result<Vec<SpriteFrame>, AsepriteError> {
+        self.frames.iter().map(sprite_frame).collect()
+    }
+
+    /// A clip for every tag.
+    pub fn clips(&self) -> Result<BTreeMap<String, SpriteClip>, AsepriteError> {
+        let frames = self.sprite_frames()?;
+        self.tags
+            .iter()
+            .map(|tag| {
+                let mut clip = frames
+                    .get(tag.from..=tag.to)
+                    .filter(|clip| !clip.is_empty())
+                    .ok_or_else(|| AsepriteError::BadTag(tag.name.clone()))?
+                    .to_vec();
+                if matches!(
+                    tag.direction,
+                    AsepriteDirection::Reverse | AsepriteDirection::PingPongReverse
+                ) {
+                    clip.reverse();
+                }
+                Ok((tag.name.clone(), SpriteClip { frames: clip }))
+            })
+            .collect()
+    }
+
+    /// Registers the sheet image, named after the file stem of [`AsepriteSheet::image`], and
+    /// returns it with the clips of every tag. Nothing is registered if the sheet is invalid.
+    pub fn import(
+        &self,
+        gl: &glow::Context,
+        resources: &mut ResourceManager,
+        image: &[u8],
+    ) -> Result<(TextureHandle, BTreeMap<String, SpriteClip>), AsepriteError> {
+        let clips = self.clips()?;
+        let image = image::load_from_memory(image)?.to_rgba8();
+        // the positions and sizes come from the file, adding them can overflow
+        let outside =
+            |start: u32, size: u32, end: u32| start.checked_add(size).is_none_or(|last| last > end);
+        if let Some(frame) = self.frames.iter().find(|frame| {
+            outside(frame.x, frame.width, image.width())
+                || outside(frame.y, frame.height, image.height())
+        }) {
+            return Err(AsepriteError::BadFrame(format!(
+                "{} is outside the image",
+                frame.name
+            )));
+        }
+        let name = self
+            .image
+            .as_deref()
+            .and_then(|path| std::path::Path::new(path).file_stem()?.to_str())
+            .unwrap_or("aseprite");
+        Ok((resources.create_texture(gl, name, image), clips))
+    }
+}
+
+fn sprite_frame(frame: &AsepriteFrame) -> Result<SpriteFrame, AsepriteError> {
+    let size = |pixels: u32| {
+        (pixels.is_multiple_of(8) && (8..=32).contains(&pixels)).then(|| pixels / 8 - 1)
+    };
+    let tile = |pixels: u32| {
+        pixels
+            .is_multiple_of(8)
+            .then(|| u16::try_from(pixels / 8).ok())?
+    };
+    let (Some(x_size), Some(y_size), Some(tx), Some(ty)) = (
+        size(frame.width),
+        size(frame.height),
+        tile(frame.x),
+        tile(frame.y),
+    ) else {
+        return Err(AsepriteError::BadFrame(format!(
+            "{} is {}x{} at {}, {}, it has to be on the 8 pixel grid and 8 to 32 pixels big",
+            frame.name, frame.width, frame.height, frame.x, frame.y
+        )));
+    };
+    Ok(SpriteFrame {
+        tx,
+        ty,
+        x_size,
+        y_size,
+        duration: frame.duration as f32 / 1000.0,
+        offset_x: frame.offset_x,
+        offset_y: frame.offset_y,
+        ..Default::default()
+    })
+}
+
+#[derive(Deserialize)]
+struct JsonSheet {
+    frames: JsonFrames,
+    #[serde(default)]
+    meta: JsonMeta,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonFrames {
+    Array(Vec<JsonFrame>),
+    Hash(FrameHash),
+}
+
+/// Frames keyed by name, in the order they're in the file.
+struct FrameHash(Vec<JsonFrame>);
+
+impl<'de> Deserialize<'de> for FrameHash {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Frames;
+
+        impl<'de> de::Visitor<'de> for Frames {
+            type Value = FrameHash;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("frames by name")
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<FrameHash, A::Error> {
+                let mut frames = Vec::new();
+                while let Some((name, mut frame)) = map.next_entry::<String, JsonFrame>()? {
+                    frame.filename = name;
+                    frames.push(frame);
+                }
+                Ok(FrameHash(frames))
+            }
+        }
+
+        deserializer.deserialize_map(Frames)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonFrame {
+    #[serde(default)]
+    filename: String,
+    frame: JsonRect,
+    #[serde(default)]
+    rotated: bool,
+    sprite_source_size: Option<JsonPoint>,
+    duration: u32,
+}
+
+#[derive(Deserialize)]
+struct JsonRect {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+struct JsonPoint {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonMeta {
+    image: Option<String>,
+    #[serde(default)]
+    frame_tags: Vec<JsonTag>,
+    #[serde(default)]
+    slices: Vec<JsonSlice>,
+}
+
+#[derive(Deserialize)]
+struct JsonTag {
+    name: String,
+    from: usize,
+    to: usize,
+    direction: AsepriteDirection,
+    /// A number in a string.
+    repeat: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JsonSlice {
+    name: String,
+    keys: Vec<JsonSliceKey>,
+}
+
+#[derive(Deserialize)]
+struct JsonSliceKey {
+    frame: usize,
+    bounds: JsonBounds,
+    pivot: Option<JsonPoint>,
+}
+
+#[derive(Deserialize)]
+struct JsonBounds {
+    x: i32,
+    y: i32,
+    w: u32,
+    h: u32,
+}
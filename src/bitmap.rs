@@ -0,0 +1,347 @@
+use glow::HasContext;
+
+use crate::{resources::ResourceManager, ScreenContext};
+
+pub type Color = [u8; 4];
+
+/// A direct color pixel buffer that keeps track of what changed, all drawing clips to its
+/// edges.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Bitmap {
+    width: i32,
+    height: i32,
+    pixels: Vec<Color>,
+    // x0, y0, x1, y1 (exclusive) of the pixels changed since the last upload
+    dirty: Option<(i32, i32, i32, i32)>,
+}
+
+pub struct BitmapContext {
+    pub layer: u8,
+    pub bitmap: Bitmap,
+
+    program: glow::Program,
+    vertex_array: glow::VertexArray,
+    texture: glow::Texture,
+    texture_width: i32,
+    texture_height: i32,
+}
+
+impl Bitmap {
+    pub fn new(width: i32, height: i32) -> Self {
+        let mut bitmap = Self::default();
+        bitmap.resize(width, height);
+        bitmap
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    /// Resizes the buffer, keeping whatever pixels overlap the old size.
+    pub fn resize(&mut self, width: i32, height: i32) {
+        let width = width.max(0);
+        let height = height.max(0);
+        if width == self.width && height == self.height {
+            return;
+        }
+
+        // in usize, the pixel count of big bitmaps doesn't fit an i32
+        let (old_width, new_width) = (self.width as usize, width as usize);
+        let mut pixels = vec![[0; 4]; new_width * height as usize];
+        for y in 0..height.min(self.height) as usize {
+            for x in 0..width.min(self.width) as usize {
+                pixels[x + y * new_width] = self.pixels[x + y * old_width];
+            }
+        }
+
+        self.pixels = pixels;
+        self.width = width;
+        self.height = height;
+        self.dirty = Some((0, 0, width, height));
+    }
+
+    pub fn get_pixel(&self, x: i32, y: i32) -> Option<Color> {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(self.pixels[(x + y * self.width) as usize])
+    }
+
+    pub fn put_pixel(&mut self, x: i32, y: i32, color: Color) {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return;
+        }
+        self.pixels[(x + y * self.width) as usize] = color;
+        self.mark_dirty(x, y, x + 1, y + 1);
+    }
+
+    pub fn clear(&mut self, color: Color) {
+        self.pixels.fill(color);
+        self.mark_dirty(0, 0, self.width, self.height);
+    }
+
+    pub fn line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: Color) {
+        // Bresenham, works in every octant
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let sx = if x0 < x1 { 1 } else { -1 };
+        let sy = if y0 < y1 { 1 } else { -1 };
+        let mut err = dx + dy;
+
+        let (mut x, mut y) = (x0, y0);
+        loop {
+            self.put_pixel(x, y, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    /// Outline of the `width` x `height` rectangle with its top left corner at `x`, `y`.
+    pub fn rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: Color) {
+        if width <= 0 || height <= 0 {
+            return;
+        }
+        let (x1, y1) = (x + width - 1, y + height - 1);
+        self.line(x, y, x1, y, color);
+        self.line(x, y1, x1, y1, color);
+        self.line(x, y, x, y1, color);
+        self.line(x1, y, x1, y1, color);
+    }
+
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: Color) {
+        let x0 = x.max(0);
+        let y0 = y.max(0);
+        let x1 = (x + width).min(self.width);
+        let y1 = (y + height).min(self.height);
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+        for y in y0..y1 {
+            let row = (y * self.width) as usize;
+            self.pixels[row + x0 as usize..row + x1 as usize].fill(color);
+        }
+        self.mark_dirty(x0, y0, x1, y1);
+    }
+
+    /// Copies a `width` x `height` block of row major `pixels` into the bitmap, clipping
+    /// whatever falls outside of it. If `pixels` is too short only what's there is copied.
+    pub fn blit(&mut self, x: i32, y: i32, width: i32, height: i32, pixels: &[Color]) {
+        if width <= 0 {
+            return;
+        }
+        let rows = pixels
+            .len()
+            .div_ceil(width as usize)
+            .min(height.max(0) as usize) as i32;
+        let x0 = x.max(0);
+        let y0 = y.max(0);
+        let x1 = (x + width).min(self.width);
+        let y1 = (y + rows).min(self.height);
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+        for dy in y0..y1 {
+            let src = ((x0 - x) + (dy - y) * width) as usize;
+            let dst = (x0 + dy * self.width) as usize;
+            let len = (x1 - x0) as usize;
+            let Some(row) = pixels.get(src..pixels.len().min(src + len)) else {
+                break;
+            };
+            self.pixels[dst..dst + row.len()].copy_from_slice(row);
+        }
+        self.mark_dirty(x0, y0, x1, y1);
+    }
+
+    fn mark_dirty(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) {
+        self.dirty = Some(match self.dirty {
+            Some((dx0, dy0, dx1, dy1)) => (dx0.min(x0), dy0.min(y0), dx1.max(x1), dy1.max(y1)),
+            None => (x0, y0, x1, y1),
+        });
+    }
+}
+
+impl BitmapContext {
+    pub fn new(
+        gl: &glow::Context,
+        resources: &mut ResourceManager,
+        width: i32,
+        height: i32,
+    ) -> Option<Self> {
+        let program;
+        let vertex_array;
+        let texture;
+        unsafe {
+            program = resources.get_program(
+                gl,
+                "bitmap",
+                &[
+                    (
+                        crate::resources::ProgramKind::Vertex,
+                        include_str!("../shaders/bitmap/vertex.vert"),
+                    ),
+                    (
+                        crate::resources::ProgramKind::Fragment,
+                        include_str!("../shaders/bitmap/fragment.frag"),
+                    ),
+                ],
+            )?;
+
+            vertex_array = gl
+                .create_vertex_array()
+                .expect("Cannot create vertex array");
+
+            texture = gl.create_texture().ok()?;
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_WRAP_S,
+                glow::CLAMP_TO_EDGE as i32,
+            );
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_WRAP_T,
+                glow::CLAMP_TO_EDGE as i32,
+            );
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MIN_FILTER,
+                glow::NEAREST as i32,
+            );
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MAG_FILTER,
+                glow::NEAREST as i32,
+            );
+        }
+
+        Some(Self {
+            layer: 0,
+            bitmap: Bitmap::new(width, height),
+            program,
+            vertex_array,
+            texture,
+            texture_width: 0,
+            texture_height: 0,
+        })
+    }
+
+    /// # Safety
+    /// `gl` must be the context this was created with and it can't be painted afterwards.
+    pub unsafe fn destroy(&self, gl: &glow::Context) {
+        gl.delete_vertex_array(self.vertex_array);
+        gl.delete_texture(self.texture);
+    }
+
+    unsafe fn upload(&mut self, gl: &glow::Context) {
+        gl.bind_texture(glow::TEXTURE_2D, Some(self.texture));
+        let bitmap = &mut self.bitmap;
+
+        if self.texture_width != bitmap.width || self.texture_height != bitmap.height {
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA8 as i32,
+                bitmap.width,
+                bitmap.height,
+                0,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                None,
+            );
+            self.texture_width = bitmap.width;
+            self.texture_height = bitmap.height;
+            bitmap.dirty = Some((0, 0, bitmap.width, bitmap.height));
+        }
+
+        let Some((x0, y0, x1, y1)) = bitmap.dirty.take() else {
+            return;
+        };
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+
+        let raw_data: &[u8] = std::slice::from_raw_parts(
+            bitmap.pixels.as_ptr().cast(),
+            bitmap.pixels.len() * std::mem::size_of::<Color>(),
+        );
+        let start = (x0 + y0 * bitmap.width) as usize * std::mem::size_of::<Color>();
+
+        gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+        gl.pixel_store_i32(glow::UNPACK_ROW_LENGTH, bitmap.width);
+        gl.tex_sub_image_2d(
+            glow::TEXTURE_2D,
+            0,
+            x0,
+            y0,
+            x1 - x0,
+            y1 - y0,
+            glow::RGBA,
+            glow::UNSIGNED_BYTE,
+            glow::PixelUnpackData::Slice(&raw_data[start..]),
+        );
+        gl.pixel_store_i32(glow::UNPACK_ROW_LENGTH, 0);
+        gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+    }
+
+    /// Resizes the bitmap to the screen and uploads the pixels changed since the last frame.
+    pub fn prepare(
+        &mut self,
+        gl: &glow::Context,
+        screen: &ScreenContext,
+        priorities: &mut [bool; 256],
+    ) {
+        self.bitmap.resize(screen.screen_px_x, screen.screen_px_y);
+        if self.bitmap.width == 0 || self.bitmap.height == 0 {
+            return;
+        }
+
+        unsafe {
+            gl.active_texture(glow::TEXTURE0);
+            self.upload(gl);
+        }
+        priorities[self.layer as usize] = true;
+    }
+
+    /// Draws the bitmap if `layer` is `priority`.
+    pub fn paint(&self, gl: &glow::Context, screen: &ScreenContext, priority: u8) {
+        if priority != self.layer || self.bitmap.width == 0 || self.bitmap.height == 0 {
+            return;
+        }
+
+        unsafe {
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.texture));
+
+            gl.use_program(Some(self.program));
+            gl.uniform_1_f32(
+                gl.get_uniform_location(self.program, "zoom").as_ref(),
+                screen.zoom,
+            );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "layer").as_ref(),
+                self.layer as i32,
+            );
+
+            gl.bind_vertex_array(Some(self.vertex_array));
+            gl.draw_arrays(glow::TRIANGLES, 0, 6);
+        }
+    }
+}
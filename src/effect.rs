@@ -0,0 +1,203 @@
+use glow::HasContext;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    resources::{ProgramError, ProgramKind, ResourceManager},
+    ScreenContext,
+};
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Effect {
+    /// Passes the layers beneath through unchanged.
+    None,
+    /// Replaces every `size` x `size` block of pixels with its top left pixel.
+    Mosaic { size: u32 },
+    /// Mixes every pixel towards `color` by `color[3]`.
+    Tint { color: [f32; 4] },
+    /// Fades towards opaque black, 0.0 is untouched and 1.0 fully black.
+    Fade { amount: f32 },
+    /// Darkens every odd scanline by `intensity`.
+    Scanlines { intensity: f32 },
+    /// Parameters handed to a shader created with [`EffectContext::custom`].
+    Custom { params: [f32; 4] },
+}
+
+impl Effect {
+    pub const BUILT_IN: [Effect; 5] = [
+        Effect::None,
+        Effect::Mosaic { size: 4 },
+        Effect::Tint {
+            color: [1.0, 0.5, 0.2, 0.5],
+        },
+        Effect::Fade { amount: 0.5 },
+        Effect::Scanlines { intensity: 0.5 },
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Effect::None => "None",
+            Effect::Mosaic { .. } => "Mosaic",
+            Effect::Tint { .. } => "Tint",
+            Effect::Fade { .. } => "Fade",
+            Effect::Scanlines { .. } => "Scanlines",
+            Effect::Custom { .. } => "Custom",
+        }
+    }
+
+    // must match the modes in shaders/effect/fragment.frag
+    fn mode(&self) -> i32 {
+        match self {
+            Effect::None | Effect::Custom { .. } => 0,
+            Effect::Mosaic { .. } => 1,
+            Effect::Tint { .. } => 2,
+            Effect::Fade { .. } => 3,
+            Effect::Scanlines { .. } => 4,
+        }
+    }
+
+    fn params(&self) -> [f32; 4] {
+        match *self {
+            Effect::None => [0.0; 4],
+            Effect::Mosaic { size } => [size as f32, 0.0, 0.0, 0.0],
+            Effect::Tint { color } => color,
+            Effect::Fade { amount } => [amount, 0.0, 0.0, 0.0],
+            Effect::Scanlines { intensity } => [intensity, 0.0, 0.0, 0.0],
+            Effect::Custom { params } => params,
+        }
+    }
+}
+
+/// A full screen pass reading the composited output of every layer beneath it.
+///
+/// Fragment shaders given to [`EffectContext::custom`] get the same inputs as the built in
+/// effects: `in vec2 uv`, `uniform sampler2D tex` (premultiplied alpha), `uniform int screen_px_x`,
+/// `uniform int screen_px_y` and `uniform vec4 params`, and write to `out vec4 FragColor`.
+pub struct EffectContext {
+    pub effect: Effect,
+
+    // program name and fragment source of custom effects
+    custom: Option<(String, String)>,
+    program: glow::Program,
+    vertex_array: glow::VertexArray,
+}
+
+impl EffectContext {
+    pub fn new(
+        gl: &glow::Context,
+        resources: &mut ResourceManager,
+        effect: Effect,
+    ) -> Option<Self> {
+        let program = resources.get_program(
+            gl,
+            "effect",
+            &Self::sources(include_str!("../shaders/effect/fragment.frag")),
+        )?;
+        Some(Self::with_program(gl, program, effect, None))
+    }
+
+    /// Compiles `fragment_source` as the program `name`, sources are only used the first time
+    /// a name is seen. Fails with the info log if the shader doesn't compile.
+    pub fn custom(
+        gl: &glow::Context,
+        resources: &mut ResourceManager,
+        name: &str,
+        fragment_source: &str,
+        params: [f32; 4],
+    ) -> Result<Self, ProgramError> {
+        let program = resources.compile_program(gl, name, &Self::sources(fragment_source))?;
+        Ok(Self::with_program(
+            gl,
+            program,
+            Effect::Custom { params },
+            Some((name.to_owned(), fragment_source.to_owned())),
+        ))
+    }
+
+    fn sources(fragment_source: &str) -> [(ProgramKind, &str); 2] {
+        [
+            (
+                ProgramKind::Vertex,
+                include_str!("../shaders/effect/vertex.vert"),
+            ),
+            (ProgramKind::Fragment, fragment_source),
+        ]
+    }
+
+    fn with_program(
+        gl: &glow::Context,
+        program: glow::Program,
+        effect: Effect,
+        custom: Option<(String, String)>,
+    ) -> Self {
+        let vertex_array;
+        unsafe {
+            vertex_array = gl
+                .create_vertex_array()
+                .expect("Cannot create vertex array");
+        }
+
+        Self {
+            effect,
+            custom,
+            program,
+            vertex_array,
+        }
+    }
+
+    pub fn is_custom(&self) -> bool {
+        self.custom.is_some()
+    }
+
+    /// Program name and fragment source the effect was created with by
+    /// [`EffectContext::custom`].
+    pub fn custom_shader(&self) -> Option<(&str, &str)> {
+        self.custom
+            .as_ref()
+            .map(|(name, source)| (name.as_str(), source.as_str()))
+    }
+
+    /// # Safety
+    /// `gl` must be the context this was created with and it can't be painted afterwards.
+    pub unsafe fn destroy(&self, gl: &glow::Context) {
+        gl.delete_vertex_array(self.vertex_array);
+    }
+
+    /// Draws `source` through the effect over the whole viewport.
+    pub fn paint(&mut self, gl: &glow::Context, screen: &ScreenContext, source: glow::Texture) {
+        unsafe {
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(source));
+
+            gl.use_program(Some(self.program));
+            gl.uniform_1_f32(
+                gl.get_uniform_location(self.program, "zoom").as_ref(),
+                screen.zoom,
+            );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "screen_px_x")
+                    .as_ref(),
+                screen.screen_px_x,
+            );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "screen_px_y")
+                    .as_ref(),
+                screen.screen_px_y,
+            );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "mode").as_ref(),
+                self.effect.mode(),
+            );
+            let params = self.effect.params();
+            gl.uniform_4_f32(
+                gl.get_uniform_location(self.program, "params").as_ref(),
+                params[0],
+                params[1],
+                params[2],
+                params[3],
+            );
+
+            gl.bind_vertex_array(Some(self.vertex_array));
+            gl.draw_arrays(glow::TRIANGLES, 0, 6);
+        }
+    }
+}
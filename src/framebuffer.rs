@@ -0,0 +1,107 @@
+use glow::HasContext;
+
+/// An offscreen render target with a single RGBA color texture.
+pub struct Framebuffer {
+    framebuffer: glow::Framebuffer,
+    pub texture: glow::Texture,
+    width: i32,
+    height: i32,
+}
+
+impl Framebuffer {
+    pub fn new(gl: &glow::Context) -> Option<Self> {
+        unsafe {
+            let framebuffer = gl.create_framebuffer().ok()?;
+            let texture = gl.create_texture().ok()?;
+
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_WRAP_S,
+                glow::CLAMP_TO_EDGE as i32,
+            );
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_WRAP_T,
+                glow::CLAMP_TO_EDGE as i32,
+            );
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MIN_FILTER,
+                glow::NEAREST as i32,
+            );
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MAG_FILTER,
+                glow::NEAREST as i32,
+            );
+
+            Some(Self {
+                framebuffer,
+                texture,
+                width: 0,
+                height: 0,
+            })
+        }
+    }
+
+    /// # Safety
+    /// `gl` must be the context this was created with and it can't be used afterwards.
+    pub unsafe fn destroy(&self, gl: &glow::Context) {
+        gl.delete_framebuffer(self.framebuffer);
+        gl.delete_texture(self.texture);
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    /// Reallocates the color texture if the size changed, its contents are undefined afterwards.
+    pub fn resize(&mut self, gl: &glow::Context, width: i32, height: i32) {
+        let width = width.max(1);
+        let height = height.max(1);
+        if width == self.width && height == self.height {
+            return;
+        }
+
+        unsafe {
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.texture));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA8 as i32,
+                width,
+                height,
+                0,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                None,
+            );
+
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.framebuffer));
+            gl.framebuffer_texture_2d(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::TEXTURE_2D,
+                Some(self.texture),
+                0,
+            );
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+        }
+
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Binds the framebuffer as the draw target with a viewport covering all of it.
+    pub fn bind(&self, gl: &glow::Context) {
+        unsafe {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.framebuffer));
+            gl.viewport(0, 0, self.width, self.height);
+        }
+    }
+}
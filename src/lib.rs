@@ -1 +1,416 @@
+pub mod animation;
+pub mod aseprite;
+pub mod bitmap;
+pub mod effect;
+pub mod framebuffer;
+pub mod map_file;
+pub mod palette;
+pub mod resources;
+pub mod scene;
+pub mod software;
+pub mod sprites;
+pub mod tiled;
+pub mod tilemap;
+pub mod world;
 
+use bitmap::BitmapContext;
+use effect::{Effect, EffectContext};
+use framebuffer::Framebuffer;
+use image::RgbaImage;
+use resources::ResourceManager;
+use sprites::SpriteMapContext;
+use tilemap::TileMapContext;
+
+pub enum Layer {
+    Sprite(SpriteMapContext),
+    TileMap(TileMapContext),
+    Bitmap(BitmapContext),
+    Effect(EffectContext),
+}
+
+impl Layer {
+    /// # Safety
+    /// `gl` must be the context the layer was created with and the layer can't be painted afterwards.
+    pub unsafe fn destroy(&mut self, gl: &glow::Context) {
+        match self {
+            Layer::Sprite(l) => l.destroy(gl),
+            Layer::TileMap(l) => l.destroy(gl),
+            Layer::Bitmap(l) => l.destroy(gl),
+            Layer::Effect(l) => l.destroy(gl),
+        }
+    }
+
+    /// Uploads whatever changed since the last frame and marks the priorities the layer
+    /// draws at.
+    pub fn prepare(
+        &mut self,
+        gl: &glow::Context,
+        screen: &ScreenContext,
+        resources: &ResourceManager,
+        priorities: &mut [bool; 256],
+    ) {
+        match self {
+            Layer::Sprite(l) => l.prepare(gl, screen, priorities),
+            Layer::TileMap(l) => l.prepare(gl, screen, resources, priorities),
+            Layer::Bitmap(l) => l.prepare(gl, screen, priorities),
+            Layer::Effect(_) => {}
+        }
+    }
+
+    /// Draws the parts of the layer at `priority`, [`Layer::prepare`] has to run first.
+    pub fn paint(
+        &self,
+        gl: &glow::Context,
+        screen: &ScreenContext,
+        resources: &ResourceManager,
+        priority: u8,
+    ) {
+        match self {
+            Layer::Sprite(l) => l.paint(gl, screen, resources, priority),
+            Layer::TileMap(l) => l.paint(gl, screen, resources, priority),
+            Layer::Bitmap(l) => l.paint(gl, screen, priority),
+            // effects need the layers beneath them, RetroGraphics::paint applies them
+            Layer::Effect(_) => {}
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ScreenContext {
+    pub screen_px_x: i32,
+    pub screen_px_y: i32,
+    pub zoom: f32,
+}
+
+impl ScreenContext {
+    /// The screen pixel shown at `x`, `y` in the viewport `RetroGraphics::paint` draws to,
+    /// both going from 0.0 at the top left to 1.0 at the bottom right.
+    pub fn pixel_at(&self, x: f32, y: f32) -> (i32, i32) {
+        // the screen is scaled by the zoom around the center of the viewport
+        let x = ((x * 2.0 - 1.0) / self.zoom + 1.0) * 0.5;
+        let y = ((y * 2.0 - 1.0) / self.zoom + 1.0) * 0.5;
+        (
+            (x * self.screen_px_x as f32).floor() as i32,
+            (y * self.screen_px_y as f32).floor() as i32,
+        )
+    }
+
+    /// Where the top left corner of screen pixel `x`, `y` is in the viewport, the inverse of
+    /// [`ScreenContext::pixel_at`].
+    pub fn viewport_position(&self, x: i32, y: i32) -> (f32, f32) {
+        let x = (x as f32 / self.screen_px_x as f32 * 2.0 - 1.0) * self.zoom;
+        let y = (y as f32 / self.screen_px_y as f32 * 2.0 - 1.0) * self.zoom;
+        ((x + 1.0) * 0.5, (y + 1.0) * 0.5)
+    }
+}
+
+impl Default for ScreenContext {
+    fn default() -> Self {
+        Self {
+            screen_px_x: 256,
+            screen_px_y: 224,
+            zoom: 1.0,
+        }
+    }
+}
+
+/// Position of the scene camera in pixels, tilemaps and sprites scroll relative to it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Camera {
+    pub x: i32,
+    pub y: i32,
+}
+
+/// A value that doesn't fit the bits it's packed into, named after its field.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct OutOfRange(pub &'static str);
+
+impl std::fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is out of range", self.0)
+    }
+}
+
+impl std::error::Error for OutOfRange {}
+
+impl OutOfRange {
+    /// `value` if it's at most `max`.
+    pub fn check<T: PartialOrd>(value: T, max: T, field: &'static str) -> Result<T, Self> {
+        match value <= max {
+            true => Ok(value),
+            false => Err(Self(field)),
+        }
+    }
+}
+
+/// How a tilemap or sprite layer scrolls, its pan is `x`, `y` plus the camera position
+/// scaled by the parallax factors.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Scroll {
+    /// The layer's own scroll position in pixels.
+    pub x: i32,
+    pub y: i32,
+    /// 1.0 moves with the camera, less scrolls slower like a distant background and 0.0
+    /// stays put.
+    pub parallax_x: f32,
+    pub parallax_y: f32,
+}
+
+impl Default for Scroll {
+    fn default() -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            parallax_x: 1.0,
+            parallax_y: 1.0,
+        }
+    }
+}
+
+impl Scroll {
+    /// The pan for the camera at `camera`.
+    pub fn pan(&self, camera: Camera) -> (i32, i32) {
+        (
+            self.x + (camera.x as f32 * self.parallax_x).floor() as i32,
+            self.y + (camera.y as f32 * self.parallax_y).floor() as i32,
+        )
+    }
+}
+
+pub struct RetroGraphics {
+    pub resources: ResourceManager,
+    pub screen: ScreenContext,
+    pub camera: Camera,
+    /// Painted last to first, see [`RetroGraphics::composite`] for how they're ordered.
+    pub layers: Vec<Layer>,
+    // layers are composited offscreen at native resolution, effects ping pong between these
+    targets: [Framebuffer; 2],
+    present: EffectContext,
+}
+
+impl RetroGraphics {
+    pub fn new(gl: &glow::Context) -> Option<Self> {
+        use glow::HasContext as _;
+        unsafe {
+            gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
+        }
+
+        let mut resources = ResourceManager::new();
+
+        let present = EffectContext::new(gl, &mut resources, Effect::None)?;
+        let targets = [Framebuffer::new(gl)?, Framebuffer::new(gl)?];
+
+        Some(Self {
+            layers: Vec::new(),
+            screen: ScreenContext::default(),
+            camera: Camera::default(),
+            targets,
+            present,
+            resources,
+        })
+    }
+
+    pub fn destroy(&mut self, gl: &glow::Context) {
+        unsafe {
+            for layer in &mut self.layers {
+                layer.destroy(gl)
+            }
+            for target in &self.targets {
+                target.destroy(gl);
+            }
+            self.present.destroy(gl);
+            self.resources.destroy(gl);
+        }
+    }
+
+    /// Removes the layer at `index` and frees its GPU resources.
+    pub fn remove_layer(&mut self, gl: &glow::Context, index: usize) {
+        let mut layer = self.layers.remove(index);
+        unsafe {
+            layer.destroy(gl);
+        }
+    }
+
+    /// Moves every animation `dt` seconds forward.
+    pub fn advance(&mut self, dt: f64) {
+        for layer in &mut self.layers {
+            match layer {
+                Layer::TileMap(tilemap) => tilemap.advance(dt),
+                Layer::Sprite(sprites) => sprites.advance(dt),
+                _ => {}
+            }
+        }
+    }
+
+    /// Sets the pan of every tilemap and sprite layer from its [`Scroll`] and the camera,
+    /// compositing does this before drawing.
+    pub fn apply_scroll(&mut self) {
+        for layer in &mut self.layers {
+            match layer {
+                Layer::TileMap(tilemap) => {
+                    (tilemap.map.pan_x, tilemap.map.pan_y) = tilemap.scroll.pan(self.camera)
+                }
+                Layer::Sprite(sprites) => {
+                    (sprites.pan_x, sprites.pan_y) = sprites.scroll.pan(self.camera)
+                }
+                Layer::Bitmap(_) | Layer::Effect(_) => {}
+            }
+        }
+    }
+
+    /// Composites every layer offscreen at native resolution, returning the index of the target
+    /// holding the result which is left bound.
+    ///
+    /// Effects split the layers into groups, each group is drawn over everything beneath the
+    /// effect it sits on. Inside a group tiles, sprites and bitmaps are ordered by their `layer`
+    /// value, lower values in front, then by the order of `layers` (the first one on top) and
+    /// finally later sprites in front of earlier ones. Every priority is drawn back to front
+    /// so translucent pixels blend over exactly what is behind them.
+    fn composite(&mut self, gl: &glow::Context) -> usize {
+        use glow::HasContext as _;
+
+        self.apply_scroll();
+        let native = ScreenContext {
+            zoom: 1.0,
+            ..self.screen
+        };
+        for target in &mut self.targets {
+            target.resize(gl, native.screen_px_x, native.screen_px_y);
+        }
+        self.resources.upload_palettes(gl);
+
+        let mut current = 0;
+        self.targets[current].bind(gl);
+        unsafe {
+            gl.disable(glow::SCISSOR_TEST);
+            gl.clear_color(0.0, 0.0, 0.0, 0.0);
+            gl.clear(glow::COLOR_BUFFER_BIT);
+
+            // layers output straight alpha, keep the framebuffer premultiplied
+            gl.enable(glow::BLEND);
+            gl.blend_func_separate(
+                glow::SRC_ALPHA,
+                glow::ONE_MINUS_SRC_ALPHA,
+                glow::ONE,
+                glow::ONE_MINUS_SRC_ALPHA,
+            );
+        }
+
+        let mut group = Vec::new();
+        for index in (0..self.layers.len()).rev() {
+            if let Layer::Effect(_) = self.layers[index] {
+                Self::paint_group(gl, &native, &mut self.layers, &group, &self.resources);
+                group.clear();
+
+                let Layer::Effect(effect) = &mut self.layers[index] else {
+                    unreachable!()
+                };
+                let next = (current + 1) % self.targets.len();
+                self.targets[next].bind(gl);
+                unsafe {
+                    gl.disable(glow::BLEND);
+                    effect.paint(gl, &native, self.targets[current].texture);
+                    gl.enable(glow::BLEND);
+                }
+                current = next;
+            } else {
+                group.push(index);
+            }
+        }
+        Self::paint_group(gl, &native, &mut self.layers, &group, &self.resources);
+        current
+    }
+
+    // `group` holds layer indices in painting order, last to first
+    fn paint_group(
+        gl: &glow::Context,
+        screen: &ScreenContext,
+        layers: &mut [Layer],
+        group: &[usize],
+        resources: &ResourceManager,
+    ) {
+        let mut priorities = [false; 256];
+        for &index in group {
+            layers[index].prepare(gl, screen, resources, &mut priorities);
+        }
+        for priority in (0..=255u8).rev() {
+            if !priorities[priority as usize] {
+                continue;
+            }
+            for &index in group {
+                layers[index].paint(gl, screen, resources, priority);
+            }
+        }
+    }
+
+    /// Composites every layer offscreen then draws the result, scaled by the zoom, into `output`
+    /// using the current viewport.
+    pub fn paint(&mut self, gl: &glow::Context, output: Option<glow::Framebuffer>) {
+        use glow::HasContext as _;
+
+        let mut viewport = [0; 4];
+        let scissor;
+        unsafe {
+            gl.get_parameter_i32_slice(glow::VIEWPORT, &mut viewport);
+            scissor = gl.is_enabled(glow::SCISSOR_TEST);
+        }
+
+        let current = self.composite(gl);
+
+        unsafe {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, output);
+            gl.viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+            if scissor {
+                gl.enable(glow::SCISSOR_TEST);
+            }
+            gl.blend_func(glow::ONE, glow::ONE_MINUS_SRC_ALPHA);
+        }
+        self.present
+            .paint(gl, &self.screen, self.targets[current].texture);
+    }
+
+    /// Renders every layer at `screen_px_x` x `screen_px_y` and reads the pixels back with
+    /// straight alpha, the top row first. Leaves the default framebuffer bound.
+    pub fn render_to_image(&mut self, gl: &glow::Context) -> RgbaImage {
+        use glow::HasContext as _;
+
+        let mut viewport = [0; 4];
+        let scissor;
+        unsafe {
+            gl.get_parameter_i32_slice(glow::VIEWPORT, &mut viewport);
+            scissor = gl.is_enabled(glow::SCISSOR_TEST);
+        }
+
+        let current = self.composite(gl);
+        let target = &self.targets[current];
+
+        let (width, height) = (target.width(), target.height());
+        let mut pixels = vec![0; (width * height * 4) as usize];
+        unsafe {
+            gl.pixel_store_i32(glow::PACK_ALIGNMENT, 1);
+            gl.read_pixels(
+                0,
+                0,
+                width,
+                height,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                glow::PixelPackData::Slice(&mut pixels),
+            );
+            gl.pixel_store_i32(glow::PACK_ALIGNMENT, 4);
+
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+            gl.viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+            if scissor {
+                gl.enable(glow::SCISSOR_TEST);
+            }
+        }
+
+        let mut image = RgbaImage::from_raw(width as u32, height as u32, pixels)
+            .expect("Read back the wrong number of pixels");
+        // framebuffers start at the bottom
+        image::imageops::flip_vertical_in_place(&mut image);
+        software::unpremultiply(&mut image);
+        image
+    }
+}
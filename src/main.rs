@@ -1,7 +1,3 @@
-pub mod resources;
-pub mod sprites;
-pub mod tilemap;
-
 #[cfg(not(target_arch = "wasm32"))]
 fn main() {
     let options = eframe::NativeOptions {
@@ -39,15 +35,33 @@ fn main() {
     });
 }
 
-use std::{io::Cursor, sync::Arc};
+mod sprite_editor;
+mod tile_editor;
+
+use std::{collections::HashMap, sync::Arc};
 
 use eframe::egui_glow;
 use egui::{mutex::Mutex, ComboBox, Slider, Widget};
 use egui_glow::glow;
-use resources::ResourceManager;
-use sprites::SpriteMapContext;
-
-use crate::{resources::Texture, tilemap::TileMapContext};
+use graphics_test::{
+    animation::{PlaybackMode, SpriteClip, SpriteFrame},
+    aseprite::AsepriteSheet,
+    bitmap::BitmapContext,
+    effect::{Effect, EffectContext},
+    map_file::TileMapFile,
+    palette::{self, PALETTE_COUNT},
+    resources::{ResourceManager, TextureHandle},
+    scene::Scene,
+    sprites::{Sprite, SpriteAttributes, SpriteFormat, SpriteLimits, SpriteMapContext},
+    tiled::TiledMap,
+    tilemap::{
+        Affine, EdgeMode, Raster, TileAnimation, TileAttributes, TileFrame, TileMap, TileMapContext,
+    },
+    world::{WorldError, WorldMap},
+    Camera, Layer, RetroGraphics, ScreenContext, Scroll,
+};
+use sprite_editor::SpriteEditor;
+use tile_editor::TileEditor;
 
 pub struct Custom3d {
     /// Behind an `Arc<Mutex<…>>` so we can pass it to [`egui::PaintCallback`] and paint later.
@@ -55,28 +69,141 @@ pub struct Custom3d {
     zoom: f32,
     panx: f32,
     pany: f32,
+    status: Option<String>,
+    // palette 0 holds the indexed miniroguelike colors, cycled past the transparent index 0
+    cycle_colors: usize,
+    cycling: bool,
+    last_cycle: f64,
+    tile_editor: TileEditor,
+    sprite_editor: SpriteEditor,
+    /// The raster demos switched on for the tilemap layer at each index.
+    raster_demos: HashMap<usize, RasterDemo>,
+    /// Streamed into the tilemap layer at that index every frame.
+    world: Option<(usize, WorldMap)>,
 }
 
 impl Custom3d {
     pub fn new<'a>(cc: &'a eframe::CreationContext<'a>) -> Option<Self> {
         let gl = cc.gl.as_ref()?;
+        let mut graphics = RetroGraphics::new(gl)?;
+
+        let texture = graphics
+            .resources
+            .load_texture(gl, "spritesheet", include_bytes!("../res/spritesheet.png"))
+            .expect("Failed to load spritesheet");
+        graphics
+            .resources
+            .load_texture(
+                gl,
+                "miniroguelike",
+                include_bytes!("../res/miniroguelike-8x8.png"),
+            )
+            .expect("Failed to load miniroguelike");
+
+        let (indices, colors) =
+            palette::decode_indexed(include_bytes!("../res/miniroguelike-8x8.png"))
+                .expect("Failed to index miniroguelike");
+        let swapped: Vec<_> = colors.iter().map(|&[r, g, b, a]| [b, r, g, a]).collect();
+        graphics.resources.set_palette(0, &colors);
+        graphics.resources.set_palette(1, &swapped);
+        graphics
+            .resources
+            .create_indexed_texture(gl, "miniroguelike-indexed", indices);
+
+        let screen = graphics.screen;
+        let resources = &mut graphics.resources;
+        let mut hud = BitmapContext::new(gl, resources, screen.screen_px_x, screen.screen_px_y)
+            .expect("Failed to create bitmap");
+        hud.bitmap.fill_rect(4, 4, 64, 14, [0, 0, 0, 160]);
+        hud.bitmap.rect(4, 4, 64, 14, [255, 255, 255, 255]);
+        hud.bitmap.line(8, 10, 64, 10, [255, 64, 64, 255]);
+
+        graphics.layers = vec![
+            Layer::Bitmap(hud),
+            Layer::Effect(
+                EffectContext::new(gl, resources, Effect::None).expect("Failed to create effect"),
+            ),
+            Layer::Sprite(
+                SpriteMapContext::new(gl, resources, texture).expect("Failed to create tilemap"),
+            ),
+            Layer::TileMap(
+                TileMapContext::new(gl, resources, texture).expect("Failed to create tilemap"),
+            ),
+        ];
+
         Some(Self {
-            retro_graphics: Arc::new(Mutex::new(RetroGraphics::new(gl)?)),
+            retro_graphics: Arc::new(Mutex::new(graphics)),
             zoom: 0.0,
             panx: 0.0,
             pany: 0.0,
+            status: None,
+            cycle_colors: colors.len(),
+            cycling: false,
+            last_cycle: 0.0,
+            tile_editor: TileEditor::default(),
+            sprite_editor: SpriteEditor::default(),
+            raster_demos: HashMap::new(),
+            world: None,
         })
     }
 }
 
 impl eframe::App for Custom3d {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        if let Some(gl) = frame.gl() {
+            self.load_dropped_files(ctx, gl);
+        }
+
+        self.editor_shortcuts(ctx);
+
+        if self.cycling {
+            let time = ctx.input(|io| io.time);
+            if time - self.last_cycle > 0.15 {
+                self.last_cycle = time;
+                self.retro_graphics
+                    .lock()
+                    .resources
+                    .cycle_palette(0, 1..self.cycle_colors, 1);
+            }
+            ctx.request_repaint();
+        }
+
+        {
+            let mut lock = self.retro_graphics.lock();
+            lock.advance(ctx.input(|io| io.stable_dt) as f64);
+            if let Some((index, world)) = &mut self.world {
+                let graphics = &mut *lock;
+                let result = match graphics.layers.get_mut(*index) {
+                    Some(Layer::TileMap(tilemap)) => {
+                        world.update(tilemap, &graphics.screen, graphics.camera)
+                    }
+                    // the layers were replaced by a scene
+                    _ => Ok(()),
+                };
+                if let Err(err) = result {
+                    self.status = Some(format!("world: {err}"));
+                    self.world = None;
+                }
+            }
+            let animated = lock.layers.iter().any(|layer| match layer {
+                Layer::TileMap(tilemap) => {
+                    !lock.resources.tile_animations(tilemap.texture).is_empty()
+                }
+                Layer::Sprite(sprites) => sprites.playback.iter().any(Option::is_some),
+                _ => false,
+            });
+            if animated {
+                ctx.request_repaint();
+            }
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
                 ui.horizontal(|ui| {
                     let mut lock = self.retro_graphics.lock();
                     ui.vertical(|ui| {
                         ui.label(format!("zoom: {}", lock.screen.zoom));
+                        ui.label(format!("camera: {}, {}", lock.camera.x, lock.camera.y));
 
                         Slider::new(&mut lock.screen.screen_px_x, 0..=256)
                             .text(" pixels x")
@@ -88,79 +215,262 @@ impl eframe::App for Custom3d {
                             .show_value(true)
                             .step_by(8.0)
                             .ui(ui);
+
+                        #[cfg(not(target_arch = "wasm32"))]
+                        if ui.button("Export PNG").clicked() {
+                            if let Some(gl) = frame.gl() {
+                                let image = lock.render_to_image(gl);
+                                let secs = std::time::SystemTime::now()
+                                    .duration_since(std::time::UNIX_EPOCH)
+                                    .map(|time| time.as_secs())
+                                    .unwrap_or_default();
+                                let path = format!("screenshot-{secs}.png");
+                                self.status = Some(
+                                    match image.save_with_format(&path, image::ImageFormat::Png) {
+                                        Ok(()) => format!("saved {path}"),
+                                        Err(err) => format!("failed to save {path}: {err}"),
+                                    },
+                                );
+                            }
+                        }
+                        #[cfg(not(target_arch = "wasm32"))]
+                        if ui.button("Save scene").clicked() {
+                            self.status = Some(save_scene(&lock));
+                        }
+                        ui.checkbox(&mut self.cycling, "Cycle palette 0");
+
+                        if let Some(status) = &self.status {
+                            ui.label(status);
+                        }
                     });
-                    for (index, item) in lock.layers.iter_mut().enumerate() {
+                    let graphics = &mut *lock;
+                    for (index, item) in graphics.layers.iter_mut().enumerate() {
                         ui.add_space(1.0);
 
                         ui.vertical(|ui| match item {
                             Layer::Sprite(sprites) => {
-                                ComboBox::new(index, "Sprite").show_ui(ui, |ui| {
-                                    for i in 0..sprites.thing.len() {
-                                        ui.label(format!("{i}"));
+                                texture_picker(
+                                    ui,
+                                    index,
+                                    &graphics.resources,
+                                    &mut sprites.texture,
+                                );
+                                let mut editing = self.sprite_editor.layer == Some(index);
+                                if ui.checkbox(&mut editing, "Edit").changed() {
+                                    self.sprite_editor = SpriteEditor::default();
+                                    self.sprite_editor.layer = editing.then_some(index);
+                                    self.tile_editor.layer = None;
+                                }
+                                ui.label(format!("{} sprites", sprites.thing.len()));
+                                scroll_ui(ui, &mut sprites.scroll);
+                                let mut palette =
+                                    sprites.thing.first().map_or(0, |sprite| sprite.palette);
+                                if palette_slider(ui, &mut palette) {
+                                    for sprite in &mut sprites.thing {
+                                        sprite.palette = palette;
                                     }
-                                });
+                                }
+                                clip_ui(ui, sprites);
+                                limits_ui(ui, index, sprites);
+                                let mut compact = sprites.format == SpriteFormat::Compact;
+                                if ui.checkbox(&mut compact, "Compact").changed() {
+                                    sprites.format = match compact {
+                                        true => SpriteFormat::Compact,
+                                        false => SpriteFormat::Extended,
+                                    };
+                                }
                             }
                             Layer::TileMap(tilemap) => {
-                                let mut changed = Slider::new(&mut tilemap.map.tiles_x, 1..=30)
+                                texture_picker(
+                                    ui,
+                                    index,
+                                    &graphics.resources,
+                                    &mut tilemap.texture,
+                                );
+                                let mut streaming =
+                                    matches!(self.world, Some((world, _)) if world == index);
+                                let mut editing = self.tile_editor.layer == Some(index);
+                                // the window is filled again every frame, edits would be lost
+                                let edit = egui::Checkbox::new(&mut editing, "Edit");
+                                if ui.add_enabled(!streaming, edit).changed() {
+                                    self.tile_editor.layer = editing.then_some(index);
+                                    self.sprite_editor.layer = None;
+                                }
+
+                                let (mut tiles_x, mut tiles_y) =
+                                    (tilemap.map.tiles_x, tilemap.map.tiles_y);
+                                let mut changed = Slider::new(&mut tiles_x, 1..=30)
                                     .text(" tiles x")
                                     .show_value(true)
                                     .ui(ui)
                                     .changed();
-                                changed |= Slider::new(&mut tilemap.map.tiles_y, 1..=30)
+                                changed |= Slider::new(&mut tiles_y, 1..=30)
                                     .text(" tiles y")
                                     .show_value(true)
                                     .ui(ui)
                                     .changed();
 
                                 if changed {
+                                    if editing {
+                                        self.tile_editor.checkpoint(&tilemap.map);
+                                    }
+                                    tilemap.map.resize(tiles_x, tiles_y);
+                                }
+                                tile_size_ui(ui, index, &mut tilemap.map);
+                                if ui.button("Reset tiles").clicked() {
+                                    if editing {
+                                        self.tile_editor.checkpoint(&tilemap.map);
+                                    }
                                     tilemap.map.recalc();
                                 }
+                                if ui.checkbox(&mut streaming, "Stream as world").changed() {
+                                    let started = match streaming {
+                                        true => save_world(self.world.take()).and_then(|()| {
+                                            let world = world_from(tilemap, &graphics.resources)?;
+                                            self.world = Some((index, world));
+                                            self.tile_editor.layer = None;
+                                            Ok(())
+                                        }),
+                                        false => save_world(self.world.take()),
+                                    };
+                                    if let Err(err) = started {
+                                        self.status = Some(format!("world: {err}"));
+                                    }
+                                }
+                                if let Some((_, world)) = &self.world {
+                                    if streaming {
+                                        ui.label(format!(
+                                            "{} chunks loaded",
+                                            world.loaded().count()
+                                        ));
+                                    }
+                                }
+                                #[cfg(not(target_arch = "wasm32"))]
+                                if ui.button("Save map").clicked() {
+                                    self.status = Some(save_map(&TileMapFile::from_context(
+                                        tilemap,
+                                        &graphics.resources,
+                                    )));
+                                }
 
-                                ui.label(format!("pan x: {}", tilemap.map.pan_x));
-                                ui.label(format!("pan y: {}", tilemap.map.pan_y));
-                            }
-                            Layer::Bitmap() => todo!(),
-                            Layer::Effect() => todo!(),
-                        });
-                    }
-
-                    ui.vertical(|ui| {
-                        // let item = &mut lock.sprite_map. thing[0];
-
-                        // Slider::new(&mut item.x, 0..=256).text(" x").show_value(true).step_by(1.0).ui(ui);
-                        // Slider::new(&mut item.y, 0..=256).text(" y").show_value(true).step_by(1.0).ui(ui);
-
-                        // Slider::new(&mut item.tx, 0..=255).text(" uv x").show_value(true).step_by(1.0).ui(ui);
-                        // Slider::new(&mut item.ty, 0..=255).text(" uv y").show_value(true).step_by(1.0).ui(ui);
-
-                        // Slider::new(&mut item.layer, 0..=255).text(" layer").show_value(true).step_by(1.0).ui(ui);
+                                let mut palette =
+                                    tilemap.map.tiles.first().map_or(0, |tile| {
+                                        tile.attributes.get(TileAttributes::PALETTE)
+                                    });
+                                if palette_slider(ui, &mut palette) {
+                                    for tile in &mut tilemap.map.tiles {
+                                        tile.attributes.set(TileAttributes::PALETTE, palette);
+                                    }
+                                }
 
-                        // let mut tmp = item.attribute.get(SpriteAttributes::HORIZONTAL);
-                        // ui.checkbox(&mut tmp, "Horizontal Flip");
-                        // item.attribute.set(SpriteAttributes::HORIZONTAL, tmp);
+                                scroll_ui(ui, &mut tilemap.scroll);
+                                ui.label(format!(
+                                    "pan: {}, {}",
+                                    tilemap.map.pan_x, tilemap.map.pan_y
+                                ));
 
-                        // let mut tmp = item.attribute.get(SpriteAttributes::VERTICAL);
-                        // ui.checkbox(&mut tmp, "Vertical Flip");
-                        // item.attribute.set(SpriteAttributes::VERTICAL, tmp);
+                                raster_ui(
+                                    ui,
+                                    index,
+                                    self.raster_demos.entry(index).or_default(),
+                                    &mut tilemap.raster,
+                                    &tilemap.map,
+                                    &graphics.screen,
+                                );
+                                animation_ui(ui, tilemap, &mut graphics.resources);
+                            }
+                            Layer::Bitmap(bitmap) => {
+                                ui.label(format!(
+                                    "bitmap: {}x{}",
+                                    bitmap.bitmap.width(),
+                                    bitmap.bitmap.height()
+                                ));
+                                Slider::new(&mut bitmap.layer, 0..=255)
+                                    .text(" layer")
+                                    .show_value(true)
+                                    .ui(ui);
+                                if ui.button("Clear").clicked() {
+                                    bitmap.bitmap.clear([0; 4]);
+                                }
+                            }
+                            Layer::Effect(effect) => {
+                                ComboBox::new(index, "Effect")
+                                    .selected_text(effect.effect.name())
+                                    .show_ui(ui, |ui| {
+                                        for built_in in Effect::BUILT_IN {
+                                            let selected = std::mem::discriminant(&effect.effect)
+                                                == std::mem::discriminant(&built_in);
+                                            if ui
+                                                .selectable_label(selected, built_in.name())
+                                                .clicked()
+                                                && !selected
+                                                && !effect.is_custom()
+                                            {
+                                                effect.effect = built_in;
+                                            }
+                                        }
+                                    });
 
-                        // let mut tmp = item.attribute.get(SpriteAttributes::ROTATION);
-                        // Slider::new(&mut tmp, 0..=3).text(" rot").show_value(true).ui(ui);
-                        // item.attribute.set(SpriteAttributes::ROTATION, tmp);
+                                match &mut effect.effect {
+                                    Effect::None => {}
+                                    Effect::Mosaic { size } => {
+                                        Slider::new(size, 1..=32).text(" size").ui(ui);
+                                    }
+                                    Effect::Tint { color } => {
+                                        ui.color_edit_button_rgba_unmultiplied(color);
+                                    }
+                                    Effect::Fade { amount } => {
+                                        Slider::new(amount, 0.0..=1.0).text(" amount").ui(ui);
+                                    }
+                                    Effect::Scanlines { intensity } => {
+                                        Slider::new(intensity, 0.0..=1.0).text(" intensity").ui(ui);
+                                    }
+                                    Effect::Custom { params } => {
+                                        for param in params {
+                                            Slider::new(param, 0.0..=1.0).ui(ui);
+                                        }
+                                    }
+                                }
+                            }
+                        });
+                    }
 
-                        // let mut tmp = item.attribute.get(SpriteAttributes::XSIZE);
-                        // Slider::new(&mut tmp, 0..=3).text(" size x").show_value(true).ui(ui);
-                        // item.attribute.set(SpriteAttributes::XSIZE, tmp);
+                    if let Some(Layer::TileMap(tilemap)) = self
+                        .tile_editor
+                        .layer
+                        .and_then(|index| graphics.layers.get_mut(index))
+                    {
+                        ui.vertical(|ui| {
+                            self.tile_editor.ui(
+                                ui,
+                                &mut tilemap.map,
+                                tilemap.texture,
+                                &graphics.resources,
+                            );
+                        });
+                    }
 
-                        // let mut tmp = item.attribute.get(SpriteAttributes::YSIZE);
-                        // Slider::new(&mut tmp, 0..=3).text(" size y").show_value(true).step_by(1.0).ui(ui);
-                        // item.attribute.set(SpriteAttributes::YSIZE, tmp);
-                    });
+                    if let Some(Layer::Sprite(sprites)) = self
+                        .sprite_editor
+                        .layer
+                        .and_then(|index| graphics.layers.get_mut(index))
+                    {
+                        ui.vertical(|ui| {
+                            self.sprite_editor.ui(ui, sprites, &graphics.screen);
+                        });
+                    }
                 });
 
                 egui::Frame::canvas(ui.style()).show(ui, |ui| {
                     self.custom_painting(ui);
                 });
-                ui.label("Drag to pan, Scroll to zoom!");
+                ui.label(if self.tile_editor.editing() {
+                    "Drag to edit, right click to pick a tile, Scroll to zoom!"
+                } else if self.sprite_editor.layer.is_some() {
+                    "Click to select a sprite, drag to move it or pan, Scroll to zoom!"
+                } else {
+                    "Drag to pan, Scroll to zoom!"
+                });
             });
         });
     }
@@ -173,6 +483,204 @@ impl eframe::App for Custom3d {
 }
 
 impl Custom3d {
+    fn load_dropped_files(&mut self, ctx: &egui::Context, gl: &glow::Context) {
+        let mut files = ctx.input(|io| io.raw.dropped_files.clone());
+        // tilesets have to be loaded before the Tiled maps using them
+        files.sort_by_key(|file| {
+            let file_name = dropped_file_name(file);
+            file_name.ends_with(".tmx") || file_name.ends_with(".tmj")
+        });
+        for file in &files {
+            let bytes = match (&file.bytes, &file.path) {
+                (Some(bytes), _) => bytes.to_vec(),
+                #[cfg(not(target_arch = "wasm32"))]
+                (None, Some(path)) => match std::fs::read(path) {
+                    Ok(bytes) => bytes,
+                    Err(err) => {
+                        self.status = Some(format!("failed to read {}: {err}", file.name));
+                        continue;
+                    }
+                },
+                _ => continue,
+            };
+            let name = match &file.path {
+                Some(path) => path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                None => file.name.clone(),
+            };
+            let file_name = dropped_file_name(file);
+
+            // before maps, scenes are JSON too
+            if file_name.ends_with(".scene.json") {
+                self.status = Some(
+                    match Scene::from_json(&bytes)
+                        .and_then(|scene| scene.restore(gl, &mut self.retro_graphics.lock()))
+                    {
+                        Ok(()) => {
+                            // layer indices and textures changed
+                            self.tile_editor.layer = None;
+                            self.tile_editor.reload_textures();
+                            self.sprite_editor = SpriteEditor::default();
+                            self.raster_demos.clear();
+                            match save_world(self.world.take()) {
+                                Ok(()) => format!("loaded scene {file_name}"),
+                                Err(err) => format!(
+                                    "loaded scene {file_name}, failed to save the world: {err}"
+                                ),
+                            }
+                        }
+                        Err(err) => format!("failed to load {file_name}: {err}"),
+                    },
+                );
+                continue;
+            }
+
+            if file_name.ends_with(".tmap") || file_name.ends_with(".tmap.json") {
+                self.status = Some(match TileMapFile::load(&bytes) {
+                    Ok(map) => self.load_map(map),
+                    Err(err) => format!("failed to load {file_name}: {err}"),
+                });
+                continue;
+            }
+
+            if file_name.ends_with(".tmx") || file_name.ends_with(".tmj") {
+                let mut lock = self.retro_graphics.lock();
+                self.status = Some(
+                    match TiledMap::parse(&bytes).and_then(|map| map.import(gl, &mut lock)) {
+                        Ok(count) => format!("imported {count} layers from {file_name}"),
+                        Err(err) => format!("failed to import {file_name}: {err}"),
+                    },
+                );
+                continue;
+            }
+
+            if file_name.ends_with(".json") {
+                self.status = Some(
+                    match AsepriteSheet::parse(&bytes)
+                        .map_err(|err| err.to_string())
+                        .and_then(|sheet| self.import_aseprite(gl, &sheet, file, &files))
+                    {
+                        Ok(count) => format!("imported {count} clips from {file_name}"),
+                        Err(err) => format!("failed to import {file_name}: {err}"),
+                    },
+                );
+                continue;
+            }
+
+            self.tile_editor.reload_textures();
+            let mut lock = self.retro_graphics.lock();
+            self.status = Some(match lock.resources.load_texture(gl, &name, &bytes) {
+                Ok(_) => format!("loaded texture {name}"),
+                Err(err) => format!("failed to load {name}: {err}"),
+            });
+        }
+    }
+
+    /// Adds a sprite layer with a sprite playing each of the sheet's clips. The image is taken
+    /// from the dropped files or read from next to the JSON file.
+    fn import_aseprite(
+        &mut self,
+        gl: &glow::Context,
+        sheet: &AsepriteSheet,
+        file: &egui::DroppedFile,
+        files: &[egui::DroppedFile],
+    ) -> Result<usize, String> {
+        let path = sheet.image.as_deref().unwrap_or_default();
+        let image_name = std::path::Path::new(path).file_name();
+        let dropped = files
+            .iter()
+            .find(|other| Some(std::ffi::OsStr::new(&dropped_file_name(other))) == image_name)
+            .and_then(|other| other.bytes.as_ref().map(|bytes| bytes.to_vec()));
+        #[cfg(not(target_arch = "wasm32"))]
+        let dropped = dropped.or_else(|| {
+            let image = file.path.as_ref()?.parent()?.join(path);
+            std::fs::read(image).ok()
+        });
+        #[cfg(target_arch = "wasm32")]
+        let _ = file;
+        let Some(image) = dropped else {
+            return Err(format!("its image {path} wasn't dropped"));
+        };
+
+        self.tile_editor.reload_textures();
+        let mut lock = self.retro_graphics.lock();
+        let graphics = &mut *lock;
+        let (texture, clips) = sheet
+            .import(gl, &mut graphics.resources, &image)
+            .map_err(|err| err.to_string())?;
+        let Some(mut sprites) = SpriteMapContext::new(gl, &mut graphics.resources, texture) else {
+            return Err("sprite shaders failed to compile".into());
+        };
+        sprites.clips = clips;
+        sprites.thing.clear();
+        for (index, tag) in sheet.tags.iter().enumerate() {
+            let mut sprite = Sprite::default();
+            sprite.x = 8 + index as i32 * 40;
+            sprite.y = 8;
+            sprite.layer = 2;
+            sprites.thing.push(sprite);
+            sprites.play(index, &tag.name, tag.mode());
+        }
+        let count = sprites.clips.len();
+        graphics.layers.push(Layer::Sprite(sprites));
+        Ok(count)
+    }
+
+    /// Replaces the map of the tilemap being edited, or the first tilemap if none is.
+    fn load_map(&mut self, map: TileMapFile) -> String {
+        let mut lock = self.retro_graphics.lock();
+        let graphics = &mut *lock;
+        let index = self.tile_editor.layer.or_else(|| {
+            graphics
+                .layers
+                .iter()
+                .position(|layer| matches!(layer, Layer::TileMap(_)))
+        });
+        let Some(Layer::TileMap(tilemap)) = index.and_then(|index| graphics.layers.get_mut(index))
+        else {
+            return "there is no tilemap to load the map into".into();
+        };
+        if self.tile_editor.layer.is_some() {
+            self.tile_editor.checkpoint(&tilemap.map);
+        }
+        let tileset = map.tileset.clone();
+        if map.apply(tilemap, &graphics.resources) {
+            "loaded map".into()
+        } else {
+            format!("loaded map, its tileset {tileset} isn't loaded")
+        }
+    }
+
+    /// Ctrl+Z undoes and Ctrl+Shift+Z or Ctrl+Y redoes edits to the tilemap being edited.
+    fn editor_shortcuts(&mut self, ctx: &egui::Context) {
+        use egui::{Key, KeyboardShortcut, Modifiers};
+
+        let Some(index) = self.tile_editor.layer else {
+            return;
+        };
+        let (undo, redo) = ctx.input_mut(|io| {
+            let redo = io.consume_shortcut(&KeyboardShortcut::new(
+                Modifiers::COMMAND | Modifiers::SHIFT,
+                Key::Z,
+            )) || io
+                .consume_shortcut(&KeyboardShortcut::new(Modifiers::COMMAND, Key::Y));
+            let undo = io.consume_shortcut(&KeyboardShortcut::new(Modifiers::COMMAND, Key::Z));
+            (undo, redo)
+        });
+
+        let mut lock = self.retro_graphics.lock();
+        if let Some(Layer::TileMap(tilemap)) = lock.layers.get_mut(index) {
+            if undo {
+                self.tile_editor.undo(&mut tilemap.map);
+            }
+            if redo {
+                self.tile_editor.redo(&mut tilemap.map);
+            }
+        }
+    }
+
     fn custom_painting(&mut self, ui: &mut egui::Ui) {
         let area;
         {
@@ -185,12 +693,37 @@ impl Custom3d {
                 area = egui::Vec2::new(600.0 * aspect_px, 600.0);
             }
         }
-        let (rect, response) = ui.allocate_exact_size(area, egui::Sense::drag());
+        let (rect, response) = ui.allocate_exact_size(area, egui::Sense::click_and_drag());
 
         self.zoom += ui.input(|io| io.smooth_scroll_delta.y * 0.002);
 
-        self.panx -= response.drag_delta().x / rect.width();
-        self.pany -= response.drag_delta().y / rect.height();
+        let mut dragging_sprite = false;
+        {
+            let mut lock = self.retro_graphics.lock();
+            let screen = lock.screen;
+            if let Some(Layer::TileMap(tilemap)) = self
+                .tile_editor
+                .layer
+                .and_then(|index| lock.layers.get_mut(index))
+            {
+                let edge = tilemap.raster.edge;
+                self.tile_editor
+                    .canvas(ui, &response, rect, &screen, &mut tilemap.map, edge);
+            }
+            if let Some(Layer::Sprite(sprites)) = self
+                .sprite_editor
+                .layer
+                .and_then(|index| lock.layers.get_mut(index))
+            {
+                dragging_sprite = self
+                    .sprite_editor
+                    .canvas(ui, &response, rect, &screen, sprites);
+            }
+        }
+        if !self.tile_editor.editing() && !dragging_sprite {
+            self.panx -= response.drag_delta().x / rect.width();
+            self.pany -= response.drag_delta().y / rect.height();
+        }
 
         // Clone locals so we can move them into the paint callback:
         let zoom = self.zoom;
@@ -202,25 +735,13 @@ impl Custom3d {
             let mut lock = rotating_triangle.lock();
 
             lock.screen.zoom = zoom.exp();
-            let pan_x = (pan_x * lock.screen.screen_px_x as f32) as i32;
-            let pan_y = (pan_y * lock.screen.screen_px_y as f32) as i32;
-
-            for layer in lock.layers.iter_mut() {
-                match layer {
-                    Layer::Sprite(l) => {
-                        l.pan_x = pan_x;
-                        l.pan_y = pan_y;
-                    }
-                    Layer::TileMap(l) => {
-                        l.map.pan_x = pan_x;
-                        l.map.pan_y = pan_y;
-                    }
-                    Layer::Bitmap() => {}
-                    Layer::Effect() => {}
-                }
-            }
+            // layers scroll relative to the camera, see `scroll_ui`
+            lock.camera = Camera {
+                x: (pan_x * lock.screen.screen_px_x as f32) as i32,
+                y: (pan_y * lock.screen.screen_px_y as f32) as i32,
+            };
 
-            lock.paint(painter.gl());
+            lock.paint(painter.gl(), painter.intermediate_fbo());
         });
 
         let callback = egui::PaintCallback {
@@ -231,163 +752,348 @@ impl Custom3d {
     }
 }
 
-#[allow(unused)]
-enum Layer {
-    Sprite(SpriteMapContext),
-    TileMap(TileMapContext),
-    Bitmap(),
-    Effect(),
+// only the web backend sets `name`
+fn dropped_file_name(file: &egui::DroppedFile) -> String {
+    match &file.path {
+        Some(path) => path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        None => file.name.clone(),
+    }
 }
 
-impl Layer {
-    pub unsafe fn destroy(&mut self, gl: &glow::Context) {
-        match self {
-            Layer::Sprite(l) => l.destroy(gl),
-            Layer::TileMap(l) => l.destroy(gl),
-            Layer::Bitmap() => todo!(),
-            Layer::Effect() => todo!(),
-        }
+/// Writes the whole scene next to the executable, returns the status to show.
+#[cfg(not(target_arch = "wasm32"))]
+fn save_scene(graphics: &RetroGraphics) -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+    let path = format!("scene-{secs}.scene.json");
+    match Scene::capture(graphics)
+        .map_err(|err| err.to_string())
+        .and_then(|scene| std::fs::write(&path, scene.to_json()).map_err(|err| err.to_string()))
+    {
+        Ok(()) => format!("saved {path}"),
+        Err(err) => format!("failed to save {path}: {err}"),
     }
+}
 
-    pub unsafe fn paint(&mut self, gl: &glow::Context, screen: &ScreenContext) {
-        match self {
-            Layer::Sprite(l) => l.paint(gl, screen),
-            Layer::TileMap(l) => l.paint(gl, screen),
-            Layer::Bitmap() => todo!(),
-            Layer::Effect() => todo!(),
-        }
+/// Writes `map` in both formats next to the executable, returns the status to show.
+#[cfg(not(target_arch = "wasm32"))]
+fn save_map(map: &TileMapFile) -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+    let path = format!("map-{secs}.tmap");
+    let json_path = format!("{path}.json");
+    let saved = std::fs::write(&path, map.to_binary())
+        .and_then(|()| std::fs::write(&json_path, map.to_json()));
+    match saved {
+        Ok(()) => format!("saved {path} and {json_path}"),
+        Err(err) => format!("failed to save {path}: {err}"),
     }
 }
 
-pub struct ScreenContext {
-    screen_px_x: i32,
-    screen_px_y: i32,
-    zoom: f32,
+/// Saves the world that was streaming, if there was one.
+fn save_world(world: Option<(usize, WorldMap)>) -> Result<(), WorldError> {
+    match world {
+        Some((_, mut world)) => world.save(),
+        None => Ok(()),
+    }
 }
 
-struct RetroGraphics {
-    resources: ResourceManager,
-    screen: ScreenContext,
-    layers: Vec<Layer>,
-    // tile_map: TileMapContext,
-    // sprite_map: SpriteMapContext,
+/// Where the demo's world chunks are saved, next to the saved maps.
+#[cfg(not(target_arch = "wasm32"))]
+const WORLD_DIRECTORY: &str = "world";
+
+/// The world in [`WORLD_DIRECTORY`]. The first time, it's the map of `tilemap` at the origin
+/// and nothing around it. On the web it only lives in memory.
+fn world_from(
+    tilemap: &TileMapContext,
+    resources: &ResourceManager,
+) -> Result<WorldMap, WorldError> {
+    #[cfg(not(target_arch = "wasm32"))]
+    let directory = Some(std::path::PathBuf::from(WORLD_DIRECTORY));
+    #[cfg(target_arch = "wasm32")]
+    let directory = None;
+    let seed = !directory
+        .as_ref()
+        .is_some_and(|directory| directory.exists());
+
+    let mut world = WorldMap::new(
+        resources.texture_name(tilemap.texture).unwrap_or_default(),
+        directory,
+    );
+    world.tile_width = tilemap.map.tile_width;
+    world.tile_height = tilemap.map.tile_height;
+    if seed {
+        for y in 0..tilemap.map.tiles_y as i32 {
+            for x in 0..tilemap.map.tiles_x as i32 {
+                world.set(x, y, tilemap.map.get(x, y).unwrap_or_default())?;
+            }
+        }
+        world.save()?;
+    }
+    Ok(world)
 }
 
-fn sprite_sheet() -> (i32, i32, Vec<u8>) {
-    let image = include_bytes!("../res/spritesheet.png");
-    let thing = image::load(Cursor::new(image), image::ImageFormat::Png).unwrap();
-    let other = thing.to_rgba8();
-    (
-        thing.width() as i32,
-        thing.height() as i32,
-        other.into_vec(),
-    )
+fn texture_picker(
+    ui: &mut egui::Ui,
+    index: usize,
+    resources: &ResourceManager,
+    texture: &mut TextureHandle,
+) {
+    ComboBox::new(("texture", index), "Texture")
+        .selected_text(resources.texture_name(*texture).unwrap_or("<missing>"))
+        .show_ui(ui, |ui| {
+            for (handle, name) in resources.textures() {
+                ui.selectable_value(texture, handle, name);
+            }
+        });
 }
 
-impl RetroGraphics {
-    fn new(gl: &glow::Context) -> Option<Self> {
-        use glow::HasContext as _;
-        unsafe {
-            // gl.enable(glow::BLEND);
-            gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
-        }
+/// The layer's own scroll position and how fast it follows the camera.
+fn scroll_ui(ui: &mut egui::Ui, scroll: &mut Scroll) {
+    ui.horizontal(|ui| {
+        ui.label("scroll");
+        egui::DragValue::new(&mut scroll.x).ui(ui);
+        egui::DragValue::new(&mut scroll.y).ui(ui);
+    });
+    Slider::new(&mut scroll.parallax_x, 0.0..=2.0)
+        .text(" parallax x")
+        .ui(ui);
+    Slider::new(&mut scroll.parallax_y, 0.0..=2.0)
+        .text(" parallax y")
+        .ui(ui);
+}
 
-        let mut resources = ResourceManager::new();
+/// Which of a tilemap's raster tables the demo generates.
+#[derive(Clone, Copy, Default)]
+struct RasterDemo {
+    wave: bool,
+    spin: bool,
+    floor: bool,
+}
 
-        let texture;
-        unsafe {
-            let (width, height, pixels) = sprite_sheet();
-            let ntexture = gl.create_texture().unwrap();
-            gl.bind_texture(glow::TEXTURE_2D, Some(ntexture));
+/// Animated raster effects, they're regenerated every frame while they're on. Switching one
+/// off clears its table, tables from scenes or set in code are left alone otherwise.
+fn raster_ui(
+    ui: &mut egui::Ui,
+    index: usize,
+    demo: &mut RasterDemo,
+    raster: &mut Raster,
+    map: &TileMap,
+    screen: &ScreenContext,
+) {
+    let time = ui.input(|io| io.time);
+    let (width, height) = (screen.screen_px_x, screen.screen_px_y);
 
-            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, glow::REPEAT as i32);
-            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, glow::REPEAT as i32);
+    let wave_changed = ui.checkbox(&mut demo.wave, "Wave").changed();
+    if demo.wave {
+        raster.scanlines = wave_scanlines(time, height);
+    } else if wave_changed {
+        raster.scanlines.clear();
+    }
 
-            gl.tex_parameter_i32(
-                glow::TEXTURE_2D,
-                glow::TEXTURE_MIN_FILTER,
-                glow::NEAREST as i32,
-            );
-            gl.tex_parameter_i32(
-                glow::TEXTURE_2D,
-                glow::TEXTURE_MAG_FILTER,
-                glow::NEAREST as i32,
-            );
+    let spin_changed = ui.checkbox(&mut demo.spin, "Spin").changed();
+    if demo.spin {
+        let scale = 1.0 + 0.5 * (time * 0.7).sin() as f32;
+        let center = [
+            (map.pan_x + width / 2) as f32,
+            (map.pan_y + height / 2) as f32,
+        ];
+        raster.affine = Some(Affine::rotate_scale(
+            time as f32 * 0.5,
+            scale,
+            scale,
+            center,
+        ));
+    } else if spin_changed {
+        raster.affine = None;
+    }
 
-            gl.tex_image_2d(
-                glow::TEXTURE_2D,
-                0,
-                glow::RGBA8 as i32,
-                width,
-                height,
-                glow::NONE as i32,
-                glow::RGBA,
-                glow::UNSIGNED_BYTE,
-                Some(&pixels),
-            );
+    let floor_changed = ui.checkbox(&mut demo.floor, "Floor").changed();
+    if demo.floor {
+        raster.affine_scanlines = floor_scanlines(map, width, height);
+    } else if floor_changed {
+        raster.affine_scanlines.clear();
+    }
 
-            gl.generate_mipmap(glow::TEXTURE_2D);
+    let fill = EdgeMode::Fill(map.tiles.first().copied().unwrap_or_default());
+    ComboBox::new(("edge", index), "Edge")
+        .selected_text(match raster.edge {
+            EdgeMode::Wrap => "Wrap",
+            EdgeMode::Clamp => "Clamp",
+            EdgeMode::Fill(_) => "Fill",
+            EdgeMode::Transparent => "Transparent",
+        })
+        .show_ui(ui, |ui| {
+            ui.selectable_value(&mut raster.edge, EdgeMode::Wrap, "Wrap");
+            ui.selectable_value(&mut raster.edge, EdgeMode::Clamp, "Clamp");
+            ui.selectable_value(&mut raster.edge, fill, "Fill with the first tile");
+            ui.selectable_value(&mut raster.edge, EdgeMode::Transparent, "Transparent");
+        });
 
-            texture = Texture {
-                texture: ntexture,
-                width,
-                height,
-            };
-            resources.insert_texture(texture);
+    if demo.wave || demo.spin {
+        ui.ctx().request_repaint();
+    }
+}
+
+/// Plays the first sprite's tile and the next few after it back and forth on every sprite.
+fn clip_ui(ui: &mut egui::Ui, sprites: &mut SpriteMapContext) {
+    let mut playing = sprites.playback.iter().any(Option::is_some);
+    if !ui.checkbox(&mut playing, "Animate").changed() {
+        return;
+    }
+    if let Some(first) = sprites.thing.first() {
+        let x_size = first.attribute.get(SpriteAttributes::XSIZE);
+        let y_size = first.attribute.get(SpriteAttributes::YSIZE);
+        let frames = (0..4)
+            .map(|frame| SpriteFrame {
+                tx: first.tx.wrapping_add(frame * (x_size as u16 + 1)),
+                ty: first.ty,
+                x_size,
+                y_size,
+                duration: 0.2,
+                ..Default::default()
+            })
+            .collect();
+        sprites.clips.insert("demo".into(), SpriteClip { frames });
+    }
+    for index in 0..sprites.thing.len() {
+        if playing {
+            sprites.play(index, "demo", PlaybackMode::PingPong);
+        } else {
+            sprites.stop(index);
         }
+    }
+}
 
-        Some(Self {
-            layers: vec![
-                Layer::Sprite(
-                    SpriteMapContext::new(gl, &mut resources, texture)
-                        .expect("Failed to create tilemap"),
-                ),
-                Layer::TileMap(
-                    TileMapContext::new(gl, &mut resources, texture)
-                        .expect("Failed to create tilemap"),
-                ),
-            ],
-            screen: ScreenContext {
-                screen_px_x: 256,
-                screen_px_y: 224,
-                zoom: 1.0,
-            },
-            resources: ResourceManager::new(),
+/// Hardware sprite limits, and what they dropped last frame.
+fn limits_ui(ui: &mut egui::Ui, index: usize, sprites: &mut SpriteMapContext) {
+    let flicker = sprites.limits.is_some_and(|limits| limits.flicker);
+    let with_flicker = |limits| SpriteLimits { flicker, ..limits };
+    ComboBox::new(("limits", index), "Limits")
+        .selected_text(match sprites.limits {
+            None => "None",
+            Some(limits) if limits.sprites == SpriteLimits::NES.sprites => "NES",
+            Some(_) => "SNES",
         })
+        .show_ui(ui, |ui| {
+            ui.selectable_value(&mut sprites.limits, None, "None");
+            ui.selectable_value(
+                &mut sprites.limits,
+                Some(with_flicker(SpriteLimits::NES)),
+                "NES",
+            );
+            ui.selectable_value(
+                &mut sprites.limits,
+                Some(with_flicker(SpriteLimits::SNES)),
+                "SNES",
+            );
+        });
+    let Some(limits) = &mut sprites.limits else {
+        return;
+    };
+    ui.checkbox(&mut limits.flicker, "Flicker");
+    if limits.flicker {
+        ui.ctx().request_repaint();
     }
+    let overflow = sprites.overflow();
+    ui.label(format!(
+        "dropped {} sprites on {} lines",
+        overflow.iter().sum::<u32>(),
+        overflow.iter().filter(|&&dropped| dropped > 0).count()
+    ));
+}
 
-    fn destroy(&mut self, gl: &glow::Context) {
-        unsafe {
-            for layer in &mut self.layers {
-                layer.destroy(gl)
-            }
-            // self.tile_map.destroy(gl);
-            // self.sprite_map.destroy(gl);
-            self.resources.destroy(gl);
-        }
+fn tile_size_ui(ui: &mut egui::Ui, index: usize, map: &mut TileMap) {
+    for (name, size) in [
+        ("Tile width", &mut map.tile_width),
+        ("Tile height", &mut map.tile_height),
+    ] {
+        ComboBox::new((name, index), name)
+            .selected_text(size.to_string())
+            .show_ui(ui, |ui| {
+                for option in [8, 16, 32] {
+                    ui.selectable_value(size, option, option.to_string());
+                }
+            });
     }
+}
 
-    fn paint(&mut self, gl: &glow::Context) {
-        use glow::HasContext as _;
-        unsafe {
-            gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
-            // gl.enable(glow::DEPTH_TEST);
-            // gl.clear(glow::DEPTH_BUFFER_BIT);
+/// Animates the map's first tile through the next few tiles of its sheet row.
+fn animation_ui(ui: &mut egui::Ui, tilemap: &TileMapContext, resources: &mut ResourceManager) {
+    let Some(first) = tilemap.map.tiles.first() else {
+        return;
+    };
+    let animations = resources.tile_animations(tilemap.texture);
+    let mut animated = animations
+        .iter()
+        .any(|animation| (animation.x, animation.y) == (first.x, first.y));
+    if ui.checkbox(&mut animated, "Animate first tile").changed() {
+        let mut animations: Vec<_> = animations
+            .iter()
+            .filter(|animation| (animation.x, animation.y) != (first.x, first.y))
+            .cloned()
+            .collect();
+        if animated {
+            // it and the next 3 tiles right of it, as many of them as are on the sheet
+            let columns = resources
+                .texture(tilemap.texture)
+                .map_or(0, |texture| texture.width / tilemap.map.tile_size().0);
+            animations.push(TileAnimation {
+                x: first.x,
+                y: first.y,
+                frames: (0..4)
+                    .map_while(|frame| first.x.checked_add(frame))
+                    .filter(|&x| (x as i32) < columns)
+                    .map(|x| TileFrame {
+                        x,
+                        y: first.y,
+                        duration: 0.25,
+                    })
+                    .collect(),
+            });
         }
-        for layer in self.layers.iter_mut().rev() {
-            unsafe {
-                layer.paint(gl, &self.screen);
+        resources.set_tile_animations(tilemap.texture, animations);
+    }
+}
+
+/// A floor stretching away to the top of the screen, each line is further away and smaller.
+fn floor_scanlines(map: &TileMap, width: i32, height: i32) -> Vec<Affine> {
+    let center_x = (map.pan_x + width / 2) as f32;
+    (0..height.max(1))
+        .map(|line| {
+            let distance = 2048.0 / (line as f32 + 16.0);
+            Affine {
+                matrix: [distance / 128.0, 0.0, 0.0, 0.0],
+                center: [center_x, map.pan_y as f32 + distance],
             }
-        }
+        })
+        .collect()
+}
 
-        // for layer in self.
-        // self.tile_map.paint(gl, zoom);
+/// Sways every line sideways on a sine wave that moves down the screen over `time`.
+fn wave_scanlines(time: f64, lines: i32) -> Vec<(i32, i32)> {
+    (0..lines.max(1))
+        .map(|line| {
+            let phase = line as f64 * 0.15 - time * 4.0;
+            ((phase.sin() * 4.0).round() as i32, 0)
+        })
+        .collect()
+}
 
-        // self.sprite_map.paint(gl, zoom,
-        //     self.tile_map.map.tiles_vis_x as i32 * 8,
-        //     self.tile_map.map.tiles_vis_y as i32 * 8,
-        //     self.tile_map.map.pan_x,
-        //     self.tile_map.map.pan_y
-        // );
-    }
+/// Only affects indexed textures.
+fn palette_slider<T: egui::emath::Numeric>(ui: &mut egui::Ui, palette: &mut T) -> bool {
+    Slider::new(
+        palette,
+        T::from_f64(0.0)..=T::from_f64((PALETTE_COUNT - 1) as f64),
+    )
+    .text(" palette")
+    .ui(ui)
+    .changed()
 }
@@ -0,0 +1,383 @@
+//! Tilemap files.
+//!
+//! The binary form is little endian:
+//!
+//! | bytes       | contents                                                          |
+//! |-------------|-------------------------------------------------------------------|
+//! | 4           | magic `RTMP`                                                      |
+//! | 2           | format version, currently 2                                       |
+//! | 2           | `tiles_x`                                                         |
+//! | 2           | `tiles_y`                                                         |
+//! | 2           | `tile_width`, not in version 1 where tiles are 8x8                |
+//! | 2           | `tile_height`, not in version 1                                   |
+//! | 2           | length of the tileset name in bytes                               |
+//! | name length | tileset name in UTF-8, the name it has in `ResourceManager`       |
+//! | 8 per tile  | the tiles row by row, laid out like the `#[repr(C)]` [`Tile`]     |
+//!
+//! A tile is `x` u16, `y` u16, `layer` u8, a reserved zero byte and `attributes` u16. Files
+//! with anything but zero in the reserved byte or the unused attribute bits are rejected, so
+//! both forms hold the same maps.
+//!
+//! The JSON form holds the same information with the attribute bits spelled out:
+//!
+//! ```json
+//! {
+//!   "version": 2,
+//!   "tileset": "spritesheet",
+//!   "tiles_x": 2,
+//!   "tiles_y": 1,
+//!   "tile_width": 16,
+//!   "tile_height": 8,
+//!   "tiles": [
+//!     { "x": 0, "y": 0, "layer": 50, "flip_h": false, "flip_v": false, "rotation": 0, "palette": 0 },
+//!     { "x": 1, "y": 0, "layer": 50, "flip_h": true, "flip_v": false, "rotation": 1, "palette": 2 }
+//!   ]
+//! }
+//! ```
+//!
+//! Version 1 files are still read, their tiles are 8x8.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    resources::ResourceManager,
+    tilemap::{Tile, TileAttributes, TileMap, TileMapContext},
+    OutOfRange,
+};
+
+pub const MAGIC: [u8; 4] = *b"RTMP";
+pub const VERSION: u16 = 2;
+
+/// A tilemap together with the name of the tileset it's drawn with.
+#[derive(Clone, PartialEq, Eq)]
+pub struct TileMapFile {
+    pub tileset: String,
+    /// The pan is not stored, it's 0 after loading.
+    pub map: TileMap,
+}
+
+#[derive(Debug)]
+pub enum MapFileError {
+    /// The binary form didn't start with [`MAGIC`].
+    BadMagic,
+    UnsupportedVersion(u16),
+    /// The file ended before every tile was read.
+    Truncated,
+    BadTilesetName(std::str::Utf8Error),
+    /// The number of tiles doesn't match `tiles_x` * `tiles_y`.
+    WrongTileCount {
+        expected: usize,
+        found: usize,
+    },
+    /// A field of tile `index` is too big for its bits.
+    BadTile {
+        index: usize,
+        field: &'static str,
+    },
+    Json(serde_json::Error),
+}
+
+impl std::fmt::Display for MapFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapFileError::BadMagic => write!(f, "not a tilemap file"),
+            MapFileError::UnsupportedVersion(version) => {
+                write!(f, "unsupported tilemap version {version}")
+            }
+            MapFileError::Truncated => write!(f, "tilemap file is truncated"),
+            MapFileError::BadTilesetName(err) => write!(f, "bad tileset name: {err}"),
+            MapFileError::WrongTileCount { expected, found } => {
+                write!(f, "expected {expected} tiles but found {found}")
+            }
+            MapFileError::BadTile { index, field } => {
+                write!(f, "{field} of tile {index} is out of range")
+            }
+            MapFileError::Json(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for MapFileError {}
+
+impl From<serde_json::Error> for MapFileError {
+    fn from(err: serde_json::Error) -> Self {
+        MapFileError::Json(err)
+    }
+}
+
+impl TileMapFile {
+    /// The map of `tilemap` and the name of its texture, empty if it's missing.
+    pub fn from_context(tilemap: &TileMapContext, resources: &ResourceManager) -> Self {
+        let mut map = tilemap.map.clone();
+        map.pan_x = 0;
+        map.pan_y = 0;
+        Self {
+            tileset: resources
+                .texture_name(tilemap.texture)
+                .unwrap_or_default()
+                .into(),
+            map,
+        }
+    }
+
+    /// Replaces the map of `tilemap` keeping its pan, and switches to the tileset if a
+    /// texture with that name is registered. Returns whether the tileset was found.
+    pub fn apply(self, tilemap: &mut TileMapContext, resources: &ResourceManager) -> bool {
+        let (pan_x, pan_y) = (tilemap.map.pan_x, tilemap.map.pan_y);
+        tilemap.map = TileMap {
+            pan_x,
+            pan_y,
+            ..self.map
+        };
+        match resources.texture_handle(&self.tileset) {
+            Some(texture) => {
+                tilemap.texture = texture;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Reads either form, binary files are recognized by their magic.
+    pub fn load(bytes: &[u8]) -> Result<Self, MapFileError> {
+        if bytes.starts_with(&MAGIC) {
+            Self::from_binary(bytes)
+        } else {
+            Self::from_json_file(serde_json::from_slice(bytes)?)
+        }
+    }
+
+    pub fn to_binary(&self) -> Vec<u8> {
+        let name = self.tileset.as_bytes();
+        let mut bytes = Vec::with_capacity(16 + name.len() + self.map.tiles.len() * 8);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.map.tiles_x.to_le_bytes());
+        bytes.extend_from_slice(&self.map.tiles_y.to_le_bytes());
+        bytes.extend_from_slice(&self.map.tile_width.to_le_bytes());
+        bytes.extend_from_slice(&self.map.tile_height.to_le_bytes());
+        bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(name);
+        for tile in &self.map.tiles {
+            bytes.extend_from_slice(&tile.x.to_le_bytes());
+            bytes.extend_from_slice(&tile.y.to_le_bytes());
+            bytes.push(tile.layer);
+            bytes.push(0);
+            bytes.extend_from_slice(&tile.attributes.bits().to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_binary(bytes: &[u8]) -> Result<Self, MapFileError> {
+        let mut reader = Reader(bytes);
+        if reader.take(4)? != MAGIC {
+            return Err(MapFileError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if !(1..=VERSION).contains(&version) {
+            return Err(MapFileError::UnsupportedVersion(version));
+        }
+        let tiles_x = reader.u16()?;
+        let tiles_y = reader.u16()?;
+        let (tile_width, tile_height) = match version {
+            1 => (8, 8),
+            _ => (reader.u16()?, reader.u16()?),
+        };
+        let name_len = reader.u16()? as usize;
+        let tileset = std::str::from_utf8(reader.take(name_len)?)
+            .map_err(MapFileError::BadTilesetName)?
+            .to_owned();
+
+        let count = tiles_x as usize * tiles_y as usize;
+        // before allocating, the header alone can ask for gigabytes, more than a 32 bit
+        // usize holds
+        if count.checked_mul(8).is_none_or(|len| reader.0.len() < len) {
+            return Err(MapFileError::Truncated);
+        }
+        let mut tiles = Vec::with_capacity(count);
+        for index in 0..count {
+            let mut tile = Tile::default();
+            tile.x = reader.u16()?;
+            tile.y = reader.u16()?;
+            let [layer, reserved] = reader.take(2)?.try_into().expect("Took 2 bytes");
+            tile.layer = layer;
+            tile.attributes = TileAttributes::from_bits(reader.u16()?);
+            if reserved != 0 {
+                return Err(MapFileError::BadTile {
+                    index,
+                    field: "reserved",
+                });
+            }
+            // the JSON form only holds the bits that are used
+            if Tile::try_from(JsonTile::from(&tile)) != Ok(tile) {
+                return Err(MapFileError::BadTile {
+                    index,
+                    field: "attributes",
+                });
+            }
+            tiles.push(tile);
+        }
+
+        Ok(Self {
+            tileset,
+            map: TileMap {
+                tiles_x,
+                tiles_y,
+                tile_width,
+                tile_height,
+                tiles,
+                ..Default::default()
+            },
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        let file = JsonFile {
+            version: VERSION,
+            tileset: self.tileset.clone(),
+            tiles_x: self.map.tiles_x,
+            tiles_y: self.map.tiles_y,
+            tile_width: self.map.tile_width,
+            tile_height: self.map.tile_height,
+            tiles: self.map.tiles.iter().map(JsonTile::from).collect(),
+        };
+        serde_json::to_string_pretty(&file).expect("Tilemaps always serialize")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, MapFileError> {
+        Self::from_json_file(serde_json::from_str(json)?)
+    }
+
+    fn from_json_file(file: JsonFile) -> Result<Self, MapFileError> {
+        if !(1..=VERSION).contains(&file.version) {
+            return Err(MapFileError::UnsupportedVersion(file.version));
+        }
+        let expected = file.tiles_x as usize * file.tiles_y as usize;
+        if file.tiles.len() != expected {
+            return Err(MapFileError::WrongTileCount {
+                expected,
+                found: file.tiles.len(),
+            });
+        }
+
+        Ok(Self {
+            tileset: file.tileset,
+            map: TileMap {
+                tiles_x: file.tiles_x,
+                tiles_y: file.tiles_y,
+                tile_width: file.tile_width,
+                tile_height: file.tile_height,
+                tiles: file
+                    .tiles
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, tile)| {
+                        Tile::try_from(tile)
+                            .map_err(|OutOfRange(field)| MapFileError::BadTile { index, field })
+                    })
+                    .collect::<Result<_, _>>()?,
+                ..Default::default()
+            },
+        })
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], MapFileError> {
+        if self.0.len() < len {
+            return Err(MapFileError::Truncated);
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16, MapFileError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonFile {
+    version: u16,
+    tileset: String,
+    tiles_x: u16,
+    tiles_y: u16,
+    /// Missing in version 1.
+    #[serde(default = "default_tile_size")]
+    tile_width: u16,
+    #[serde(default = "default_tile_size")]
+    tile_height: u16,
+    tiles: Vec<JsonTile>,
+}
+
+pub(crate) fn default_tile_size() -> u16 {
+    8
+}
+
+/// A [`Tile`] with its attributes spelled out.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct JsonTile {
+    pub x: u16,
+    pub y: u16,
+    pub layer: u8,
+    #[serde(default)]
+    pub flip_h: bool,
+    #[serde(default)]
+    pub flip_v: bool,
+    #[serde(default)]
+    pub rotation: u16,
+    #[serde(default)]
+    pub palette: u16,
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+impl From<&Tile> for JsonTile {
+    fn from(tile: &Tile) -> Self {
+        Self {
+            x: tile.x,
+            y: tile.y,
+            layer: tile.layer,
+            flip_h: tile.attributes.get(TileAttributes::HORIZONTAL),
+            flip_v: tile.attributes.get(TileAttributes::VERTICAL),
+            rotation: tile.attributes.get(TileAttributes::ROTATION),
+            palette: tile.attributes.get(TileAttributes::PALETTE),
+            hidden: tile.attributes.get(TileAttributes::HIDDEN),
+        }
+    }
+}
+
+impl From<Tile> for JsonTile {
+    fn from(tile: Tile) -> Self {
+        Self::from(&tile)
+    }
+}
+
+impl TryFrom<JsonTile> for Tile {
+    type Error = OutOfRange;
+
+    fn try_from(json: JsonTile) -> Result<Self, OutOfRange> {
+        let rotation = OutOfRange::check(
+            json.rotation,
+            TileAttributes::ROTATION.max_value(),
+            "rotation",
+        )?;
+        let palette =
+            OutOfRange::check(json.palette, TileAttributes::PALETTE.max_value(), "palette")?;
+        let mut tile = Tile::default();
+        tile.x = json.x;
+        tile.y = json.y;
+        tile.layer = json.layer;
+        tile.attributes
+            .set(TileAttributes::HORIZONTAL, json.flip_h)
+            .set(TileAttributes::VERTICAL, json.flip_v)
+            .set(TileAttributes::ROTATION, rotation)
+            .set(TileAttributes::PALETTE, palette)
+            .set(TileAttributes::HIDDEN, json.hidden);
+        Ok(tile)
+    }
+}
@@ -0,0 +1,109 @@
+//! Indexed color: tilesets stored as palette indices and looked up in one of the palettes
+//! owned by `ResourceManager` when drawn.
+
+use egui::ahash::HashMap;
+use image::{
+    error::{DecodingError, ParameterError, ParameterErrorKind},
+    GrayImage, ImageError, ImageFormat, Luma, RgbaImage,
+};
+
+use crate::bitmap::Color;
+
+/// How many palettes `ResourceManager` keeps, the palette bits of tiles and sprites select one.
+pub const PALETTE_COUNT: usize = 16;
+pub const PALETTE_SIZE: usize = 256;
+
+pub type Palette = [Color; PALETTE_SIZE];
+
+/// A grayscale ramp with index 0 transparent.
+pub fn default_palette() -> Palette {
+    std::array::from_fn(|index| match index {
+        0 => [0; 4],
+        index => [index as u8, index as u8, index as u8, 255],
+    })
+}
+
+/// Splits an image into palette indices and the colors they refer to.
+///
+/// Indexed PNGs keep their own palette order (and `tRNS` transparency) so palette cycling
+/// works on the ranges the artist set up, anything else goes through [`quantize`].
+pub fn decode_indexed(bytes: &[u8]) -> image::ImageResult<(GrayImage, Vec<Color>)> {
+    if let Some(indexed) = decode_indexed_png(bytes)? {
+        return Ok(indexed);
+    }
+
+    let image = image::load_from_memory(bytes)?.to_rgba8();
+    quantize(&image).ok_or_else(|| {
+        ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+            format!("image has more than {PALETTE_SIZE} colors"),
+        )))
+    })
+}
+
+/// Builds indices and a palette from `image`, fully transparent pixels all become index 0 and
+/// the other colors follow in the order they first appear. `None` if there are too many colors.
+pub fn quantize(image: &RgbaImage) -> Option<(GrayImage, Vec<Color>)> {
+    let mut colors = vec![[0; 4]];
+    let mut lookup = HashMap::default();
+
+    let mut indices = GrayImage::new(image.width(), image.height());
+    for (index, pixel) in indices.pixels_mut().zip(image.pixels()) {
+        if pixel.0[3] == 0 {
+            continue;
+        }
+        let next = colors.len();
+        let value = *lookup.entry(pixel.0).or_insert(next);
+        if value == next {
+            if next == PALETTE_SIZE {
+                return None;
+            }
+            colors.push(pixel.0);
+        }
+        *index = Luma([value as u8]);
+    }
+    Some((indices, colors))
+}
+
+fn decode_indexed_png(bytes: &[u8]) -> image::ImageResult<Option<(GrayImage, Vec<Color>)>> {
+    let png_error = |err: png::DecodingError| {
+        ImageError::Decoding(DecodingError::new(ImageFormat::Png.into(), err))
+    };
+
+    if !bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Ok(None);
+    }
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::IDENTITY);
+    let mut reader = decoder.read_info().map_err(png_error)?;
+
+    let info = reader.info();
+    let (Some(palette), png::ColorType::Indexed) = (&info.palette, info.color_type) else {
+        return Ok(None);
+    };
+    let trns = info.trns.as_deref().unwrap_or_default();
+    let colors: Vec<Color> = palette
+        .chunks_exact(3)
+        .enumerate()
+        .map(|(index, rgb)| {
+            [
+                rgb[0],
+                rgb[1],
+                rgb[2],
+                trns.get(index).copied().unwrap_or(255),
+            ]
+        })
+        .collect();
+
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buffer).map_err(png_error)?;
+
+    // rows are packed at 1, 2, 4 or 8 bits per index, leftmost pixel in the high bits
+    let depth = frame.bit_depth as u32;
+    let per_byte = 8 / depth;
+    let mask = ((1u16 << depth) - 1) as u8;
+    let indices = GrayImage::from_fn(frame.width, frame.height, |x, y| {
+        let byte = buffer[y as usize * frame.line_size + (x / per_byte) as usize];
+        Luma([(byte >> (8 - depth * (x % per_byte + 1))) & mask])
+    });
+    Ok(Some((indices, colors)))
+}
@@ -1,12 +1,98 @@
-use egui::ahash::{HashMap, HashSet};
+use egui::ahash::HashMap;
 use glow::HasContext;
+use image::{
+    error::{ParameterError, ParameterErrorKind},
+    GrayImage, ImageError, RgbaImage,
+};
+
+use crate::{
+    bitmap::Color,
+    palette::{self, Palette, PALETTE_COUNT, PALETTE_SIZE},
+    tilemap::TileAnimation,
+};
 
-#[derive(Default)]
 pub struct ResourceManager {
     programs: HashMap<String, glow::Program>,
-    textures: HashSet<Texture>,
+    // indexed by TextureHandle, removed textures leave a hole so handles stay stable
+    textures: Vec<Option<TextureEntry>>,
+    texture_names: HashMap<String, TextureHandle>,
+
+    palettes: Vec<Palette>,
+    // PALETTE_SIZE x PALETTE_COUNT, one palette per row
+    palette_texture: Option<glow::Texture>,
+    palettes_dirty: bool,
+
+    // per tileset, they stay when its texture is replaced
+    tile_animations: HashMap<TextureHandle, Vec<TileAnimation>>,
+}
+
+impl Default for ResourceManager {
+    fn default() -> Self {
+        Self {
+            programs: Default::default(),
+            textures: Vec::new(),
+            texture_names: Default::default(),
+            palettes: vec![palette::default_palette(); PALETTE_COUNT],
+            palette_texture: None,
+            palettes_dirty: true,
+            tile_animations: Default::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TextureHandle(usize);
+
+struct TextureEntry {
+    name: String,
+    texture: Texture,
+    pixels: TexturePixels,
+}
+
+/// The pixels a texture was created from.
+pub enum TexturePixels {
+    Rgba(RgbaImage),
+    /// Palette indices, drawn with the palette picked by each tile or sprite.
+    Indexed(GrayImage),
+}
+
+impl TexturePixels {
+    pub fn width(&self) -> u32 {
+        match self {
+            TexturePixels::Rgba(image) => image.width(),
+            TexturePixels::Indexed(image) => image.width(),
+        }
+    }
+
+    pub fn height(&self) -> u32 {
+        match self {
+            TexturePixels::Rgba(image) => image.height(),
+            TexturePixels::Indexed(image) => image.height(),
+        }
+    }
+}
+
+/// Why [`ResourceManager::compile_program`] failed, with the GL info log.
+#[derive(Debug)]
+pub enum ProgramError {
+    /// The context only has the old shader interface.
+    Unsupported,
+    Compile(String),
+    Link(String),
+}
+
+impl std::fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProgramError::Unsupported => write!(f, "shaders aren't supported"),
+            ProgramError::Compile(log) => write!(f, "failed to compile: {log}"),
+            ProgramError::Link(log) => write!(f, "failed to link: {log}"),
+        }
+    }
 }
 
+impl std::error::Error for ProgramError {}
+
 pub enum ProgramKind {
     Vertex,
     Fragment,
@@ -18,80 +104,401 @@ impl ResourceManager {
         Self::default()
     }
 
+    /// # Safety
+    /// `gl` must be the context the resources were created with and they can't be used afterwards.
     pub unsafe fn destroy(&mut self, gl: &glow::Context) {
         for (_, program) in self.programs.drain() {
             gl.delete_program(program);
         }
 
-        for texture in self.textures.drain() {
-            gl.delete_texture(texture.texture);
+        for entry in self.textures.drain(..).flatten() {
+            gl.delete_texture(entry.texture.texture);
+        }
+        self.texture_names.clear();
+        self.tile_animations.clear();
+
+        if let Some(texture) = self.palette_texture.take() {
+            gl.delete_texture(texture);
+        }
+        self.palettes_dirty = true;
+    }
+
+    /// Uploads `image` as a repeating, nearest filtered texture owned by the manager and
+    /// registers it under `name`, replacing whatever texture had that name before while
+    /// keeping its handle.
+    pub fn create_texture(
+        &mut self,
+        gl: &glow::Context,
+        name: &str,
+        image: RgbaImage,
+    ) -> TextureHandle {
+        self.insert_texture(gl, name, TexturePixels::Rgba(image))
+    }
+
+    /// Like [`ResourceManager::create_texture`] but every pixel is an index into the palette
+    /// chosen by the tile or sprite drawing it.
+    pub fn create_indexed_texture(
+        &mut self,
+        gl: &glow::Context,
+        name: &str,
+        indices: GrayImage,
+    ) -> TextureHandle {
+        self.insert_texture(gl, name, TexturePixels::Indexed(indices))
+    }
+
+    fn insert_texture(
+        &mut self,
+        gl: &glow::Context,
+        name: &str,
+        pixels: TexturePixels,
+    ) -> TextureHandle {
+        let width = pixels.width() as i32;
+        let height = pixels.height() as i32;
+        let texture;
+        unsafe {
+            let ntexture = gl.create_texture().expect("Cannot create texture");
+            gl.bind_texture(glow::TEXTURE_2D, Some(ntexture));
+
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, glow::REPEAT as i32);
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, glow::REPEAT as i32);
+
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MIN_FILTER,
+                glow::NEAREST as i32,
+            );
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MAG_FILTER,
+                glow::NEAREST as i32,
+            );
+
+            match &pixels {
+                TexturePixels::Rgba(image) => {
+                    gl.tex_image_2d(
+                        glow::TEXTURE_2D,
+                        0,
+                        glow::RGBA8 as i32,
+                        width,
+                        height,
+                        glow::NONE as i32,
+                        glow::RGBA,
+                        glow::UNSIGNED_BYTE,
+                        Some(image.as_raw()),
+                    );
+
+                    gl.generate_mipmap(glow::TEXTURE_2D);
+                }
+                TexturePixels::Indexed(indices) => {
+                    // rows of single bytes aren't 4 byte aligned
+                    gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+                    gl.tex_image_2d(
+                        glow::TEXTURE_2D,
+                        0,
+                        glow::R8 as i32,
+                        width,
+                        height,
+                        glow::NONE as i32,
+                        glow::RED,
+                        glow::UNSIGNED_BYTE,
+                        Some(indices.as_raw()),
+                    );
+                    gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+                }
+            }
+
+            texture = Texture {
+                texture: ntexture,
+                width,
+                height,
+                indexed: matches!(pixels, TexturePixels::Indexed(_)),
+            };
+        }
+
+        let entry = TextureEntry {
+            name: name.into(),
+            texture,
+            pixels,
+        };
+        match self.texture_names.get(name) {
+            Some(&handle) => {
+                if let Some(old) = self.textures[handle.0].replace(entry) {
+                    old.texture.destroy(gl);
+                }
+                handle
+            }
+            None => {
+                let handle = TextureHandle(self.textures.len());
+                self.textures.push(Some(entry));
+                self.texture_names.insert(name.into(), handle);
+                handle
+            }
+        }
+    }
+
+    /// Decodes an image file (PNG or anything else the `image` crate knows) and registers it
+    /// like [`ResourceManager::create_texture`].
+    pub fn load_texture(
+        &mut self,
+        gl: &glow::Context,
+        name: &str,
+        bytes: &[u8],
+    ) -> image::ImageResult<TextureHandle> {
+        let image = image::load_from_memory(bytes)?.to_rgba8();
+        Ok(self.create_texture(gl, name, image))
+    }
+
+    /// Decodes an image file into an indexed texture, its colors are written to the start of
+    /// `palette`. See [`palette::decode_indexed`].
+    pub fn load_indexed_texture(
+        &mut self,
+        gl: &glow::Context,
+        name: &str,
+        bytes: &[u8],
+        palette: usize,
+    ) -> image::ImageResult<TextureHandle> {
+        if palette >= self.palettes.len() {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(format!("there's no palette {palette}")),
+            )));
+        }
+        let (indices, colors) = palette::decode_indexed(bytes)?;
+        self.set_palette(palette, &colors);
+        Ok(self.create_indexed_texture(gl, name, indices))
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_texture_file(
+        &mut self,
+        gl: &glow::Context,
+        name: &str,
+        path: impl AsRef<std::path::Path>,
+    ) -> image::ImageResult<TextureHandle> {
+        let image = image::open(path)?.to_rgba8();
+        Ok(self.create_texture(gl, name, image))
+    }
+
+    /// Frees the texture, handles to it stay invalid even if the name gets reused.
+    pub fn remove_texture(&mut self, gl: &glow::Context, handle: TextureHandle) {
+        if let Some(entry) = self.textures.get_mut(handle.0).and_then(Option::take) {
+            self.texture_names.remove(&entry.name);
+            self.tile_animations.remove(&handle);
+            entry.texture.destroy(gl);
+        }
+    }
+
+    /// Animates tiles of the tileset `handle` wherever tilemaps place them, replacing its
+    /// previous animations.
+    pub fn set_tile_animations(&mut self, handle: TextureHandle, animations: Vec<TileAnimation>) {
+        if animations.is_empty() {
+            self.tile_animations.remove(&handle);
+        } else {
+            self.tile_animations.insert(handle, animations);
+        }
+    }
+
+    pub fn tile_animations(&self, handle: TextureHandle) -> &[TileAnimation] {
+        self.tile_animations.get(&handle).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn texture(&self, handle: TextureHandle) -> Option<Texture> {
+        self.entry(handle).map(|entry| entry.texture)
+    }
+
+    pub fn texture_pixels(&self, handle: TextureHandle) -> Option<&TexturePixels> {
+        self.entry(handle).map(|entry| &entry.pixels)
+    }
+
+    pub fn texture_name(&self, handle: TextureHandle) -> Option<&str> {
+        self.entry(handle).map(|entry| entry.name.as_str())
+    }
+
+    pub fn texture_handle(&self, name: &str) -> Option<TextureHandle> {
+        self.texture_names.get(name).copied()
+    }
+
+    /// Every registered texture in the order they were first created.
+    pub fn textures(&self) -> impl Iterator<Item = (TextureHandle, &str)> {
+        self.textures
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| {
+                entry
+                    .as_ref()
+                    .map(|entry| (TextureHandle(index), entry.name.as_str()))
+            })
+    }
+
+    fn entry(&self, handle: TextureHandle) -> Option<&TextureEntry> {
+        self.textures.get(handle.0)?.as_ref()
+    }
+
+    pub fn palettes(&self) -> &[Palette] {
+        &self.palettes
+    }
+
+    /// `None` past the last of the [`PALETTE_COUNT`] palettes.
+    pub fn palette(&self, index: usize) -> Option<&Palette> {
+        self.palettes.get(index)
+    }
+
+    /// Changes show up the next time the layers are painted.
+    pub fn palette_mut(&mut self, index: usize) -> Option<&mut Palette> {
+        let palette = self.palettes.get_mut(index)?;
+        self.palettes_dirty = true;
+        Some(palette)
+    }
+
+    /// Overwrites the start of palette `index` with `colors`, leaving the rest of it alone.
+    /// False if there's no palette `index`.
+    pub fn set_palette(&mut self, index: usize, colors: &[Color]) -> bool {
+        let colors = &colors[..colors.len().min(PALETTE_SIZE)];
+        let Some(palette) = self.palette_mut(index) else {
+            return false;
+        };
+        palette[..colors.len()].copy_from_slice(colors);
+        true
+    }
+
+    /// Rotates the colors in `range` of palette `index` by `steps`, towards the end of the range
+    /// when positive. Calling it every few frames gives classic palette cycling. False if
+    /// there's no palette `index` or `range` goes past its end.
+    pub fn cycle_palette(
+        &mut self,
+        index: usize,
+        range: std::ops::Range<usize>,
+        steps: isize,
+    ) -> bool {
+        let Some(colors) = self
+            .palettes
+            .get_mut(index)
+            .and_then(|palette| palette.get_mut(range))
+        else {
+            return false;
+        };
+        if !colors.is_empty() {
+            colors.rotate_right(steps.rem_euclid(colors.len() as isize) as usize);
         }
+        self.palettes_dirty = true;
+        true
     }
 
-    pub fn insert_texture(&mut self, texture: Texture) {
-        self.textures.insert(texture);
+    /// Uploads the palettes if they changed, `RetroGraphics` does this before painting.
+    pub fn upload_palettes(&mut self, gl: &glow::Context) {
+        if !self.palettes_dirty && self.palette_texture.is_some() {
+            return;
+        }
+        unsafe {
+            let texture = match self.palette_texture {
+                Some(texture) => texture,
+                None => {
+                    let texture = gl.create_texture().expect("Cannot create texture");
+                    gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+                    for (parameter, value) in [
+                        (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+                        (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+                        (glow::TEXTURE_MIN_FILTER, glow::NEAREST),
+                        (glow::TEXTURE_MAG_FILTER, glow::NEAREST),
+                    ] {
+                        gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, value as i32);
+                    }
+                    self.palette_texture = Some(texture);
+                    texture
+                }
+            };
+
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA8 as i32,
+                PALETTE_SIZE as i32,
+                PALETTE_COUNT as i32,
+                glow::NONE as i32,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                Some(self.palettes.as_flattened().as_flattened()),
+            );
+        }
+        self.palettes_dirty = false;
     }
 
-    pub fn remove_texture(&mut self, texture: Texture) {
-        self.textures.remove(&texture);
+    /// `None` until [`ResourceManager::upload_palettes`] runs.
+    pub fn palette_texture(&self) -> Option<glow::Texture> {
+        self.palette_texture
     }
 
+    /// Like [`ResourceManager::compile_program`] for the shaders that come with the crate,
+    /// which always compile, so failing to is a bug and panics.
     pub fn get_program(
         &mut self,
         gl: &glow::Context,
         name: &str,
         shader_sources: &[(ProgramKind, &str)],
     ) -> Option<glow::Program> {
+        match self.compile_program(gl, name, shader_sources) {
+            Ok(program) => Some(program),
+            Err(ProgramError::Unsupported) => None,
+            Err(err) => panic!("{name}: {err}"),
+        }
+    }
+
+    /// Compiles and links the program `name` the first time it's asked for, later calls
+    /// return it without looking at `shader_sources`.
+    pub fn compile_program(
+        &mut self,
+        gl: &glow::Context,
+        name: &str,
+        shader_sources: &[(ProgramKind, &str)],
+    ) -> Result<glow::Program, ProgramError> {
         if let Some(program) = self.programs.get(name) {
-            return Some(*program);
+            return Ok(*program);
         }
         unsafe {
             let shader_version = eframe::egui_glow::ShaderVersion::get(gl);
-            let program = gl.create_program().expect("Cannot create program");
-
             if !shader_version.is_new_shader_interface() {
-                return None;
+                return Err(ProgramError::Unsupported);
             }
             let shader_version = shader_version.version_declaration();
-            // panic!("{shader_version}");
-
-            let shaders: Vec<_> = shader_sources
-                .iter()
-                .map(|(shader_type, shader_source)| {
-                    let shader_type = match shader_type {
-                        ProgramKind::Vertex => glow::VERTEX_SHADER,
-                        ProgramKind::Fragment => glow::FRAGMENT_SHADER,
-                        ProgramKind::Compute => glow::COMPUTE_SHADER,
-                    };
-                    let shader = gl.create_shader(shader_type).expect("Cannot create shader");
-                    gl.shader_source(shader, &format!("{}\n{}", shader_version, shader_source));
-                    gl.compile_shader(shader);
-                    assert!(
-                        gl.get_shader_compile_status(shader),
-                        "Failed to compile custom_3d_glow {shader_type}: {}",
-                        gl.get_shader_info_log(shader)
-                    );
+            let program = gl.create_program().expect("Cannot create program");
 
-                    gl.attach_shader(program, shader);
-                    shader
-                })
-                .collect();
+            let mut shaders = Vec::with_capacity(shader_sources.len());
+            let mut error = None;
+            for (shader_type, shader_source) in shader_sources {
+                let shader_type = match shader_type {
+                    ProgramKind::Vertex => glow::VERTEX_SHADER,
+                    ProgramKind::Fragment => glow::FRAGMENT_SHADER,
+                    ProgramKind::Compute => glow::COMPUTE_SHADER,
+                };
+                let shader = gl.create_shader(shader_type).expect("Cannot create shader");
+                gl.shader_source(shader, &format!("{}\n{}", shader_version, shader_source));
+                gl.compile_shader(shader);
+                gl.attach_shader(program, shader);
+                shaders.push(shader);
+                if !gl.get_shader_compile_status(shader) {
+                    error = Some(ProgramError::Compile(gl.get_shader_info_log(shader)));
+                    break;
+                }
+            }
 
-            gl.link_program(program);
-            assert!(
-                gl.get_program_link_status(program),
-                "{}",
-                gl.get_program_info_log(program)
-            );
+            if error.is_none() {
+                gl.link_program(program);
+                if !gl.get_program_link_status(program) {
+                    error = Some(ProgramError::Link(gl.get_program_info_log(program)));
+                }
+            }
 
             for shader in shaders {
                 gl.detach_shader(program, shader);
                 gl.delete_shader(shader);
             }
+            if let Some(error) = error {
+                gl.delete_program(program);
+                return Err(error);
+            }
 
-            assert!(self.programs.insert(name.into(), program).is_none());
-
-            Some(program)
+            self.programs.insert(name.into(), program);
+            Ok(program)
         }
     }
 }
@@ -101,6 +508,8 @@ pub struct Texture {
     pub texture: glow::Texture,
     pub width: i32,
     pub height: i32,
+    /// Holds palette indices in the red channel instead of colors.
+    pub indexed: bool,
 }
 
 impl std::hash::Hash for Texture {
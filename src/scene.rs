@@ -0,0 +1,691 @@
+//! Saves and restores everything `RetroGraphics` draws: the screen size, the palettes, the
+//! textures and the layers in order.
+//!
+//! Scenes are JSON. Textures are embedded as base64 PNGs so a scene file reproduces exactly
+//! what was on screen, textures left out of [`Scene::textures`] have to be registered under
+//! the same name before restoring. Palettes and bitmap pixels are base64 RGBA bytes.
+
+use std::collections::BTreeMap;
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    animation::{SpriteClip, SpritePlayback},
+    bitmap::{BitmapContext, Color},
+    effect::{Effect, EffectContext},
+    map_file::{default_tile_size, JsonTile},
+    palette::{PALETTE_COUNT, PALETTE_SIZE},
+    resources::{ResourceManager, TextureHandle, TexturePixels},
+    sprites::{
+        Sprite, SpriteAffine, SpriteAttributes, SpriteFormat, SpriteLimits, SpriteMapContext,
+    },
+    tilemap::{Raster, Tile, TileAnimation, TileMap, TileMapContext},
+    Camera, Layer, OutOfRange, RetroGraphics, ScreenContext, Scroll,
+};
+
+pub const VERSION: u16 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct Scene {
+    pub version: u16,
+    pub screen: SceneScreen,
+    #[serde(default)]
+    pub camera: SceneCamera,
+    /// Every palette, `PALETTE_SIZE` colors each.
+    pub palettes: Vec<String>,
+    pub textures: Vec<SceneTexture>,
+    /// In the order of `RetroGraphics::layers`, the first one on top.
+    pub layers: Vec<SceneLayer>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SceneScreen {
+    pub width: i32,
+    pub height: i32,
+    pub zoom: f32,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct SceneCamera {
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SceneTexture {
+    pub name: String,
+    /// Whether the PNG is grayscale palette indices rather than colors.
+    #[serde(default)]
+    pub indexed: bool,
+    pub png: String,
+    #[serde(default)]
+    pub animations: Vec<TileAnimation>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum SceneLayer {
+    TileMap {
+        texture: String,
+        #[serde(flatten)]
+        scroll: SceneScroll,
+        tiles_x: u16,
+        tiles_y: u16,
+        #[serde(default = "default_tile_size")]
+        tile_width: u16,
+        #[serde(default = "default_tile_size")]
+        tile_height: u16,
+        tiles: Vec<JsonTile>,
+        #[serde(flatten)]
+        raster: Raster,
+        #[serde(default)]
+        animation_time: f64,
+    },
+    Sprite {
+        texture: String,
+        #[serde(flatten)]
+        scroll: SceneScroll,
+        sprites: Vec<JsonSprite>,
+        #[serde(default)]
+        affine: Vec<SpriteAffine>,
+        #[serde(default)]
+        clips: BTreeMap<String, SpriteClip>,
+        #[serde(default)]
+        playback: Vec<Option<SpritePlayback>>,
+        #[serde(default)]
+        limits: Option<SpriteLimits>,
+        #[serde(default)]
+        format: SpriteFormat,
+    },
+    Bitmap {
+        layer: u8,
+        width: i32,
+        height: i32,
+        pixels: String,
+    },
+    Effect {
+        effect: Effect,
+        /// Program name and fragment source of custom effects.
+        #[serde(default)]
+        shader: Option<(String, String)>,
+    },
+}
+
+/// The layer's [`Scroll`].
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct SceneScroll {
+    pub pan_x: i32,
+    pub pan_y: i32,
+    #[serde(default = "one")]
+    pub parallax_x: f32,
+    #[serde(default = "one")]
+    pub parallax_y: f32,
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+impl From<Scroll> for SceneScroll {
+    fn from(scroll: Scroll) -> Self {
+        Self {
+            pan_x: scroll.x,
+            pan_y: scroll.y,
+            parallax_x: scroll.parallax_x,
+            parallax_y: scroll.parallax_y,
+        }
+    }
+}
+
+impl From<SceneScroll> for Scroll {
+    fn from(scroll: SceneScroll) -> Self {
+        Self {
+            x: scroll.pan_x,
+            y: scroll.pan_y,
+            parallax_x: scroll.parallax_x,
+            parallax_y: scroll.parallax_y,
+        }
+    }
+}
+
+/// A [`Sprite`] with its attributes spelled out.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct JsonSprite {
+    pub x: i32,
+    pub y: i32,
+    pub tx: u16,
+    pub ty: u16,
+    pub layer: u8,
+    #[serde(default)]
+    pub flip_h: bool,
+    #[serde(default)]
+    pub flip_v: bool,
+    #[serde(default)]
+    pub rotation: u32,
+    /// Stored as (size / 8) - 1 like `SpriteAttributes::XSIZE`.
+    #[serde(default)]
+    pub x_size: u32,
+    #[serde(default)]
+    pub y_size: u32,
+    #[serde(default)]
+    pub palette: u8,
+    #[serde(default = "opaque")]
+    pub blend: u8,
+    #[serde(default)]
+    pub affine: bool,
+    #[serde(default)]
+    pub double_size: bool,
+    #[serde(default)]
+    pub affine_index: u32,
+}
+
+impl From<&Sprite> for JsonSprite {
+    fn from(sprite: &Sprite) -> Self {
+        let attributes = sprite.attribute;
+        Self {
+            x: sprite.x,
+            y: sprite.y,
+            tx: sprite.tx,
+            ty: sprite.ty,
+            layer: sprite.layer,
+            flip_h: attributes.get(SpriteAttributes::HORIZONTAL),
+            flip_v: attributes.get(SpriteAttributes::VERTICAL),
+            rotation: attributes.get(SpriteAttributes::ROTATION),
+            x_size: attributes.get(SpriteAttributes::XSIZE),
+            y_size: attributes.get(SpriteAttributes::YSIZE),
+            palette: sprite.palette,
+            blend: sprite.blend,
+            affine: attributes.get(SpriteAttributes::AFFINE),
+            double_size: attributes.get(SpriteAttributes::DOUBLE_SIZE),
+            affine_index: attributes.get(SpriteAttributes::AFFINE_INDEX),
+        }
+    }
+}
+
+impl TryFrom<JsonSprite> for Sprite {
+    type Error = OutOfRange;
+
+    fn try_from(json: JsonSprite) -> Result<Self, OutOfRange> {
+        let field = |value, max, field| OutOfRange::check(value, max, field);
+        let rotation = field(
+            json.rotation,
+            SpriteAttributes::ROTATION.max_value(),
+            "rotation",
+        )?;
+        let x_size = field(json.x_size, SpriteAttributes::XSIZE.max_value(), "x_size")?;
+        let y_size = field(json.y_size, SpriteAttributes::YSIZE.max_value(), "y_size")?;
+        let affine_index = field(
+            json.affine_index,
+            SpriteAttributes::AFFINE_INDEX.max_value(),
+            "affine_index",
+        )?;
+        let mut sprite = Sprite::default();
+        sprite.x = json.x;
+        sprite.y = json.y;
+        sprite.tx = json.tx;
+        sprite.ty = json.ty;
+        sprite.layer = json.layer;
+        sprite.palette = json.palette;
+        sprite.blend = json.blend;
+        sprite
+            .attribute
+            .set(SpriteAttributes::HORIZONTAL, json.flip_h)
+            .set(SpriteAttributes::VERTICAL, json.flip_v)
+            .set(SpriteAttributes::ROTATION, rotation)
+            .set(SpriteAttributes::XSIZE, x_size)
+            .set(SpriteAttributes::YSIZE, y_size)
+            .set(SpriteAttributes::AFFINE, json.affine)
+            .set(SpriteAttributes::DOUBLE_SIZE, json.double_size)
+            .set(SpriteAttributes::AFFINE_INDEX, affine_index);
+        Ok(sprite)
+    }
+}
+
+fn opaque() -> u8 {
+    255
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Json(serde_json::Error),
+    UnsupportedVersion(u16),
+    Image(image::ImageError),
+    /// Base64 that doesn't decode or pixels that don't match their size.
+    BadData(String),
+    /// A layer uses a texture that's neither embedded nor registered.
+    MissingTexture(String),
+    /// A field of tile `index` in layer `layer` is too big for its bits.
+    BadTile {
+        layer: usize,
+        index: usize,
+        field: &'static str,
+    },
+    /// Like `BadTile` for a sprite.
+    BadSprite {
+        layer: usize,
+        index: usize,
+        field: &'static str,
+    },
+    /// Creating a layer failed, usually because its shaders didn't compile.
+    Layer,
+}
+
+impl std::fmt::Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneError::Json(err) => write!(f, "{err}"),
+            SceneError::UnsupportedVersion(version) => {
+                write!(f, "unsupported scene version {version}")
+            }
+            SceneError::Image(err) => write!(f, "{err}"),
+            SceneError::BadData(what) => write!(f, "bad data: {what}"),
+            SceneError::MissingTexture(name) => write!(f, "missing texture {name}"),
+            SceneError::BadTile {
+                layer,
+                index,
+                field,
+            } => write!(
+                f,
+                "{field} of tile {index} in layer {layer} is out of range"
+            ),
+            SceneError::BadSprite {
+                layer,
+                index,
+                field,
+            } => write!(
+                f,
+                "{field} of sprite {index} in layer {layer} is out of range"
+            ),
+            SceneError::Layer => write!(f, "failed to create a layer"),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<serde_json::Error> for SceneError {
+    fn from(err: serde_json::Error) -> Self {
+        SceneError::Json(err)
+    }
+}
+
+impl From<image::ImageError> for SceneError {
+    fn from(err: image::ImageError) -> Self {
+        SceneError::Image(err)
+    }
+}
+
+impl Scene {
+    /// Everything `graphics` draws, with every registered texture embedded.
+    pub fn capture(graphics: &RetroGraphics) -> Result<Self, SceneError> {
+        let resources = &graphics.resources;
+        let texture_name = |handle: TextureHandle| {
+            resources
+                .texture_name(handle)
+                .unwrap_or_default()
+                .to_owned()
+        };
+
+        let mut textures = Vec::new();
+        for (handle, name) in resources.textures() {
+            let Some(pixels) = resources.texture_pixels(handle) else {
+                continue;
+            };
+            let mut png = std::io::Cursor::new(Vec::new());
+            match pixels {
+                TexturePixels::Rgba(image) => image.write_to(&mut png, image::ImageFormat::Png)?,
+                TexturePixels::Indexed(indices) => {
+                    indices.write_to(&mut png, image::ImageFormat::Png)?
+                }
+            }
+            textures.push(SceneTexture {
+                name: name.to_owned(),
+                indexed: matches!(pixels, TexturePixels::Indexed(_)),
+                png: encode(png.get_ref()),
+                animations: resources.tile_animations(handle).to_vec(),
+            });
+        }
+
+        let layers = graphics
+            .layers
+            .iter()
+            .map(|layer| match layer {
+                Layer::TileMap(tilemap) => SceneLayer::TileMap {
+                    texture: texture_name(tilemap.texture),
+                    scroll: tilemap.scroll.into(),
+                    tiles_x: tilemap.map.tiles_x,
+                    tiles_y: tilemap.map.tiles_y,
+                    tile_width: tilemap.map.tile_width,
+                    tile_height: tilemap.map.tile_height,
+                    tiles: tilemap.map.tiles.iter().map(JsonTile::from).collect(),
+                    raster: tilemap.raster.clone(),
+                    animation_time: tilemap.animation_time,
+                },
+                Layer::Sprite(sprites) => SceneLayer::Sprite {
+                    texture: texture_name(sprites.texture),
+                    scroll: sprites.scroll.into(),
+                    sprites: sprites.thing.iter().map(JsonSprite::from).collect(),
+                    affine: sprites.affine.clone(),
+                    clips: sprites.clips.clone(),
+                    playback: sprites.playback.clone(),
+                    limits: sprites.limits,
+                    format: sprites.format,
+                },
+                Layer::Bitmap(bitmap) => SceneLayer::Bitmap {
+                    layer: bitmap.layer,
+                    width: bitmap.bitmap.width(),
+                    height: bitmap.bitmap.height(),
+                    pixels: encode(bitmap.bitmap.pixels().as_flattened()),
+                },
+                Layer::Effect(effect) => SceneLayer::Effect {
+                    effect: effect.effect,
+                    shader: effect
+                        .custom_shader()
+                        .map(|(name, source)| (name.to_owned(), source.to_owned())),
+                },
+            })
+            .collect();
+
+        Ok(Self {
+            version: VERSION,
+            screen: SceneScreen {
+                width: graphics.screen.screen_px_x,
+                height: graphics.screen.screen_px_y,
+                zoom: graphics.screen.zoom,
+            },
+            camera: SceneCamera {
+                x: graphics.camera.x,
+                y: graphics.camera.y,
+            },
+            palettes: resources
+                .palettes()
+                .iter()
+                .map(|palette| encode(palette.as_flattened()))
+                .collect(),
+            textures,
+            layers,
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Scenes always serialize")
+    }
+
+    pub fn from_json(json: &[u8]) -> Result<Self, SceneError> {
+        let scene: Scene = serde_json::from_slice(json)?;
+        if scene.version != VERSION {
+            return Err(SceneError::UnsupportedVersion(scene.version));
+        }
+        scene.check_fields()?;
+        Ok(scene)
+    }
+
+    /// Whether every tile and sprite fits the bits its attributes are packed into and every
+    /// bitmap fits on the screen.
+    fn check_fields(&self) -> Result<(), SceneError> {
+        for (layer, scene_layer) in self.layers.iter().enumerate() {
+            match scene_layer {
+                SceneLayer::TileMap { tiles, .. } => {
+                    for (index, &tile) in tiles.iter().enumerate() {
+                        Tile::try_from(tile).map_err(|OutOfRange(field)| SceneError::BadTile {
+                            layer,
+                            index,
+                            field,
+                        })?;
+                    }
+                }
+                SceneLayer::Sprite { sprites, .. } => {
+                    for (index, &sprite) in sprites.iter().enumerate() {
+                        Sprite::try_from(sprite).map_err(|OutOfRange(field)| {
+                            SceneError::BadSprite {
+                                layer,
+                                index,
+                                field,
+                            }
+                        })?;
+                    }
+                }
+                // they're resized to the screen anyway, bigger ones only run out of memory
+                SceneLayer::Bitmap { width, height, .. }
+                    if *width > self.screen.width || *height > self.screen.height =>
+                {
+                    return Err(SceneError::BadData(format!(
+                        "{width}x{height} bitmap on a {}x{} screen",
+                        self.screen.width, self.screen.height
+                    )));
+                }
+                SceneLayer::Bitmap { .. } | SceneLayer::Effect { .. } => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the screen, palettes and layers of `graphics` with the scene's and registers
+    /// its textures, replacing textures with the same names. `graphics` is left untouched
+    /// if the scene can't be decoded.
+    pub fn restore(
+        &self,
+        gl: &glow::Context,
+        graphics: &mut RetroGraphics,
+    ) -> Result<(), SceneError> {
+        let mut palettes = Vec::with_capacity(self.palettes.len());
+        for palette in self.palettes.iter().take(PALETTE_COUNT) {
+            palettes.push(colors(&decode(palette)?, PALETTE_SIZE)?);
+        }
+        let mut textures = Vec::with_capacity(self.textures.len());
+        for texture in &self.textures {
+            let image = image::load_from_memory(&decode(&texture.png)?)?;
+            let pixels = match texture.indexed {
+                true => TexturePixels::Indexed(image.to_luma8()),
+                false => TexturePixels::Rgba(image.to_rgba8()),
+            };
+            textures.push((texture.name.as_str(), pixels, &texture.animations));
+        }
+        self.check_fields()?;
+        let mut bitmaps = Vec::new();
+        for layer in &self.layers {
+            match layer {
+                SceneLayer::TileMap {
+                    texture,
+                    tiles_x,
+                    tiles_y,
+                    tiles,
+                    ..
+                } => {
+                    missing_texture(texture, &self.textures, &graphics.resources)?;
+                    if tiles.len() != *tiles_x as usize * *tiles_y as usize {
+                        return Err(SceneError::BadData(format!(
+                            "{} tiles instead of {tiles_x}x{tiles_y}",
+                            tiles.len()
+                        )));
+                    }
+                }
+                SceneLayer::Sprite { texture, .. } => {
+                    missing_texture(texture, &self.textures, &graphics.resources)?
+                }
+                SceneLayer::Bitmap {
+                    width,
+                    height,
+                    pixels,
+                    ..
+                } => {
+                    let count = ((*width).max(0) as usize)
+                        .checked_mul((*height).max(0) as usize)
+                        .ok_or_else(|| {
+                            SceneError::BadData(format!("{width}x{height} bitmap is too big"))
+                        })?;
+                    bitmaps.push(colors(&decode(pixels)?, count)?);
+                }
+                SceneLayer::Effect { .. } => {}
+            }
+        }
+
+        for (name, pixels, animations) in textures {
+            let handle = match pixels {
+                TexturePixels::Rgba(image) => graphics.resources.create_texture(gl, name, image),
+                TexturePixels::Indexed(indices) => {
+                    graphics.resources.create_indexed_texture(gl, name, indices)
+                }
+            };
+            graphics
+                .resources
+                .set_tile_animations(handle, animations.clone());
+        }
+        for (index, palette) in palettes.iter().enumerate() {
+            graphics.resources.set_palette(index, palette);
+        }
+
+        let mut layers = Vec::with_capacity(self.layers.len());
+        let mut bitmaps = bitmaps.into_iter();
+        for layer in &self.layers {
+            match create_layer(gl, &mut graphics.resources, layer, &mut bitmaps) {
+                Some(layer) => layers.push(layer),
+                None => {
+                    for mut layer in layers {
+                        unsafe { layer.destroy(gl) };
+                    }
+                    return Err(SceneError::Layer);
+                }
+            }
+        }
+
+        for mut layer in std::mem::replace(&mut graphics.layers, layers) {
+            unsafe { layer.destroy(gl) };
+        }
+        graphics.screen = ScreenContext {
+            screen_px_x: self.screen.width,
+            screen_px_y: self.screen.height,
+            zoom: self.screen.zoom,
+        };
+        graphics.camera = Camera {
+            x: self.camera.x,
+            y: self.camera.y,
+        };
+        graphics.apply_scroll();
+        Ok(())
+    }
+}
+
+fn create_layer(
+    gl: &glow::Context,
+    resources: &mut ResourceManager,
+    layer: &SceneLayer,
+    bitmaps: &mut impl Iterator<Item = Vec<Color>>,
+) -> Option<Layer> {
+    Some(match layer {
+        SceneLayer::TileMap {
+            texture,
+            scroll,
+            tiles_x,
+            tiles_y,
+            tile_width,
+            tile_height,
+            tiles,
+            raster,
+            animation_time,
+        } => {
+            let texture = resources.texture_handle(texture)?;
+            let mut tilemap = TileMapContext::new(gl, resources, texture)?;
+            tilemap.map = TileMap {
+                tiles_x: *tiles_x,
+                tiles_y: *tiles_y,
+                tile_width: *tile_width,
+                tile_height: *tile_height,
+                // restore checked every tile already
+                tiles: tiles
+                    .iter()
+                    .copied()
+                    .map(Tile::try_from)
+                    .collect::<Result<_, _>>()
+                    .ok()?,
+                ..Default::default()
+            };
+            tilemap.scroll = (*scroll).into();
+            tilemap.raster = raster.clone();
+            tilemap.animation_time = *animation_time;
+            Layer::TileMap(tilemap)
+        }
+        SceneLayer::Sprite {
+            texture,
+            scroll,
+            sprites,
+            affine,
+            clips,
+            playback,
+            limits,
+            format,
+        } => {
+            let texture = resources.texture_handle(texture)?;
+            let mut context = SpriteMapContext::new(gl, resources, texture)?;
+            context.scroll = (*scroll).into();
+            // restore checked every sprite already
+            context.thing = sprites
+                .iter()
+                .copied()
+                .map(Sprite::try_from)
+                .collect::<Result<_, _>>()
+                .ok()?;
+            context.affine = affine.clone();
+            context.clips = clips.clone();
+            context.playback = playback.clone();
+            context.limits = *limits;
+            context.format = *format;
+            Layer::Sprite(context)
+        }
+        SceneLayer::Bitmap {
+            layer,
+            width,
+            height,
+            ..
+        } => {
+            let mut bitmap = BitmapContext::new(gl, resources, *width, *height)?;
+            bitmap.layer = *layer;
+            bitmap.bitmap.blit(0, 0, *width, *height, &bitmaps.next()?);
+            Layer::Bitmap(bitmap)
+        }
+        SceneLayer::Effect { effect, shader } => Layer::Effect(match (effect, shader) {
+            (Effect::Custom { params }, Some((name, source))) => {
+                EffectContext::custom(gl, resources, name, source, *params).ok()?
+            }
+            _ => EffectContext::new(gl, resources, *effect)?,
+        }),
+    })
+}
+
+fn missing_texture(
+    name: &str,
+    textures: &[SceneTexture],
+    resources: &ResourceManager,
+) -> Result<(), SceneError> {
+    if textures.iter().any(|texture| texture.name == name)
+        || resources.texture_handle(name).is_some()
+    {
+        Ok(())
+    } else {
+        Err(SceneError::MissingTexture(name.to_owned()))
+    }
+}
+
+fn encode(bytes: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(bytes)
+}
+
+fn decode(text: &str) -> Result<Vec<u8>, SceneError> {
+    base64::engine::general_purpose::STANDARD
+        .decode(text)
+        .map_err(|err| SceneError::BadData(err.to_string()))
+}
+
+fn colors(bytes: &[u8], count: usize) -> Result<Vec<Color>, SceneError> {
+    if count.checked_mul(4) != Some(bytes.len()) {
+        return Err(SceneError::BadData(format!(
+            "{} bytes for {count} colors",
+            bytes.len()
+        )));
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|color| [color[0], color[1], color[2], color[3]])
+        .collect())
+}
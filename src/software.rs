@@ -0,0 +1,472 @@
+//! A CPU reference for what the tilemap, sprite, bitmap and effect shaders draw, so the
+//! pipeline can be checked without a GPU.
+//!
+//! Images are produced the same way `RetroGraphics` composites its layers offscreen:
+//! at native resolution (zoom is ignored), starting from transparent black, with every layer
+//! blended over the ones beneath it and colors kept premultiplied until the end, so they
+//! can be compared with `RetroGraphics::render_to_image`.
+
+use std::sync::OnceLock;
+
+use image::{Rgba, RgbaImage};
+
+use crate::{
+    bitmap::Color,
+    effect::Effect,
+    palette::{self, Palette},
+    resources::{ResourceManager, TexturePixels},
+    sprites::{Sprite, SpriteAffine, SpriteAttributes, SpriteLines},
+    tilemap::{EdgeMode, Raster, TileAttributes, TileMap},
+    Layer, ScreenContext,
+};
+
+pub enum SoftwareLayer<'a> {
+    TileMap {
+        map: &'a TileMap,
+        raster: &'a Raster,
+        sheet: &'a TexturePixels,
+    },
+    Sprite {
+        sprites: &'a [Sprite],
+        affine: &'a [SpriteAffine],
+        /// Lines dropped by `SpriteMapContext::limits`, every sprite is drawn when it's `None`.
+        lines: Option<&'a SpriteLines>,
+        pan_x: i32,
+        pan_y: i32,
+        sheet: &'a TexturePixels,
+    },
+    Bitmap {
+        layer: u8,
+        width: i32,
+        height: i32,
+        pixels: &'a [Color],
+    },
+    /// Custom effects can't run on the CPU and pass the layers beneath through unchanged.
+    /// Tint, fade and scanlines scale colors so drivers may round them one step differently.
+    Effect(Effect),
+}
+
+/// Renders `layers` in the same order as `RetroGraphics::layers`, the first one on top.
+/// Indexed sheets look their colors up in `palettes`.
+pub fn render(screen: &ScreenContext, palettes: &[Palette], layers: &[SoftwareLayer]) -> RgbaImage {
+    let mut target = RgbaImage::new(
+        screen.screen_px_x.max(0) as u32,
+        screen.screen_px_y.max(0) as u32,
+    );
+    let mut group = Vec::new();
+    for layer in layers.iter().rev() {
+        match layer {
+            SoftwareLayer::Effect(effect) => {
+                render_group(&mut target, palettes, &group);
+                group.clear();
+                apply_effect(&mut target, effect);
+            }
+            layer => group.push(layer),
+        }
+    }
+    render_group(&mut target, palettes, &group);
+    unpremultiply(&mut target);
+    target
+}
+
+// same ordering as RetroGraphics::composite, `group` is in painting order
+fn render_group(target: &mut RgbaImage, palettes: &[Palette], group: &[&SoftwareLayer]) {
+    let mut priorities = [false; 256];
+    for layer in group {
+        match layer {
+            SoftwareLayer::TileMap { map, raster, .. } => {
+                for tile in &map.tiles {
+                    priorities[tile.layer as usize] = true;
+                }
+                if let EdgeMode::Fill(tile) = raster.edge {
+                    priorities[tile.layer as usize] = true;
+                }
+            }
+            SoftwareLayer::Sprite { sprites, .. } => {
+                for sprite in sprites.iter() {
+                    priorities[sprite.layer as usize] = true;
+                }
+            }
+            SoftwareLayer::Bitmap { layer, .. } => priorities[*layer as usize] = true,
+            SoftwareLayer::Effect(_) => {}
+        }
+    }
+
+    for priority in (0..=255u8).rev() {
+        if !priorities[priority as usize] {
+            continue;
+        }
+        for layer in group {
+            match layer {
+                SoftwareLayer::TileMap { map, raster, sheet } => {
+                    render_tilemap(target, map, raster, sheet, palettes, priority)
+                }
+                SoftwareLayer::Sprite {
+                    sprites,
+                    affine,
+                    lines,
+                    pan_x,
+                    pan_y,
+                    sheet,
+                } => render_sprites(
+                    target, sprites, affine, *lines, *pan_x, *pan_y, sheet, palettes, priority,
+                ),
+                SoftwareLayer::Bitmap {
+                    layer,
+                    width,
+                    height,
+                    pixels,
+                } if *layer == priority => render_bitmap(target, *width, *height, pixels),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Renders the CPU equivalent of `RetroGraphics::render_to_image` for `layers`, layers whose
+/// texture is missing from `resources` are skipped like they are on the GPU.
+pub fn render_layers(
+    screen: &ScreenContext,
+    layers: &[Layer],
+    resources: &ResourceManager,
+) -> RgbaImage {
+    // tilemaps at the frame their animated tiles are at
+    let animated: Vec<_> = layers
+        .iter()
+        .map(|layer| match layer {
+            Layer::TileMap(tilemap) => {
+                let frames = tilemap.animation_frames(resources);
+                let mut map = tilemap.map.clone();
+                for tile in &mut map.tiles {
+                    *tile = frames.apply(*tile);
+                }
+                let mut raster = tilemap.raster.clone();
+                if let EdgeMode::Fill(tile) = &mut raster.edge {
+                    *tile = frames.apply(*tile);
+                }
+                Some((map, raster))
+            }
+            _ => None,
+        })
+        .collect();
+    // sprites as their format leaves them and the lines their limits leave them on
+    let sprites: Vec<_> = layers
+        .iter()
+        .map(|layer| match layer {
+            Layer::Sprite(sprites) => {
+                Some((sprites.drawn(), sprites.sprite_lines(screen.screen_px_y)))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let layers: Vec<_> = layers
+        .iter()
+        .zip(animated.iter().zip(&sprites))
+        .filter_map(|(layer, (animated, drawn))| {
+            Some(match layer {
+                Layer::TileMap(tilemap) => {
+                    let (map, raster) = animated.as_ref()?;
+                    SoftwareLayer::TileMap {
+                        map,
+                        raster,
+                        sheet: resources.texture_pixels(tilemap.texture)?,
+                    }
+                }
+                Layer::Sprite(sprites) => {
+                    let (drawn, lines) = drawn.as_ref()?;
+                    SoftwareLayer::Sprite {
+                        sprites: drawn,
+                        affine: &sprites.affine,
+                        lines: lines.as_ref(),
+                        pan_x: sprites.pan_x,
+                        pan_y: sprites.pan_y,
+                        sheet: resources.texture_pixels(sprites.texture)?,
+                    }
+                }
+                Layer::Bitmap(bitmap) => SoftwareLayer::Bitmap {
+                    layer: bitmap.layer,
+                    width: bitmap.bitmap.width(),
+                    height: bitmap.bitmap.height(),
+                    pixels: bitmap.bitmap.pixels(),
+                },
+                Layer::Effect(effect) => SoftwareLayer::Effect(effect.effect),
+            })
+        })
+        .collect();
+    render(screen, resources.palettes(), &layers)
+}
+
+/// Converts a composited image to straight alpha.
+pub fn unpremultiply(image: &mut RgbaImage) {
+    for pixel in image.pixels_mut() {
+        let [r, g, b, a] = pixel.0;
+        if a == 0 || a == 255 {
+            continue;
+        }
+        let scale = |c: u8| ((c as u32 * 255 + a as u32 / 2) / a as u32).min(255) as u8;
+        pixel.0 = [scale(r), scale(g), scale(b), a];
+    }
+}
+
+/// Draws the tiles whose layer is `priority` with the effects of `raster`.
+pub fn render_tilemap(
+    target: &mut RgbaImage,
+    map: &TileMap,
+    raster: &Raster,
+    sheet: &TexturePixels,
+    palettes: &[Palette],
+    priority: u8,
+) {
+    let (tile_width, tile_height) = map.tile_size();
+    for sy in 0..target.height() as i32 {
+        let (line_x, line_y) = raster
+            .scanlines
+            .get(sy as usize)
+            .copied()
+            .unwrap_or_default();
+        let affine = raster.affine(sy);
+        for sx in 0..target.width() as i32 {
+            let (mut mx, mut my) = (sx + map.pan_x + line_x, sy + map.pan_y + line_y);
+            if let Some(affine) = affine {
+                (mx, my) = affine.apply(mx, my);
+            }
+
+            let (tx, ty) = (mx.div_euclid(tile_width), my.div_euclid(tile_height));
+            let Some(tile) = map.tile_at(tx, ty, raster.edge) else {
+                continue;
+            };
+            if tile.layer != priority || tile.attributes.get(TileAttributes::HIDDEN) {
+                continue;
+            }
+            let (u, v) = texel(
+                mx.rem_euclid(tile_width),
+                my.rem_euclid(tile_height),
+                tile_width,
+                tile_height,
+                tile.attributes.get(TileAttributes::HORIZONTAL),
+                tile.attributes.get(TileAttributes::VERTICAL),
+                tile.attributes.get(TileAttributes::ROTATION) as i32,
+            );
+
+            let palette = palette(
+                palettes,
+                tile.attributes.get(TileAttributes::PALETTE) as usize,
+            );
+            let color = sample(
+                sheet,
+                palette,
+                tile.x as i32 * tile_width + u,
+                tile.y as i32 * tile_height + v,
+            );
+            blend(target, sx, sy, color);
+        }
+    }
+}
+
+/// Draws the sprites whose layer is `priority`, affine sprites pick from `affine`.
+#[allow(clippy::too_many_arguments)]
+pub fn render_sprites(
+    target: &mut RgbaImage,
+    sprites: &[Sprite],
+    affine: &[SpriteAffine],
+    lines: Option<&SpriteLines>,
+    pan_x: i32,
+    pan_y: i32,
+    sheet: &TexturePixels,
+    palettes: &[Palette],
+    priority: u8,
+) {
+    // instances are drawn in order, later sprites end up on top
+    for (index, sprite) in sprites
+        .iter()
+        .enumerate()
+        .filter(|(_, sprite)| sprite.layer == priority)
+    {
+        let attributes = sprite.attribute;
+        let rotate = attributes.get(SpriteAttributes::ROTATION) as i32;
+        let palette = palette(palettes, sprite.palette as usize);
+        let (width, height) = sprite.size();
+        let (bounds_w, bounds_h) = sprite.bounds();
+        let matrix = sprite.affine(affine).map(|affine| affine.matrix());
+
+        let left = sprite.x - pan_x;
+        let top = sprite.y - pan_y;
+        for by in 0..bounds_h {
+            for bx in 0..bounds_w {
+                let (sx, sy) = (left + bx, top + by);
+                if sx < 0 || sy < 0 || sx >= target.width() as i32 || sy >= target.height() as i32 {
+                    continue;
+                }
+                if lines.is_some_and(|lines| !lines.is_drawn(index, sy)) {
+                    continue;
+                }
+
+                // affine sprites sample the middle of each pixel relative to their center
+                let (lx, ly) = match matrix {
+                    Some([a, b, c, d]) => {
+                        let x = bx as f32 + 0.5 - bounds_w as f32 * 0.5;
+                        let y = by as f32 + 0.5 - bounds_h as f32 * 0.5;
+                        (
+                            (a * x + b * y + width as f32 * 0.5).floor() as i32,
+                            (c * x + d * y + height as f32 * 0.5).floor() as i32,
+                        )
+                    }
+                    None => (bx, by),
+                };
+                if lx < 0 || ly < 0 || lx >= width || ly >= height {
+                    continue;
+                }
+
+                let (u, v) = texel(
+                    lx,
+                    ly,
+                    width,
+                    height,
+                    attributes.get(SpriteAttributes::HORIZONTAL),
+                    attributes.get(SpriteAttributes::VERTICAL),
+                    rotate,
+                );
+                let mut color = sample(
+                    sheet,
+                    palette,
+                    sprite.tx as i32 * 8 + u,
+                    sprite.ty as i32 * 8 + v,
+                );
+                color[3] = ((color[3] as u32 * sprite.blend as u32 + 127) / 255) as u8;
+                blend(target, sx, sy, color);
+            }
+        }
+    }
+}
+
+pub fn render_bitmap(target: &mut RgbaImage, width: i32, height: i32, pixels: &[Color]) {
+    for y in 0..height.min(target.height() as i32) {
+        for x in 0..width.min(target.width() as i32) {
+            blend(target, x, y, pixels[(x + y * width) as usize]);
+        }
+    }
+}
+
+pub fn apply_effect(target: &mut RgbaImage, effect: &Effect) {
+    let source = target.clone();
+    let (width, height) = (target.width() as i32, target.height() as i32);
+
+    for y in 0..height {
+        for x in 0..width {
+            let (mut fx, mut fy) = (x, y);
+            if let Effect::Mosaic { size } = *effect {
+                let block = (size as i32).max(1);
+                fx = x / block * block;
+                fy = y / block * block;
+            }
+
+            let [r, g, b, a] = source.get_pixel(fx as u32, fy as u32).0.map(unorm);
+            let color = match *effect {
+                Effect::Tint { color } => {
+                    let t = color[3];
+                    [
+                        mix(r, color[0] * a, t),
+                        mix(g, color[1] * a, t),
+                        mix(b, color[2] * a, t),
+                        a,
+                    ]
+                }
+                Effect::Fade { amount } => {
+                    let t = amount.clamp(0.0, 1.0);
+                    [
+                        mix(r, 0.0, t),
+                        mix(g, 0.0, t),
+                        mix(b, 0.0, t),
+                        mix(a, 1.0, t),
+                    ]
+                }
+                Effect::Scanlines { intensity } if y % 2 == 1 => {
+                    let scale = 1.0 - intensity.clamp(0.0, 1.0);
+                    [r * scale, g * scale, b * scale, a]
+                }
+                _ => [r, g, b, a],
+            };
+
+            target.put_pixel(x as u32, y as u32, Rgba(color.map(to_unorm)));
+        }
+    }
+}
+
+/// Maps a pixel inside the on screen `width` x `height` quad back to the texel it shows,
+/// relative to the top left of the quad's area in the sheet.
+///
+/// The shaders rotate the sheet area clockwise `rotate` times and then mirror the result.
+fn texel(
+    mut x: i32,
+    mut y: i32,
+    mut width: i32,
+    mut height: i32,
+    flip_h: bool,
+    flip_v: bool,
+    rotate: i32,
+) -> (i32, i32) {
+    if flip_h {
+        x = width - 1 - x;
+    }
+    if flip_v {
+        y = height - 1 - y;
+    }
+    for _ in 0..rotate {
+        (x, y) = (y, width - 1 - x);
+        (width, height) = (height, width);
+    }
+    (x, y)
+}
+
+/// Like the shaders, indices wrap around the palettes there are. Without any the default
+/// palette is used.
+fn palette(palettes: &[Palette], index: usize) -> &Palette {
+    static DEFAULT: OnceLock<Palette> = OnceLock::new();
+    match palettes.len() {
+        0 => DEFAULT.get_or_init(palette::default_palette),
+        len => &palettes[index % len],
+    }
+}
+
+// textures repeat
+fn sample(sheet: &TexturePixels, palette: &Palette, x: i32, y: i32) -> Color {
+    if sheet.width() == 0 || sheet.height() == 0 {
+        return [0; 4];
+    }
+    let x = x.rem_euclid(sheet.width() as i32) as u32;
+    let y = y.rem_euclid(sheet.height() as i32) as u32;
+    match sheet {
+        TexturePixels::Rgba(image) => image.get_pixel(x, y).0,
+        TexturePixels::Indexed(indices) => palette[indices.get_pixel(x, y).0[0] as usize],
+    }
+}
+
+// glBlendFuncSeparate(SRC_ALPHA, ONE_MINUS_SRC_ALPHA, ONE, ONE_MINUS_SRC_ALPHA), each product
+// is rounded to the 8 bit framebuffer before they're added like blend units do
+fn blend(target: &mut RgbaImage, x: i32, y: i32, color: Color) {
+    let pixel = target.get_pixel_mut(x as u32, y as u32);
+    let src = color.map(unorm);
+    let dst = pixel.0.map(unorm);
+    let a = src[3];
+    let term = |value: f32| to_unorm(value) as u32;
+    let add = |src: f32, dst: f32| (term(src) + term(dst * (1.0 - a))).min(255) as u8;
+    pixel.0 = [
+        add(src[0] * a, dst[0]),
+        add(src[1] * a, dst[1]),
+        add(src[2] * a, dst[2]),
+        add(a, dst[3]),
+    ];
+}
+
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    a * (1.0 - t) + b * t
+}
+
+fn unorm(value: u8) -> f32 {
+    value as f32 / 255.0
+}
+
+fn to_unorm(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
@@ -0,0 +1,216 @@
+use std::f32::consts::PI;
+
+use egui::{Color32, DragValue, Rect, Response, Slider, Stroke, Widget};
+use graphics_test::{
+    palette::PALETTE_COUNT,
+    sprites::{Sprite, SpriteAffine, SpriteAttributes, SpriteMapContext, AFFINE_COUNT},
+    ScreenContext,
+};
+
+use crate::tile_editor::{canvas_position, screen_pixel};
+
+/// Selects, moves and edits the sprites of one `Layer::Sprite`.
+#[derive(Default)]
+pub struct SpriteEditor {
+    /// Index into `RetroGraphics::layers` of the sprites being edited.
+    pub layer: Option<usize>,
+    /// Index into `SpriteMapContext::thing`.
+    pub selected: Option<usize>,
+    // where the pointer grabbed the dragged sprite, relative to its top left corner
+    grab: Option<(i32, i32)>,
+}
+
+impl SpriteEditor {
+    /// The sprite list and every field of the selected sprite.
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        sprites: &mut SpriteMapContext,
+        screen: &ScreenContext,
+    ) {
+        ui.horizontal(|ui| {
+            if ui.button("Add").clicked() {
+                let mut sprite = match self.selected.and_then(|index| sprites.thing.get(index)) {
+                    Some(selected) => *selected,
+                    None => {
+                        let mut sprite = Sprite::default();
+                        sprite.layer = 2;
+                        sprite
+                    }
+                };
+                // in the middle of the screen
+                sprite.x = sprites.pan_x + screen.screen_px_x / 2;
+                sprite.y = sprites.pan_y + screen.screen_px_y / 2;
+                sprites.thing.push(sprite);
+                self.selected = Some(sprites.thing.len() - 1);
+            }
+            if ui
+                .add_enabled(self.selected.is_some(), egui::Button::new("Delete"))
+                .clicked()
+            {
+                if let Some(index) = self.selected.take() {
+                    if index < sprites.thing.len() {
+                        sprites.thing.remove(index);
+                        if index < sprites.playback.len() {
+                            sprites.playback.remove(index);
+                        }
+                    }
+                }
+            }
+        });
+
+        egui::ScrollArea::vertical()
+            .id_source("sprite list")
+            .max_height(160.0)
+            .show(ui, |ui| {
+                for (index, sprite) in sprites.thing.iter().enumerate() {
+                    let text = format!(
+                        "{index}: {}, {} tile {}, {} layer {}",
+                        sprite.x, sprite.y, sprite.tx, sprite.ty, sprite.layer
+                    );
+                    if ui
+                        .selectable_label(self.selected == Some(index), text)
+                        .clicked()
+                    {
+                        self.selected = Some(index);
+                    }
+                }
+            });
+
+        let Some(sprite) = self.selected.and_then(|index| sprites.thing.get_mut(index)) else {
+            return;
+        };
+
+        ui.horizontal(|ui| {
+            ui.label("x");
+            DragValue::new(&mut sprite.x).ui(ui);
+            ui.label("y");
+            DragValue::new(&mut sprite.y).ui(ui);
+        });
+        Slider::new(&mut sprite.tx, 0..=255).text(" uv x").ui(ui);
+        Slider::new(&mut sprite.ty, 0..=255).text(" uv y").ui(ui);
+        Slider::new(&mut sprite.layer, 0..=255)
+            .text(" layer")
+            .ui(ui);
+        Slider::new(&mut sprite.palette, 0..=PALETTE_COUNT as u8 - 1)
+            .text(" palette")
+            .ui(ui);
+        Slider::new(&mut sprite.blend, 0..=255)
+            .text(" opacity")
+            .ui(ui);
+
+        let attributes = &mut sprite.attribute;
+        let mut flip_h = attributes.get(SpriteAttributes::HORIZONTAL);
+        let mut flip_v = attributes.get(SpriteAttributes::VERTICAL);
+        let mut rotation = attributes.get(SpriteAttributes::ROTATION);
+        let mut x_size = attributes.get(SpriteAttributes::XSIZE);
+        let mut y_size = attributes.get(SpriteAttributes::YSIZE);
+        let mut affine = attributes.get(SpriteAttributes::AFFINE);
+        let mut double_size = attributes.get(SpriteAttributes::DOUBLE_SIZE);
+        let mut affine_index = attributes.get(SpriteAttributes::AFFINE_INDEX);
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut flip_h, "Flip H");
+            ui.checkbox(&mut flip_v, "Flip V");
+        });
+        Slider::new(&mut rotation, 0..=3).text(" rotation").ui(ui);
+        // stored as (size / 8) - 1
+        Slider::new(&mut x_size, 0..=3)
+            .text(" size x")
+            .custom_formatter(|value, _| format!("{}", (value as u32 + 1) * 8))
+            .ui(ui);
+        Slider::new(&mut y_size, 0..=3)
+            .text(" size y")
+            .custom_formatter(|value, _| format!("{}", (value as u32 + 1) * 8))
+            .ui(ui);
+        attributes.set(SpriteAttributes::HORIZONTAL, flip_h);
+        attributes.set(SpriteAttributes::VERTICAL, flip_v);
+        attributes.set(SpriteAttributes::ROTATION, rotation);
+        attributes.set(SpriteAttributes::XSIZE, x_size);
+        attributes.set(SpriteAttributes::YSIZE, y_size);
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut affine, "Affine");
+            ui.checkbox(&mut double_size, "Double size");
+        });
+        attributes.set(SpriteAttributes::AFFINE, affine);
+        attributes.set(SpriteAttributes::DOUBLE_SIZE, double_size);
+        if !affine {
+            return;
+        }
+        Slider::new(&mut affine_index, 0..=AFFINE_COUNT as u32 - 1)
+            .text(" affine set")
+            .ui(ui);
+        attributes.set(SpriteAttributes::AFFINE_INDEX, affine_index);
+
+        // the set is shared with every sprite that picks it
+        let index = affine_index as usize;
+        if sprites.affine.len() <= index {
+            sprites.affine.resize(index + 1, SpriteAffine::default());
+        }
+        let set = &mut sprites.affine[index];
+        Slider::new(&mut set.angle, -PI..=PI).text(" angle").ui(ui);
+        Slider::new(&mut set.scale_x, 0.25..=4.0)
+            .text(" scale x")
+            .logarithmic(true)
+            .ui(ui);
+        Slider::new(&mut set.scale_y, 0.25..=4.0)
+            .text(" scale y")
+            .logarithmic(true)
+            .ui(ui);
+    }
+
+    /// Clicking on the canvas selects the sprite under the pointer and dragging moves it.
+    /// Returns whether a sprite is being dragged, the canvas shouldn't pan then.
+    pub fn canvas(
+        &mut self,
+        ui: &egui::Ui,
+        response: &Response,
+        rect: Rect,
+        screen: &ScreenContext,
+        sprites: &mut SpriteMapContext,
+    ) -> bool {
+        let press = ui.input(|io| io.pointer.press_origin());
+        if response.clicked() || response.drag_started() {
+            if let Some(pos) = press.or_else(|| response.interact_pointer_pos()) {
+                let (x, y) = screen_pixel(pos, rect, screen);
+                self.selected = sprites.sprite_at(x, y);
+                self.grab = self
+                    .selected
+                    .filter(|_| response.drag_started())
+                    .map(|index| {
+                        let sprite = &sprites.thing[index];
+                        (x + sprites.pan_x - sprite.x, y + sprites.pan_y - sprite.y)
+                    });
+            }
+        }
+
+        if let (Some(index), Some((grab_x, grab_y))) = (self.selected, self.grab) {
+            if let (Some(pos), Some(sprite)) = (
+                response.interact_pointer_pos(),
+                sprites.thing.get_mut(index),
+            ) {
+                let (x, y) = screen_pixel(pos, rect, screen);
+                sprite.x = x + sprites.pan_x - grab_x;
+                sprite.y = y + sprites.pan_y - grab_y;
+            }
+        }
+        let dragging = self.grab.is_some();
+        if response.drag_stopped() {
+            self.grab = None;
+        }
+
+        if let Some(sprite) = self.selected.and_then(|index| sprites.thing.get(index)) {
+            let (width, height) = sprite.bounds();
+            let (x, y) = (sprite.x - sprites.pan_x, sprite.y - sprites.pan_y);
+            ui.painter_at(rect).rect_stroke(
+                Rect::from_min_max(
+                    canvas_position(x, y, rect, screen),
+                    canvas_position(x + width, y + height, rect, screen),
+                ),
+                0.0,
+                Stroke::new(1.0, Color32::YELLOW),
+            );
+        }
+        dragging
+    }
+}
@@ -1,26 +1,188 @@
+use std::{borrow::Cow, cmp::Reverse, collections::BTreeMap};
+
 use glow::HasContext;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    resources::{ResourceManager, Texture},
-    ScreenContext,
+    animation::{PlaybackMode, SpriteClip, SpritePlayback},
+    resources::{ResourceManager, TextureHandle},
+    tilemap::{data_texture, Affine},
+    OutOfRange, ScreenContext, Scroll,
 };
 
+/// How many of [`SpriteMapContext::affine`] sprites can pick from.
+pub const AFFINE_COUNT: usize = 32;
+
 #[derive(Clone)]
 pub struct SpriteMapContext {
     pub thing: Vec<Sprite>,
+    /// Set from `scroll` whenever `RetroGraphics` composites.
     pub pan_x: i32,
     pub pan_y: i32,
+    pub scroll: Scroll,
+    /// Picked by sprites with `SpriteAttributes::AFFINE` through `AFFINE_INDEX`, missing sets
+    /// don't transform.
+    pub affine: Vec<SpriteAffine>,
+    /// Clips by name for [`SpriteMapContext::play`].
+    pub clips: BTreeMap<String, SpriteClip>,
+    /// What the sprite at the same index in `thing` is playing, it can be shorter.
+    pub playback: Vec<Option<SpritePlayback>>,
+    /// Per line limits like sprite hardware had, `None` draws every sprite.
+    pub limits: Option<SpriteLimits>,
+    pub format: SpriteFormat,
+
+    pub texture: TextureHandle,
 
-    texture: Texture,
     program: glow::Program,
     vertex_array: glow::VertexArray,
     buffer: glow::Buffer,
     last_buffer_size: usize,
+    /// How many sprites were uploaded, compact ones that don't fit aren't.
+    instances: usize,
+    /// Advanced by every `prepare` while `limits` flicker.
+    flicker_phase: usize,
+    /// What `limits` dropped the last time the sprites were prepared.
+    lines: Option<SpriteLines>,
+    line_texture: glow::Texture,
 }
 
-#[derive(Default, Clone, Copy, PartialEq, Eq)]
+/// How many sprites and sprite pixels are drawn on one screen line. Sprites are taken in
+/// front to back order, like [`SpriteMapContext::sprite_at`], from every layer and whether
+/// they're on screen horizontally or not. One that doesn't fit in what's left of the line
+/// isn't drawn on it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpriteLimits {
+    pub sprites: u32,
+    /// The whole width of the bounds of every sprite counts.
+    pub pixels: u32,
+    /// Rotates which sprite is taken first every frame, so sprites over the limits flicker
+    /// instead of staying hidden.
+    pub flicker: bool,
+}
+
+impl SpriteLimits {
+    /// 8 sprites of 8 pixels.
+    pub const NES: Self = Self {
+        sprites: 8,
+        pixels: 64,
+        flicker: false,
+    };
+    /// 32 sprites, 34 slivers of 8 pixels.
+    pub const SNES: Self = Self {
+        sprites: 32,
+        pixels: 272,
+        flicker: false,
+    };
+}
+
+/// Which sprites [`SpriteLimits`] leave on each screen line.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpriteLines {
+    lines: usize,
+    /// 255 where a sprite is drawn, a row of lines per sprite like the texture it goes to.
+    drawn: Vec<u8>,
+    /// How many sprites were dropped on each line, the top one first.
+    pub dropped: Vec<u32>,
+}
+
+impl SpriteLines {
+    /// `phase` is how far the order is rotated when `limits` flicker.
+    pub fn new(
+        sprites: &[Sprite],
+        pan_y: i32,
+        lines: i32,
+        limits: SpriteLimits,
+        phase: usize,
+    ) -> Self {
+        let lines = lines.max(0) as usize;
+        let mut drawn = vec![0; sprites.len() * lines];
+        let mut dropped = vec![0; lines];
+
+        let mut order: Vec<_> = (0..sprites.len()).collect();
+        order.sort_by_key(|&index| (sprites[index].layer, Reverse(index)));
+        if limits.flicker && !order.is_empty() {
+            let len = order.len();
+            order.rotate_left(phase % len);
+        }
+
+        // sprites and pixels taken on each line
+        let mut taken = vec![(0, 0); lines];
+        for index in order {
+            let sprite = &sprites[index];
+            let (width, height) = sprite.bounds();
+            let top = sprite.y - pan_y;
+            for line in top.max(0)..(top + height).min(lines as i32) {
+                let line = line as usize;
+                let (count, pixels) = &mut taken[line];
+                if *count < limits.sprites && *pixels + width as u32 <= limits.pixels {
+                    *count += 1;
+                    *pixels += width as u32;
+                    drawn[index * lines + line] = 255;
+                } else {
+                    dropped[line] += 1;
+                }
+            }
+        }
+        Self {
+            lines,
+            drawn,
+            dropped,
+        }
+    }
+
+    pub fn is_drawn(&self, sprite: usize, line: i32) -> bool {
+        usize::try_from(line)
+            .ok()
+            .filter(|&line| line < self.lines)
+            .and_then(|line| self.drawn.get(sprite * self.lines + line))
+            .is_some_and(|&drawn| drawn != 0)
+    }
+}
+
+/// A sprite the way it's uploaded, [`CompactSprite`] is the smaller record.
+#[derive(Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
 pub struct Sprite {
+    /// Top left of its bounds before panning, it can be off the top or left of the screen.
+    pub x: i32,
+    pub y: i32,
+
+    /// Where it is on the sheet in 8 pixel tiles.
+    pub tx: u16,
+    pub ty: u16,
+
+    pub layer: u8,
+    /// Which of the `ResourceManager` palettes indexed sheets are drawn with, it wraps around
+    /// the palettes there are.
+    pub palette: u8,
+    /// How opaque it's drawn, 255 keeps the sheet's alpha as it is.
+    pub blend: u8,
+    _unused: u8,
+
+    pub attribute: SpriteAttributes,
+}
+
+impl Default for Sprite {
+    fn default() -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            tx: 0,
+            ty: 0,
+            layer: 0,
+            palette: 0,
+            blend: 255,
+            _unused: 0,
+            attribute: SpriteAttributes::default(),
+        }
+    }
+}
+
+/// The original packed 8 byte record of [`SpriteFormat::Compact`]. Positions are unsigned
+/// 16 bit and sheet tiles 8 bit, it's opaque, drawn with palette 0 and never affine.
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct CompactSprite {
     pub x: u16,
     pub y: u16,
 
@@ -28,25 +190,249 @@ pub struct Sprite {
     pub ty: u8,
 
     pub layer: u8,
-    pub attribute: SpriteAttributes,
+    pub attribute: CompactAttributes,
+}
+
+mycelium_bitfield::bitfield! {
+    /// The first byte of [`SpriteAttributes`].
+    #[derive(Default, PartialEq, Eq)]
+    pub struct CompactAttributes<u8> {
+        pub const HORIZONTAL: bool;
+        pub const VERTICAL: bool;
+        pub const ROTATION = 2;
+        pub const XSIZE = 2;
+        pub const YSIZE = 2;
+    }
+}
+
+/// Fails for positions and sheet tiles that don't fit, the palette, blend and affine
+/// attributes are dropped.
+impl TryFrom<&Sprite> for CompactSprite {
+    type Error = OutOfRange;
+
+    fn try_from(sprite: &Sprite) -> Result<Self, OutOfRange> {
+        let field = |value: i32, field| u16::try_from(value).map_err(|_| OutOfRange(field));
+        let tile = |value: u16, field| u8::try_from(value).map_err(|_| OutOfRange(field));
+        let mut attribute = CompactAttributes::new();
+        attribute
+            .set(
+                CompactAttributes::HORIZONTAL,
+                sprite.attribute.get(SpriteAttributes::HORIZONTAL),
+            )
+            .set(
+                CompactAttributes::VERTICAL,
+                sprite.attribute.get(SpriteAttributes::VERTICAL),
+            )
+            .set(
+                CompactAttributes::ROTATION,
+                sprite.attribute.get(SpriteAttributes::ROTATION) as u8,
+            )
+            .set(
+                CompactAttributes::XSIZE,
+                sprite.attribute.get(SpriteAttributes::XSIZE) as u8,
+            )
+            .set(
+                CompactAttributes::YSIZE,
+                sprite.attribute.get(SpriteAttributes::YSIZE) as u8,
+            );
+        Ok(Self {
+            x: field(sprite.x, "x")?,
+            y: field(sprite.y, "y")?,
+            tx: tile(sprite.tx, "tx")?,
+            ty: tile(sprite.ty, "ty")?,
+            layer: sprite.layer,
+            attribute,
+        })
+    }
+}
+
+impl From<CompactSprite> for Sprite {
+    fn from(compact: CompactSprite) -> Self {
+        let mut attribute = SpriteAttributes::new();
+        attribute
+            .set(
+                SpriteAttributes::HORIZONTAL,
+                compact.attribute.get(CompactAttributes::HORIZONTAL),
+            )
+            .set(
+                SpriteAttributes::VERTICAL,
+                compact.attribute.get(CompactAttributes::VERTICAL),
+            )
+            .set(
+                SpriteAttributes::ROTATION,
+                compact.attribute.get(CompactAttributes::ROTATION) as u32,
+            )
+            .set(
+                SpriteAttributes::XSIZE,
+                compact.attribute.get(CompactAttributes::XSIZE) as u32,
+            )
+            .set(
+                SpriteAttributes::YSIZE,
+                compact.attribute.get(CompactAttributes::YSIZE) as u32,
+            );
+        Self {
+            x: compact.x as i32,
+            y: compact.y as i32,
+            tx: compact.tx as u16,
+            ty: compact.ty as u16,
+            layer: compact.layer,
+            attribute,
+            ..Default::default()
+        }
+    }
+}
+
+/// What's uploaded for every sprite.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpriteFormat {
+    /// [`Sprite`], 20 bytes.
+    #[default]
+    Extended,
+    /// [`CompactSprite`], 8 bytes. Sprites that don't fit it aren't drawn.
+    Compact,
 }
 
 mycelium_bitfield::bitfield! {
     #[derive(Default, PartialEq, Eq)]
-    pub struct SpriteAttributes<u8> {
+    pub struct SpriteAttributes<u32> {
         pub const HORIZONTAL: bool;
         pub const VERTICAL: bool;
         pub const ROTATION = 2;
         pub const XSIZE = 2;
         pub const YSIZE = 2;
+        const _RESERVED = 4;
+        /// Rotated and scaled by a [`SpriteAffine`] around its center, flips and `ROTATION`
+        /// still apply first.
+        pub const AFFINE: bool;
+        /// An affine sprite's bounds are twice as big so it isn't cut off when it turns or grows.
+        pub const DOUBLE_SIZE: bool;
+        pub const AFFINE_INDEX = 5;
+        const _UNUSED = 13;
+    }
+}
+
+/// Rotation and scale shared by every sprite that picks it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpriteAffine {
+    /// Radians clockwise.
+    pub angle: f32,
+    pub scale_x: f32,
+    pub scale_y: f32,
+}
+
+impl Default for SpriteAffine {
+    fn default() -> Self {
+        Self {
+            angle: 0.0,
+            scale_x: 1.0,
+            scale_y: 1.0,
+        }
+    }
+}
+
+impl SpriteAffine {
+    /// Takes pixels relative to the center of the sprite's bounds to pixels relative to the
+    /// center of the sprite, `[a, b, c, d]` like [`Affine::matrix`].
+    pub fn matrix(&self) -> [f32; 4] {
+        Affine::rotate_scale(self.angle, self.scale_x, self.scale_y, [0.0, 0.0]).matrix
+    }
+}
+
+impl Sprite {
+    /// Width and height in pixels on screen, rotating by 90 or 270 degrees swaps them.
+    pub fn size(&self) -> (i32, i32) {
+        let x_size = self.attribute.get(SpriteAttributes::XSIZE) as i32 * 8 + 8;
+        let y_size = self.attribute.get(SpriteAttributes::YSIZE) as i32 * 8 + 8;
+        if self.attribute.get(SpriteAttributes::ROTATION) & 1 == 1 {
+            (y_size, x_size)
+        } else {
+            (x_size, y_size)
+        }
+    }
+
+    /// Width and height of the area it's drawn in, its `size` unless it's affine and double size.
+    pub fn bounds(&self) -> (i32, i32) {
+        let (width, height) = self.size();
+        if self.attribute.get(SpriteAttributes::AFFINE)
+            && self.attribute.get(SpriteAttributes::DOUBLE_SIZE)
+        {
+            (width * 2, height * 2)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// The transform it's drawn with if it's affine.
+    pub fn affine(&self, sets: &[SpriteAffine]) -> Option<SpriteAffine> {
+        self.attribute.get(SpriteAttributes::AFFINE).then(|| {
+            let index = self.attribute.get(SpriteAttributes::AFFINE_INDEX) as usize;
+            sets.get(index).copied().unwrap_or_default()
+        })
     }
 }
 
 impl SpriteMapContext {
+    /// Index of the frontmost sprite covering screen pixel `x`, `y`, ignoring transparency.
+    pub fn sprite_at(&self, x: i32, y: i32) -> Option<usize> {
+        let (x, y) = (x + self.pan_x, y + self.pan_y);
+        self.thing
+            .iter()
+            .enumerate()
+            .filter(|(_, sprite)| {
+                let (width, height) = sprite.bounds();
+                let (left, top) = (sprite.x, sprite.y);
+                x >= left && y >= top && x < left + width && y < top + height
+            })
+            // lower layers are in front, then later sprites
+            .min_by_key(|(index, sprite)| (sprite.layer, std::cmp::Reverse(*index)))
+            .map(|(index, _)| index)
+    }
+
+    /// Starts the sprite at `sprite` on the first frame of the clip named `clip`, false if
+    /// either doesn't exist. Whatever it played before stops.
+    pub fn play(&mut self, sprite: usize, clip: &str, mode: PlaybackMode) -> bool {
+        let (Some(target), Some(frames)) = (self.thing.get_mut(sprite), self.clips.get(clip))
+        else {
+            return false;
+        };
+        if self.playback.len() <= sprite {
+            self.playback.resize(sprite + 1, None);
+        }
+        let mut playback = SpritePlayback::new(clip, mode);
+        // the new frames are offset from where the sprite was before the old ones moved it
+        if let Some(previous) = self.playback[sprite].take() {
+            playback.offset = previous.offset;
+        }
+        playback.apply(frames, target);
+        self.playback[sprite] = Some(playback);
+        true
+    }
+
+    /// Leaves the sprite at `sprite` on the frame it's showing.
+    pub fn stop(&mut self, sprite: usize) -> Option<SpritePlayback> {
+        self.playback.get_mut(sprite)?.take()
+    }
+
+    /// Moves every sprite's clip `dt` seconds forward.
+    pub fn advance(&mut self, dt: f64) {
+        self.playback.truncate(self.thing.len());
+        for (sprite, playback) in self.thing.iter_mut().zip(&mut self.playback) {
+            let Some(playback) = playback else {
+                continue;
+            };
+            let Some(clip) = self.clips.get(&playback.clip) else {
+                continue;
+            };
+            if playback.advance(clip, dt) {
+                playback.apply(clip, sprite);
+            }
+        }
+    }
+
     pub fn new(
         gl: &glow::Context,
         resources: &mut ResourceManager,
-        texture: Texture,
+        texture: TextureHandle,
     ) -> Option<Self> {
         let buffer;
         unsafe {
@@ -79,6 +465,12 @@ impl SpriteMapContext {
         Some(Self {
             pan_x: 0,
             pan_y: 0,
+            scroll: Scroll::default(),
+            affine: Vec::new(),
+            clips: BTreeMap::new(),
+            playback: Vec::new(),
+            limits: None,
+            format: SpriteFormat::default(),
             thing: vec![
                 Sprite {
                     x: 10,
@@ -87,6 +479,7 @@ impl SpriteMapContext {
                     ty: 3 * 2,
                     layer: 2,
                     attribute: SpriteAttributes(0b00001010),
+                    ..Default::default()
                 },
                 // Sprite{ x: 10, y: 10, tx: 5, ty: 0, layer: 3, attribute: SpriteAttributes(0b00000000) },
             ],
@@ -94,21 +487,191 @@ impl SpriteMapContext {
             vertex_array,
             buffer,
             last_buffer_size: 0,
+            instances: 0,
+            flicker_phase: 0,
+            lines: None,
+            line_texture: unsafe { data_texture(gl) },
             texture,
         })
     }
 
+    /// # Safety
+    /// `gl` must be the context this was created with and it can't be painted afterwards.
     pub unsafe fn destroy(&self, gl: &glow::Context) {
         gl.delete_vertex_array(self.vertex_array);
         gl.delete_buffer(self.buffer);
+        gl.delete_texture(self.line_texture);
+    }
+
+    /// The sprites as they're drawn, compact ones lose what doesn't fit and the ones that
+    /// don't fit at all are left out.
+    pub fn drawn(&self) -> Cow<'_, [Sprite]> {
+        match self.format {
+            SpriteFormat::Extended => Cow::Borrowed(&self.thing),
+            SpriteFormat::Compact => Cow::Owned(
+                self.thing
+                    .iter()
+                    .filter_map(|sprite| CompactSprite::try_from(sprite).ok())
+                    .map(Sprite::from)
+                    .collect(),
+            ),
+        }
+    }
+
+    /// The sprites `limits` leave on each of the screen's `lines` this frame.
+    pub fn sprite_lines(&self, lines: i32) -> Option<SpriteLines> {
+        self.limits.map(|limits| {
+            SpriteLines::new(&self.drawn(), self.pan_y, lines, limits, self.flicker_phase)
+        })
+    }
+
+    /// Sprites dropped on each screen line the last time they were prepared, empty without
+    /// limits.
+    pub fn overflow(&self) -> &[u32] {
+        self.lines.as_ref().map_or(&[], |lines| &lines.dropped)
+    }
+
+    /// Uploads the sprites and marks the priorities they use.
+    pub fn prepare(
+        &mut self,
+        gl: &glow::Context,
+        screen: &ScreenContext,
+        priorities: &mut [bool; 256],
+    ) {
+        for sprite in &self.thing {
+            priorities[sprite.layer as usize] = true;
+        }
+
+        if self.limits.is_some_and(|limits| limits.flicker) {
+            self.flicker_phase = self.flicker_phase.wrapping_add(1);
+        }
+        self.instances = self.drawn().len();
+        self.lines = self.sprite_lines(screen.screen_px_y);
+        if let Some(lines) = &self.lines {
+            unsafe {
+                gl.bind_texture(glow::TEXTURE_2D, Some(self.line_texture));
+                gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+                gl.tex_image_2d(
+                    glow::TEXTURE_2D,
+                    0,
+                    glow::R8 as i32,
+                    lines.lines as i32,
+                    self.instances as i32,
+                    0,
+                    glow::RED,
+                    glow::UNSIGNED_BYTE,
+                    Some(&lines.drawn),
+                );
+                gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+                gl.bind_texture(glow::TEXTURE_2D, None);
+            }
+        }
+
+        unsafe {
+            gl.bind_vertex_array(Some(self.vertex_array));
+
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.buffer));
+
+            {
+                let compact: Vec<CompactSprite>;
+                let raw_data = match self.format {
+                    SpriteFormat::Extended => std::slice::from_raw_parts(
+                        self.thing.as_ptr().cast(),
+                        std::mem::size_of_val(self.thing.as_slice()),
+                    ),
+                    SpriteFormat::Compact => {
+                        compact = self
+                            .thing
+                            .iter()
+                            .filter_map(|sprite| CompactSprite::try_from(sprite).ok())
+                            .collect();
+                        std::slice::from_raw_parts(
+                            compact.as_ptr().cast(),
+                            std::mem::size_of_val(compact.as_slice()),
+                        )
+                    }
+                };
+                if raw_data.len() <= self.last_buffer_size {
+                    gl.buffer_sub_data_u8_slice(glow::ARRAY_BUFFER, 0, raw_data);
+                } else {
+                    gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, raw_data, glow::DYNAMIC_DRAW);
+                    self.last_buffer_size = raw_data.len();
+                }
+            }
+            // gl.buffer_sub_data_u8_slice(target, offset, src_data)
+            // gl.bind_buffer_base(glow::ARRAY_BUFFER, 0, Some(self.buffer));
+            // the shader reads whichever format is enabled
+            match self.format {
+                SpriteFormat::Extended => {
+                    let stride = std::mem::size_of::<Sprite>() as i32;
+                    gl.disable_vertex_attrib_array(2);
+                    gl.enable_vertex_attrib_array(3);
+                    gl.enable_vertex_attrib_array(4);
+                    gl.vertex_attrib_pointer_i32(3, 4, glow::INT, stride, 0);
+                    gl.vertex_attrib_pointer_i32(4, 1, glow::INT, stride, 16);
+                }
+                SpriteFormat::Compact => {
+                    let stride = std::mem::size_of::<CompactSprite>() as i32;
+                    gl.enable_vertex_attrib_array(2);
+                    gl.disable_vertex_attrib_array(3);
+                    gl.disable_vertex_attrib_array(4);
+                    gl.vertex_attrib_pointer_i32(2, 2, glow::INT, stride, 0);
+                }
+            }
+            gl.vertex_attrib_divisor(2, 1);
+            gl.vertex_attrib_divisor(3, 1);
+            gl.vertex_attrib_divisor(4, 1);
+            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+        }
     }
 
-    pub fn paint(&mut self, gl: &glow::Context, screen: &ScreenContext) {
+    /// Draws the sprites whose layer is `priority` in order, later sprites on top.
+    /// [`SpriteMapContext::prepare`] has to run first.
+    pub fn paint(
+        &self,
+        gl: &glow::Context,
+        screen: &ScreenContext,
+        resources: &ResourceManager,
+        priority: u8,
+    ) {
+        let Some(texture) = resources.texture(self.texture) else {
+            return;
+        };
         unsafe {
+            gl.active_texture(glow::TEXTURE2);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.line_texture));
+            gl.active_texture(glow::TEXTURE1);
+            gl.bind_texture(glow::TEXTURE_2D, resources.palette_texture());
             gl.active_texture(glow::TEXTURE0);
-            gl.bind_texture(glow::TEXTURE_2D, Some(self.texture.texture));
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture.texture));
 
             gl.use_program(Some(self.program));
+            gl.uniform_1_i32(gl.get_uniform_location(self.program, "tex").as_ref(), 0);
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "palettes").as_ref(),
+                1,
+            );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "sprite_lines")
+                    .as_ref(),
+                2,
+            );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "limited").as_ref(),
+                self.lines.is_some() as i32,
+            );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "compact").as_ref(),
+                (self.format == SpriteFormat::Compact) as i32,
+            );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "indexed").as_ref(),
+                texture.indexed as i32,
+            );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "priority").as_ref(),
+                priority as i32,
+            );
 
             gl.uniform_1_f32(
                 gl.get_uniform_location(self.program, "zoom").as_ref(),
@@ -117,11 +680,11 @@ impl SpriteMapContext {
 
             gl.uniform_1_i32(
                 gl.get_uniform_location(self.program, "map_width").as_ref(),
-                self.texture.width,
+                texture.width,
             );
             gl.uniform_1_i32(
                 gl.get_uniform_location(self.program, "map_height").as_ref(),
-                self.texture.height,
+                texture.height,
             );
 
             gl.uniform_1_i32(
@@ -144,30 +707,17 @@ impl SpriteMapContext {
                 self.pan_y,
             );
 
-            gl.bind_vertex_array(Some(self.vertex_array));
-
-            gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.buffer));
-            gl.enable_vertex_attrib_array(2);
-
-            {
-                let raw_data = std::slice::from_raw_parts(
-                    self.thing.as_ptr().cast(),
-                    self.thing.len() * std::mem::size_of::<Sprite>(),
-                );
-                if raw_data.len() <= self.last_buffer_size {
-                    gl.buffer_sub_data_u8_slice(glow::ARRAY_BUFFER, 0, raw_data);
-                } else {
-                    gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, raw_data, glow::DYNAMIC_DRAW);
-                    self.last_buffer_size = raw_data.len();
-                }
+            let mut matrices = [SpriteAffine::default().matrix(); AFFINE_COUNT];
+            for (matrix, set) in matrices.iter_mut().zip(&self.affine) {
+                *matrix = set.matrix();
             }
-            // gl.buffer_sub_data_u8_slice(target, offset, src_data)
-            // gl.bind_buffer_base(glow::ARRAY_BUFFER, 0, Some(self.buffer));
-            gl.vertex_attrib_pointer_i32(2, 2, glow::INT, 2 * std::mem::size_of::<i32>() as i32, 0);
-            gl.vertex_attrib_divisor(2, 1);
-            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+            gl.uniform_4_f32_slice(
+                gl.get_uniform_location(self.program, "affine").as_ref(),
+                matrices.as_flattened(),
+            );
 
-            gl.draw_arrays_instanced(glow::TRIANGLES, 0, 6, self.thing.len() as i32);
+            gl.bind_vertex_array(Some(self.vertex_array));
+            gl.draw_arrays_instanced(glow::TRIANGLES, 0, 6, self.instances as i32);
         }
     }
 }
@@ -0,0 +1,337 @@
+use egui::{Color32, Pos2, Rect, Response, Slider, Stroke, Widget};
+use graphics_test::{
+    palette::{self, PALETTE_COUNT},
+    resources::{ResourceManager, TextureHandle, TexturePixels},
+    tilemap::{EdgeMode, Tile, TileAttributes, TileMap},
+    ScreenContext,
+};
+
+const HISTORY: usize = 100;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    /// Dragging the canvas pans every layer.
+    Pan,
+    Paint,
+    Fill,
+    Rect,
+}
+
+/// Paints tiles onto one `Layer::TileMap`, with undo and redo.
+pub struct TileEditor {
+    /// Index into `RetroGraphics::layers` of the tilemap being edited.
+    pub layer: Option<usize>,
+    pub tool: Tool,
+    /// Placed by every tool, right click on the canvas picks it up from the map.
+    pub brush: Tile,
+
+    undo: Vec<TileMap>,
+    redo: Vec<TileMap>,
+    // tile (not wrapped into the map) the rect tool's drag started on
+    rect_start: Option<(i32, i32)>,
+    // the tileset as an egui texture for the picker
+    picker: Option<(TextureHandle, u16, egui::TextureHandle)>,
+}
+
+impl Default for TileEditor {
+    fn default() -> Self {
+        let mut brush = Tile::default();
+        brush.layer = 50;
+        Self {
+            layer: None,
+            tool: Tool::Pan,
+            brush,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            rect_start: None,
+            picker: None,
+        }
+    }
+}
+
+impl TileEditor {
+    /// Whether dragging the canvas edits the map instead of panning.
+    pub fn editing(&self) -> bool {
+        self.layer.is_some() && self.tool != Tool::Pan
+    }
+
+    /// Forgets the cached tileset picker, textures may have been replaced.
+    pub fn reload_textures(&mut self) {
+        self.picker = None;
+    }
+
+    pub fn undo(&mut self, map: &mut TileMap) {
+        if let Some(previous) = self.undo.pop() {
+            self.redo.push(restore(map, previous));
+        }
+    }
+
+    pub fn redo(&mut self, map: &mut TileMap) {
+        if let Some(next) = self.redo.pop() {
+            self.undo.push(restore(map, next));
+        }
+    }
+
+    /// Remembers the map before an edit.
+    pub fn checkpoint(&mut self, map: &TileMap) {
+        if self.undo.len() == HISTORY {
+            self.undo.remove(0);
+        }
+        self.undo.push(map.clone());
+        self.redo.clear();
+    }
+
+    /// Tools, brush settings and the tile picker.
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        map: &mut TileMap,
+        texture: TextureHandle,
+        resources: &ResourceManager,
+    ) {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.tool, Tool::Pan, "Pan");
+            ui.selectable_value(&mut self.tool, Tool::Paint, "Paint");
+            ui.selectable_value(&mut self.tool, Tool::Fill, "Fill");
+            ui.selectable_value(&mut self.tool, Tool::Rect, "Rect");
+        });
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(!self.undo.is_empty(), egui::Button::new("Undo"))
+                .clicked()
+            {
+                self.undo(map);
+            }
+            if ui
+                .add_enabled(!self.redo.is_empty(), egui::Button::new("Redo"))
+                .clicked()
+            {
+                self.redo(map);
+            }
+        });
+
+        let attributes = &mut self.brush.attributes;
+        let mut flip_h = attributes.get(TileAttributes::HORIZONTAL);
+        let mut flip_v = attributes.get(TileAttributes::VERTICAL);
+        let mut rotation = attributes.get(TileAttributes::ROTATION);
+        let mut palette = attributes.get(TileAttributes::PALETTE);
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut flip_h, "Flip H");
+            ui.checkbox(&mut flip_v, "Flip V");
+        });
+        Slider::new(&mut rotation, 0..=3).text(" rotation").ui(ui);
+        Slider::new(&mut palette, 0..=PALETTE_COUNT as u16 - 1)
+            .text(" palette")
+            .ui(ui);
+        attributes.set(TileAttributes::HORIZONTAL, flip_h);
+        attributes.set(TileAttributes::VERTICAL, flip_v);
+        attributes.set(TileAttributes::ROTATION, rotation);
+        attributes.set(TileAttributes::PALETTE, palette);
+        Slider::new(&mut self.brush.layer, 0..=255)
+            .text(" layer")
+            .ui(ui);
+        ui.label(format!("tile: {}, {}", self.brush.x, self.brush.y));
+
+        self.tile_picker(ui, texture, palette, map.tile_size(), resources);
+    }
+
+    fn tile_picker(
+        &mut self,
+        ui: &mut egui::Ui,
+        texture: TextureHandle,
+        palette: u16,
+        (tile_width, tile_height): (i32, i32),
+        resources: &ResourceManager,
+    ) {
+        let Some(pixels) = resources.texture_pixels(texture) else {
+            return;
+        };
+        let cached = self
+            .picker
+            .as_ref()
+            .map(|(texture, palette, _)| (*texture, *palette));
+        if cached != Some((texture, palette)) {
+            let rgba: Vec<u8> = match pixels {
+                TexturePixels::Rgba(image) => image.as_raw().clone(),
+                TexturePixels::Indexed(indices) => {
+                    let colors = resources
+                        .palette(palette as usize)
+                        .copied()
+                        .unwrap_or_else(palette::default_palette);
+                    indices
+                        .as_raw()
+                        .iter()
+                        .flat_map(|&index| colors[index as usize])
+                        .collect()
+                }
+            };
+            let image = egui::ColorImage::from_rgba_unmultiplied(
+                [pixels.width() as usize, pixels.height() as usize],
+                &rgba,
+            );
+            let handle = ui
+                .ctx()
+                .load_texture("tile picker", image, egui::TextureOptions::NEAREST);
+            self.picker = Some((texture, palette, handle));
+        }
+        let Some((_, _, handle)) = &self.picker else {
+            return;
+        };
+
+        let size = handle.size_vec2() * 2.0;
+        // tiles are shown twice as big
+        let tile = egui::vec2(tile_width as f32, tile_height as f32) * 2.0;
+        egui::ScrollArea::both()
+            .id_source("tile picker")
+            .max_height(320.0)
+            .max_width(320.0)
+            .show(ui, |ui| {
+                let response = egui::Image::new((handle.id(), size))
+                    .sense(egui::Sense::click())
+                    .ui(ui);
+                let rect = response.rect;
+                if let Some(pos) = response.interact_pointer_pos() {
+                    if response.clicked() {
+                        self.brush.x = ((pos.x - rect.min.x) / tile.x) as u16;
+                        self.brush.y = ((pos.y - rect.min.y) / tile.y) as u16;
+                    }
+                }
+                let selected = Rect::from_min_size(
+                    rect.min + egui::vec2(self.brush.x as f32, self.brush.y as f32) * tile,
+                    tile,
+                );
+                ui.painter()
+                    .rect_stroke(selected, 0.0, Stroke::new(2.0, Color32::YELLOW));
+            });
+    }
+
+    /// Applies the current tool for pointer input on the canvas `response`, which shows the
+    /// screen in `rect`. Past the edges of maps that don't wrap there's nothing to edit.
+    pub fn canvas(
+        &mut self,
+        ui: &egui::Ui,
+        response: &Response,
+        rect: Rect,
+        screen: &ScreenContext,
+        map: &mut TileMap,
+        edge: EdgeMode,
+    ) {
+        if map.tiles_x == 0 || map.tiles_y == 0 {
+            return;
+        }
+        let Some(pos) = response
+            .interact_pointer_pos()
+            .or_else(|| response.hover_pos())
+        else {
+            return;
+        };
+        let (px, py) = screen_pixel(pos, rect, screen);
+        let (tile_width, tile_height) = map.tile_size();
+        // tile under the pointer, not wrapped into the map yet
+        let (tx, ty) = (
+            (px + map.pan_x).div_euclid(tile_width),
+            (py + map.pan_y).div_euclid(tile_height),
+        );
+        let (tiles_x, tiles_y) = (map.tiles_x as i32, map.tiles_y as i32);
+        let wrap = |x: i32, y: i32| match edge {
+            EdgeMode::Wrap => (x.rem_euclid(tiles_x), y.rem_euclid(tiles_y)),
+            _ => (x, y),
+        };
+        let wrapped = wrap(tx, ty);
+
+        if response.secondary_clicked() {
+            if let Some(tile) = map.get(wrapped.0, wrapped.1) {
+                self.brush = tile;
+            }
+            return;
+        }
+
+        match self.tool {
+            Tool::Pan => {}
+            Tool::Paint => {
+                if response.drag_started() || response.clicked() {
+                    self.checkpoint(map);
+                }
+                if response.dragged() || response.clicked() {
+                    map.set(wrapped.0, wrapped.1, self.brush);
+                }
+            }
+            Tool::Fill => {
+                if response.clicked() {
+                    self.checkpoint(map);
+                    map.flood_fill(wrapped.0, wrapped.1, self.brush);
+                }
+            }
+            Tool::Rect => {
+                if response.drag_started() {
+                    self.rect_start = Some((tx, ty));
+                }
+                let Some(start) = self.rect_start else {
+                    return;
+                };
+                let (x0, x1) = (start.0.min(tx), start.0.max(tx));
+                let (y0, y1) = (start.1.min(ty), start.1.max(ty));
+
+                if response.drag_stopped() {
+                    self.rect_start = None;
+                    self.checkpoint(map);
+                    // wraps like the map does, but never covers a tile twice
+                    let (xs, ys) = match edge {
+                        EdgeMode::Wrap => {
+                            (x0..=x1.min(x0 + tiles_x - 1), y0..=y1.min(y0 + tiles_y - 1))
+                        }
+                        _ => (
+                            x0.max(0)..=x1.min(tiles_x - 1),
+                            y0.max(0)..=y1.min(tiles_y - 1),
+                        ),
+                    };
+                    for y in ys {
+                        for x in xs.clone() {
+                            let (x, y) = wrap(x, y);
+                            map.set(x, y, self.brush);
+                        }
+                    }
+                } else {
+                    let min = canvas_position(
+                        x0 * tile_width - map.pan_x,
+                        y0 * tile_height - map.pan_y,
+                        rect,
+                        screen,
+                    );
+                    let max = canvas_position(
+                        (x1 + 1) * tile_width - map.pan_x,
+                        (y1 + 1) * tile_height - map.pan_y,
+                        rect,
+                        screen,
+                    );
+                    ui.painter_at(rect).rect_stroke(
+                        Rect::from_min_max(min, max),
+                        0.0,
+                        Stroke::new(1.0, Color32::YELLOW),
+                    );
+                }
+            }
+        }
+    }
+}
+
+// the old map comes back but the pan and tile size stay what they are
+fn restore(map: &mut TileMap, mut previous: TileMap) -> TileMap {
+    previous.pan_x = map.pan_x;
+    previous.pan_y = map.pan_y;
+    previous.tile_width = map.tile_width;
+    previous.tile_height = map.tile_height;
+    std::mem::replace(map, previous)
+}
+
+/// Screen pixel under `pos` on the canvas showing the screen in `rect`.
+pub fn screen_pixel(pos: Pos2, rect: Rect, screen: &ScreenContext) -> (i32, i32) {
+    let pos = (pos - rect.min) / rect.size();
+    screen.pixel_at(pos.x, pos.y)
+}
+
+/// Top left corner of screen pixel `x`, `y` on the canvas showing the screen in `rect`.
+pub fn canvas_position(x: i32, y: i32, rect: Rect, screen: &ScreenContext) -> Pos2 {
+    let (x, y) = screen.viewport_position(x, y);
+    rect.min + egui::vec2(x, y) * rect.size()
+}
@@ -0,0 +1,547 @@
+//! Imports maps made with [Tiled](https://www.mapeditor.org/), either `.tmx` (XML) or `.tmj`
+//! (JSON).
+//!
+//! Every tile layer becomes a `TileMapContext` drawing from one tileset. Tilesets are looked
+//! up in `ResourceManager` by the file stem of their image, then by their name, then by the
+//! file stem of their `source` for external tilesets, so their textures have to be loaded
+//! first. Tiles are the map's size, tilesets with other sizes aren't supported, and empty
+//! tiles are [`TileAttributes::HIDDEN`]. Layer offsets and
+//! parallax factors become the layer's [`Scroll`]. Infinite maps aren't supported, object and
+//! image layers are skipped.
+
+use base64::Engine;
+use std::io::Read;
+
+use crate::{
+    resources::{ResourceManager, TextureHandle},
+    tilemap::{Tile, TileAttributes, TileMap, TileMapContext},
+    Layer, RetroGraphics, Scroll,
+};
+
+const FLIPPED_HORIZONTALLY: u32 = 1 << 31;
+const FLIPPED_VERTICALLY: u32 = 1 << 30;
+const FLIPPED_DIAGONALLY: u32 = 1 << 29;
+// hexagonal maps only, ignored
+const ROTATED_HEXAGONAL_120: u32 = 1 << 28;
+const FLAGS: u32 =
+    FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY | ROTATED_HEXAGONAL_120;
+
+/// The parts of a Tiled map the importer uses.
+pub struct TiledMap {
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub tilesets: Vec<TiledTileset>,
+    /// Bottom to top, like in Tiled. Layers inside groups are flattened.
+    pub layers: Vec<TiledLayer>,
+}
+
+pub struct TiledTileset {
+    pub first_gid: u32,
+    pub name: String,
+    /// Path of the tileset image.
+    pub image: Option<String>,
+    /// Path of an external tileset, its name and image aren't known then.
+    pub source: Option<String>,
+    /// Tiles per row of the image, the texture's width is used if it's unknown.
+    pub columns: Option<u32>,
+    pub tile_width: Option<u32>,
+    pub tile_height: Option<u32>,
+}
+
+pub struct TiledLayer {
+    pub name: String,
+    pub width: u16,
+    pub height: u16,
+    /// Global tile ids with Tiled's flip flags, row by row.
+    pub gids: Vec<u32>,
+    /// From the layer's offset and parallax factor combined with those of its groups.
+    pub scroll: Scroll,
+}
+
+#[derive(Debug)]
+pub enum TiledError {
+    Xml(roxmltree::Error),
+    Json(serde_json::Error),
+    /// A required attribute or field is missing or isn't a valid number.
+    Missing(&'static str),
+    Unsupported(String),
+    BadData(String),
+    /// None of the names of the tileset match a texture.
+    MissingTileset(String),
+    /// Tiles of one layer come from more than one tileset.
+    MixedTilesets(String),
+}
+
+impl std::fmt::Display for TiledError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TiledError::Xml(err) => write!(f, "{err}"),
+            TiledError::Json(err) => write!(f, "{err}"),
+            TiledError::Missing(what) => write!(f, "missing or invalid {what}"),
+            TiledError::Unsupported(what) => write!(f, "unsupported: {what}"),
+            TiledError::BadData(what) => write!(f, "bad layer data: {what}"),
+            TiledError::MissingTileset(name) => write!(f, "no texture for tileset {name}"),
+            TiledError::MixedTilesets(layer) => {
+                write!(f, "layer {layer} uses more than one tileset")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TiledError {}
+
+impl From<roxmltree::Error> for TiledError {
+    fn from(err: roxmltree::Error) -> Self {
+        TiledError::Xml(err)
+    }
+}
+
+impl From<serde_json::Error> for TiledError {
+    fn from(err: serde_json::Error) -> Self {
+        TiledError::Json(err)
+    }
+}
+
+/// Sets the attributes of `tile` from the flip flags of `gid`.
+///
+/// Tiled flips diagonally (swapping x and y) first and then horizontally and vertically,
+/// which comes out as one clockwise rotation with the horizontal flip inverted.
+pub fn apply_flip_flags(tile: &mut Tile, gid: u32) {
+    let horizontal = gid & FLIPPED_HORIZONTALLY != 0;
+    let vertical = gid & FLIPPED_VERTICALLY != 0;
+    let diagonal = gid & FLIPPED_DIAGONALLY != 0;
+    tile.attributes
+        .set(TileAttributes::HORIZONTAL, horizontal != diagonal)
+        .set(TileAttributes::VERTICAL, vertical)
+        .set(TileAttributes::ROTATION, diagonal as u16);
+}
+
+impl TiledMap {
+    /// Reads either format, XML is recognized by its leading `<`.
+    pub fn parse(bytes: &[u8]) -> Result<Self, TiledError> {
+        let text = std::str::from_utf8(bytes)
+            .map_err(|err| TiledError::BadData(format!("not UTF-8: {err}")))?;
+        if text.trim_start().starts_with('<') {
+            Self::from_tmx(text)
+        } else {
+            Self::from_tmj(text)
+        }
+    }
+
+    pub fn from_tmx(xml: &str) -> Result<Self, TiledError> {
+        let document = roxmltree::Document::parse(xml)?;
+        let map = document.root_element();
+        if map.attribute("infinite") == Some("1") {
+            return Err(TiledError::Unsupported("infinite maps".into()));
+        }
+
+        let mut tilesets = Vec::new();
+        for tileset in map.children().filter(|node| node.has_tag_name("tileset")) {
+            let image = tileset.children().find(|node| node.has_tag_name("image"));
+            tilesets.push(TiledTileset {
+                first_gid: number(tileset.attribute("firstgid"), "tileset firstgid")?,
+                name: tileset.attribute("name").unwrap_or_default().into(),
+                image: image
+                    .and_then(|image| image.attribute("source"))
+                    .map(Into::into),
+                source: tileset.attribute("source").map(Into::into),
+                columns: optional(tileset.attribute("columns"), "tileset columns")?,
+                tile_width: optional(tileset.attribute("tilewidth"), "tileset tilewidth")?,
+                tile_height: optional(tileset.attribute("tileheight"), "tileset tileheight")?,
+            });
+        }
+
+        let mut layers = Vec::new();
+        tmx_layers(map, Scroll::default(), &mut layers)?;
+
+        Ok(Self {
+            tile_width: number(map.attribute("tilewidth"), "map tilewidth")?,
+            tile_height: number(map.attribute("tileheight"), "map tileheight")?,
+            tilesets,
+            layers,
+        })
+    }
+
+    pub fn from_tmj(json: &str) -> Result<Self, TiledError> {
+        let map: serde_json::Value = serde_json::from_str(json)?;
+        if map["infinite"].as_bool() == Some(true) {
+            return Err(TiledError::Unsupported("infinite maps".into()));
+        }
+
+        let mut tilesets = Vec::new();
+        for tileset in map["tilesets"].as_array().into_iter().flatten() {
+            tilesets.push(TiledTileset {
+                first_gid: json_number(&tileset["firstgid"], "tileset firstgid")?,
+                name: tileset["name"].as_str().unwrap_or_default().into(),
+                image: tileset["image"].as_str().map(Into::into),
+                source: tileset["source"].as_str().map(Into::into),
+                columns: json_optional(&tileset["columns"], "tileset columns")?,
+                tile_width: json_optional(&tileset["tilewidth"], "tileset tilewidth")?,
+                tile_height: json_optional(&tileset["tileheight"], "tileset tileheight")?,
+            });
+        }
+
+        let mut layers = Vec::new();
+        tmj_layers(&map["layers"], Scroll::default(), &mut layers)?;
+
+        Ok(Self {
+            tile_width: json_number(&map["tilewidth"], "map tilewidth")?,
+            tile_height: json_number(&map["tileheight"], "map tileheight")?,
+            tilesets,
+            layers,
+        })
+    }
+
+    /// The tileset `gid` belongs to, flags are ignored.
+    pub fn tileset(&self, gid: u32) -> Option<&TiledTileset> {
+        let gid = gid & !FLAGS;
+        self.tilesets
+            .iter()
+            .filter(|tileset| tileset.first_gid <= gid)
+            .max_by_key(|tileset| tileset.first_gid)
+    }
+
+    /// Converts every layer, bottom to top, along with the texture of its tileset.
+    pub fn tile_maps(
+        &self,
+        resources: &ResourceManager,
+    ) -> Result<Vec<(TextureHandle, TileMap)>, TiledError> {
+        self.layers
+            .iter()
+            .map(|layer| self.tile_map(layer, resources))
+            .collect()
+    }
+
+    fn tile_map(
+        &self,
+        layer: &TiledLayer,
+        resources: &ResourceManager,
+    ) -> Result<(TextureHandle, TileMap), TiledError> {
+        let mut used = layer
+            .gids
+            .iter()
+            .filter(|&&gid| gid & !FLAGS != 0)
+            .map(|&gid| self.tileset(gid));
+        let tileset = match used.next() {
+            Some(Some(tileset)) => tileset,
+            Some(None) => {
+                return Err(TiledError::BadData(format!(
+                    "unknown tile in {}",
+                    layer.name
+                )))
+            }
+            // only empty tiles, any tileset will do
+            None => self
+                .tilesets
+                .first()
+                .ok_or(TiledError::Missing("tileset"))?,
+        };
+        if used.any(|other| other.is_none_or(|other| other.first_gid != tileset.first_gid)) {
+            return Err(TiledError::MixedTilesets(layer.name.clone()));
+        }
+        if tileset
+            .tile_width
+            .is_some_and(|width| width != self.tile_width)
+            || tileset
+                .tile_height
+                .is_some_and(|height| height != self.tile_height)
+        {
+            return Err(TiledError::Unsupported(format!(
+                "tileset {} doesn't have the map's {}x{} tiles",
+                tileset.name, self.tile_width, self.tile_height
+            )));
+        }
+        let size = |pixels: u32| u16::try_from(pixels).ok().filter(|&pixels| pixels > 0);
+        let (Some(tile_width), Some(tile_height)) = (size(self.tile_width), size(self.tile_height))
+        else {
+            return Err(TiledError::Unsupported(format!(
+                "{}x{} tiles",
+                self.tile_width, self.tile_height
+            )));
+        };
+
+        let texture = tileset
+            .image
+            .iter()
+            .chain(Some(&tileset.name))
+            .chain(&tileset.source)
+            .find_map(|name| resources.texture_handle(file_stem(name)))
+            .ok_or_else(|| TiledError::MissingTileset(tileset.name.clone()))?;
+        let columns = match tileset.columns {
+            Some(columns) => columns,
+            None => resources
+                .texture(texture)
+                .map_or(0, |texture| texture.width as u32 / tile_width as u32),
+        }
+        .max(1);
+
+        let tiles = layer
+            .gids
+            .iter()
+            .map(|&gid| {
+                let mut tile = Tile::default();
+                tile.layer = 50;
+                match (gid & !FLAGS).checked_sub(tileset.first_gid) {
+                    Some(id) if gid & !FLAGS != 0 => {
+                        tile.x = (id % columns) as u16;
+                        tile.y = (id / columns) as u16;
+                        apply_flip_flags(&mut tile, gid);
+                    }
+                    _ => {
+                        tile.attributes.set(TileAttributes::HIDDEN, true);
+                    }
+                }
+                tile
+            })
+            .collect();
+
+        Ok((
+            texture,
+            TileMap {
+                tiles_x: layer.width,
+                tiles_y: layer.height,
+                tile_width,
+                tile_height,
+                tiles,
+                ..Default::default()
+            },
+        ))
+    }
+
+    /// Adds a tilemap layer for every Tiled layer, the topmost Tiled layer first, after the
+    /// existing layers. Returns how many were added.
+    pub fn import(
+        &self,
+        gl: &glow::Context,
+        graphics: &mut RetroGraphics,
+    ) -> Result<usize, TiledError> {
+        let maps = self.tile_maps(&graphics.resources)?;
+        let count = maps.len();
+        for ((texture, map), layer) in maps.into_iter().zip(&self.layers).rev() {
+            let Some(mut tilemap) = TileMapContext::new(gl, &mut graphics.resources, texture)
+            else {
+                return Err(TiledError::Unsupported(
+                    "tilemap shaders failed to compile".into(),
+                ));
+            };
+            tilemap.map = map;
+            tilemap.scroll = layer.scroll;
+            graphics.layers.push(Layer::TileMap(tilemap));
+        }
+        Ok(count)
+    }
+}
+
+fn tmx_layers(
+    parent: roxmltree::Node,
+    parent_scroll: Scroll,
+    layers: &mut Vec<TiledLayer>,
+) -> Result<(), TiledError> {
+    for node in parent.children().filter(|node| node.is_element()) {
+        let scroll = nested_scroll(
+            parent_scroll,
+            [
+                optional(node.attribute("offsetx"), "offsetx")?,
+                optional(node.attribute("offsety"), "offsety")?,
+                optional(node.attribute("parallaxx"), "parallaxx")?,
+                optional(node.attribute("parallaxy"), "parallaxy")?,
+            ],
+        );
+        match node.tag_name().name() {
+            "group" => tmx_layers(node, scroll, layers)?,
+            "layer" => {
+                let width = number(node.attribute("width"), "layer width")?;
+                let height = number(node.attribute("height"), "layer height")?;
+                let data = node
+                    .children()
+                    .find(|node| node.has_tag_name("data"))
+                    .ok_or(TiledError::Missing("layer data"))?;
+                let gids = match data.attribute("encoding") {
+                    None => data
+                        .children()
+                        .filter(|node| node.has_tag_name("tile"))
+                        .map(|tile| {
+                            optional(tile.attribute("gid"), "tile gid")
+                                .map(Option::unwrap_or_default)
+                        })
+                        .collect::<Result<_, _>>()?,
+                    Some("csv") => csv(data.text().unwrap_or_default())?,
+                    Some("base64") => base64(
+                        data.text().unwrap_or_default(),
+                        data.attribute("compression").unwrap_or_default(),
+                    )?,
+                    Some(encoding) => {
+                        return Err(TiledError::Unsupported(format!("{encoding} encoding")))
+                    }
+                };
+                layers.push(layer(node.attribute("name"), width, height, gids, scroll)?);
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn tmj_layers(
+    parent: &serde_json::Value,
+    parent_scroll: Scroll,
+    layers: &mut Vec<TiledLayer>,
+) -> Result<(), TiledError> {
+    for node in parent.as_array().into_iter().flatten() {
+        let scroll = nested_scroll(
+            parent_scroll,
+            [
+                node["offsetx"].as_f64().map(|value| value as f32),
+                node["offsety"].as_f64().map(|value| value as f32),
+                node["parallaxx"].as_f64().map(|value| value as f32),
+                node["parallaxy"].as_f64().map(|value| value as f32),
+            ],
+        );
+        match node["type"].as_str() {
+            Some("group") => tmj_layers(&node["layers"], scroll, layers)?,
+            Some("tilelayer") => {
+                let width = json_number(&node["width"], "layer width")?;
+                let height = json_number(&node["height"], "layer height")?;
+                let gids = match &node["data"] {
+                    serde_json::Value::Array(gids) => gids
+                        .iter()
+                        .map(|gid| json_number(gid, "tile gid"))
+                        .collect::<Result<_, _>>()?,
+                    serde_json::Value::String(data) => {
+                        base64(data, node["compression"].as_str().unwrap_or_default())?
+                    }
+                    _ => return Err(TiledError::Missing("layer data")),
+                };
+                layers.push(layer(node["name"].as_str(), width, height, gids, scroll)?);
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn layer(
+    name: Option<&str>,
+    width: u16,
+    height: u16,
+    gids: Vec<u32>,
+    scroll: Scroll,
+) -> Result<TiledLayer, TiledError> {
+    let name = name.unwrap_or_default().to_owned();
+    if gids.len() != width as usize * height as usize {
+        return Err(TiledError::BadData(format!(
+            "{name} has {} tiles instead of {width}x{height}",
+            gids.len()
+        )));
+    }
+    Ok(TiledLayer {
+        name,
+        width,
+        height,
+        gids,
+        scroll,
+    })
+}
+
+// Tiled offsets move a layer right and down, the opposite of panning, and groups add their
+// offsets to and multiply their parallax factors with those of their children
+fn nested_scroll(
+    parent: Scroll,
+    [offset_x, offset_y, parallax_x, parallax_y]: [Option<f32>; 4],
+) -> Scroll {
+    Scroll {
+        x: parent.x - offset_x.unwrap_or_default().round() as i32,
+        y: parent.y - offset_y.unwrap_or_default().round() as i32,
+        parallax_x: parent.parallax_x * parallax_x.unwrap_or(1.0),
+        parallax_y: parent.parallax_y * parallax_y.unwrap_or(1.0),
+    }
+}
+
+fn csv(text: &str) -> Result<Vec<u32>, TiledError> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|gid| !gid.is_empty())
+        .map(|gid| {
+            gid.parse()
+                .map_err(|_| TiledError::BadData(format!("{gid} isn't a tile")))
+        })
+        .collect()
+}
+
+fn base64(text: &str, compression: &str) -> Result<Vec<u32>, TiledError> {
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(text)
+        .map_err(|err| TiledError::BadData(err.to_string()))?;
+
+    let mut decompressed = Vec::new();
+    let bytes = match compression {
+        "" => bytes,
+        "zlib" => {
+            flate2::read::ZlibDecoder::new(&bytes[..])
+                .read_to_end(&mut decompressed)
+                .map_err(|err| TiledError::BadData(err.to_string()))?;
+            decompressed
+        }
+        "gzip" => {
+            flate2::read::GzDecoder::new(&bytes[..])
+                .read_to_end(&mut decompressed)
+                .map_err(|err| TiledError::BadData(err.to_string()))?;
+            decompressed
+        }
+        compression => {
+            return Err(TiledError::Unsupported(format!(
+                "{compression} compression"
+            )))
+        }
+    };
+
+    if bytes.len() % 4 != 0 {
+        return Err(TiledError::BadData("length isn't a multiple of 4".into()));
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|gid| u32::from_le_bytes([gid[0], gid[1], gid[2], gid[3]]))
+        .collect())
+}
+
+fn file_stem(path: &str) -> &str {
+    std::path::Path::new(path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(path)
+}
+
+fn number<T: std::str::FromStr>(value: Option<&str>, what: &'static str) -> Result<T, TiledError> {
+    optional(value, what)?.ok_or(TiledError::Missing(what))
+}
+
+fn optional<T: std::str::FromStr>(
+    value: Option<&str>,
+    what: &'static str,
+) -> Result<Option<T>, TiledError> {
+    value
+        .map(|value| value.trim().parse().map_err(|_| TiledError::Missing(what)))
+        .transpose()
+}
+
+fn json_number<T: TryFrom<u64>>(
+    value: &serde_json::Value,
+    what: &'static str,
+) -> Result<T, TiledError> {
+    json_optional(value, what)?.ok_or(TiledError::Missing(what))
+}
+
+fn json_optional<T: TryFrom<u64>>(
+    value: &serde_json::Value,
+    what: &'static str,
+) -> Result<Option<T>, TiledError> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    value
+        .as_u64()
+        .and_then(|value| T::try_from(value).ok())
+        .map(Some)
+        .ok_or(TiledError::Missing(what))
+}
@@ -1,22 +1,210 @@
+use std::collections::HashMap;
+
 use glow::HasContext;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    resources::{ResourceManager, Texture},
-    ScreenContext,
+    map_file::JsonTile,
+    resources::{ResourceManager, Texture, TextureHandle},
+    ScreenContext, Scroll,
 };
 
+/// Integer textures are read with texelFetch and can't be filtered.
+pub(crate) unsafe fn data_texture(gl: &glow::Context) -> glow::Texture {
+    let texture = gl.create_texture().expect("Cannot create texture");
+    gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+    for (parameter, value) in [
+        (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+        (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+        (glow::TEXTURE_MIN_FILTER, glow::NEAREST),
+        (glow::TEXTURE_MAG_FILTER, glow::NEAREST),
+    ] {
+        gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, value as i32);
+    }
+    gl.bind_texture(glow::TEXTURE_2D, None);
+    texture
+}
+
 pub struct TileMapContext {
+    /// Its pan is set from `scroll` whenever `RetroGraphics` composites.
     pub map: TileMap,
+    pub scroll: Scroll,
+    /// Unless it's the default the whole map is drawn per pixel instead of per tile.
+    pub raster: Raster,
+    /// Seconds the tileset's animations have played for, see [`TileMapContext::advance`].
+    pub animation_time: f64,
 
     program: glow::Program,
     vertex_array: glow::VertexArray,
     buffer: glow::Buffer,
     last_buffer_size: usize,
     time_data: Vec<Tile>,
-    pub texture: Texture,
+    pub texture: TextureHandle,
+
+    raster_program: glow::Program,
+    raster_vertex_array: glow::VertexArray,
+    tile_texture: glow::Texture,
+    scanline_texture: glow::Texture,
+    affine_texture: glow::Texture,
+}
+
+/// Effects a tilemap can have. Apart from `edge` they have it drawn per pixel instead of per
+/// tile.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Raster {
+    /// (pan_x, pan_y) added to the pan of each screen line from the top, lines past the end
+    /// aren't offset.
+    pub scanlines: Vec<(i32, i32)>,
+    /// Rotates and scales the whole map.
+    pub affine: Option<Affine>,
+    /// Replaces `affine` for each screen line from the top, lines past the end use `affine`.
+    pub affine_scanlines: Vec<Affine>,
+    /// What's drawn past the map's edges, whichever way it's panned.
+    pub edge: EdgeMode,
+}
+
+impl Raster {
+    /// Whether the map has to be drawn per pixel.
+    pub fn is_active(&self) -> bool {
+        !self.scanlines.is_empty() || self.affine.is_some() || !self.affine_scanlines.is_empty()
+    }
+
+    /// The transform of screen line `y`, if it has one.
+    pub fn affine(&self, y: i32) -> Option<Affine> {
+        usize::try_from(y)
+            .ok()
+            .and_then(|y| self.affine_scanlines.get(y))
+            .or(self.affine.as_ref())
+            .copied()
+    }
+}
+
+/// Maps panned screen pixels onto the map like the SNES's mode 7:
+/// `matrix * (pixel + pan - center) + center`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Affine {
+    /// `[a, b, c, d]` of the matrix `[[a, b], [c, d]]`.
+    pub matrix: [f32; 4],
+    /// The map pixel it rotates and scales around.
+    pub center: [f32; 2],
+}
+
+impl Affine {
+    pub const IDENTITY: Self = Self {
+        matrix: [1.0, 0.0, 0.0, 1.0],
+        center: [0.0, 0.0],
+    };
+
+    /// Draws the map turned `angle` radians clockwise and `scale_x`, `scale_y` times bigger
+    /// around `center`.
+    pub fn rotate_scale(angle: f32, scale_x: f32, scale_y: f32, center: [f32; 2]) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self {
+            matrix: [cos / scale_x, sin / scale_x, -sin / scale_y, cos / scale_y],
+            center,
+        }
+    }
+
+    /// The map pixel drawn for the panned screen pixel `x`, `y`.
+    pub fn apply(&self, x: i32, y: i32) -> (i32, i32) {
+        let [a, b, c, d] = self.matrix;
+        let [center_x, center_y] = self.center;
+        let (x, y) = (x as f32 - center_x, y as f32 - center_y);
+        (
+            (a * x + b * y + center_x).floor() as i32,
+            (c * x + d * y + center_y).floor() as i32,
+        )
+    }
+}
+
+/// A tile of a tileset that cycles through other tiles of it wherever a map places it, see
+/// [`ResourceManager::set_tile_animations`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TileAnimation {
+    /// The sheet tile maps place.
+    pub x: u16,
+    pub y: u16,
+    pub frames: Vec<TileFrame>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TileFrame {
+    /// The sheet tile shown instead.
+    pub x: u16,
+    pub y: u16,
+    /// Seconds it's shown for.
+    pub duration: f32,
+}
+
+impl TileAnimation {
+    /// The sheet tile shown `time` seconds in, it loops.
+    pub fn frame_at(&self, time: f64) -> (u16, u16) {
+        let total: f64 = self
+            .frames
+            .iter()
+            .map(|frame| frame.duration.max(0.0) as f64)
+            .sum();
+        let mut time = if total > 0.0 {
+            time.rem_euclid(total)
+        } else {
+            0.0
+        };
+        for frame in &self.frames {
+            time -= frame.duration.max(0.0) as f64;
+            if time < 0.0 {
+                return (frame.x, frame.y);
+            }
+        }
+        // only when every duration is 0 or rounding ran past the end
+        self.frames
+            .last()
+            .map_or((self.x, self.y), |frame| (frame.x, frame.y))
+    }
 }
 
-#[derive(Clone, Default, PartialEq, Eq)]
+/// The frame every animated tile of a tileset shows at one moment.
+#[derive(Default)]
+pub struct AnimationFrames(HashMap<(u16, u16), (u16, u16)>);
+
+impl AnimationFrames {
+    pub fn new(animations: &[TileAnimation], time: f64) -> Self {
+        Self(
+            animations
+                .iter()
+                .map(|animation| ((animation.x, animation.y), animation.frame_at(time)))
+                .collect(),
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// `tile` showing its current frame.
+    pub fn apply(&self, mut tile: Tile) -> Tile {
+        if let Some(&(x, y)) = self.0.get(&(tile.x, tile.y)) {
+            (tile.x, tile.y) = (x, y);
+        }
+        tile
+    }
+}
+
+/// Which tiles are outside a map, see [`TileMap::tile_at`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EdgeMode {
+    /// The map repeats forever.
+    #[default]
+    Wrap,
+    /// The tiles along the edges repeat outwards.
+    Clamp,
+    /// Everything outside is this tile.
+    Fill(Tile),
+    /// Nothing is drawn outside.
+    Transparent,
+}
+
+#[derive(Clone, PartialEq, Eq)]
 pub struct TileMap {
     pub tiles_x: u16, // the number of tiles actually defined in the array
     pub tiles_y: u16,
@@ -24,10 +212,30 @@ pub struct TileMap {
     pub pan_x: i32, //# of pixels to pan
     pub pan_y: i32,
 
+    /// Pixels per tile, on screen and on the sheet. Turning a tile by 90 or 270 degrees reads
+    /// a `tile_height` by `tile_width` area of the sheet so it still fills its place.
+    pub tile_width: u16,
+    pub tile_height: u16,
+
     pub tiles: Vec<Tile>, // tiles_x * tiles_y long
 }
 
-#[derive(Default, Clone, Copy, PartialEq, Eq)]
+impl Default for TileMap {
+    fn default() -> Self {
+        Self {
+            tiles_x: 0,
+            tiles_y: 0,
+            pan_x: 0,
+            pan_y: 0,
+            tile_width: 8,
+            tile_height: 8,
+            tiles: Vec::new(),
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(try_from = "JsonTile", into = "JsonTile")]
 #[repr(C)]
 pub struct Tile {
     pub x: u16,
@@ -44,7 +252,11 @@ mycelium_bitfield::bitfield! {
         pub const HORIZONTAL: bool;
         pub const VERTICAL: bool;
         pub const ROTATION = 2;
-        const _UNUSED = 12;
+        /// Which of the `ResourceManager` palettes indexed tilesets are drawn with.
+        pub const PALETTE = 4;
+        /// Empty tiles aren't drawn.
+        pub const HIDDEN: bool;
+        const _UNUSED = 7;
     }
 }
 
@@ -68,18 +280,97 @@ impl TileMap {
         //     }
         // }
     }
+
+    /// `tile_width` and `tile_height`, at least 1.
+    pub fn tile_size(&self) -> (i32, i32) {
+        (
+            self.tile_width.max(1) as i32,
+            self.tile_height.max(1) as i32,
+        )
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Option<Tile> {
+        if x < 0 || y < 0 || x >= self.tiles_x as i32 || y >= self.tiles_y as i32 {
+            return None;
+        }
+        Some(self.tiles[(x + y * self.tiles_x as i32) as usize])
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, tile: Tile) {
+        if x < 0 || y < 0 || x >= self.tiles_x as i32 || y >= self.tiles_y as i32 {
+            return;
+        }
+        self.tiles[(x + y * self.tiles_x as i32) as usize] = tile;
+    }
+
+    /// Resizes the map, keeping whatever tiles overlap the old size.
+    pub fn resize(&mut self, tiles_x: u16, tiles_y: u16) {
+        if tiles_x == self.tiles_x && tiles_y == self.tiles_y {
+            return;
+        }
+
+        let mut tiles = vec![Tile::default(); tiles_x as usize * tiles_y as usize];
+        for y in 0..tiles_y.min(self.tiles_y) as usize {
+            for x in 0..tiles_x.min(self.tiles_x) as usize {
+                tiles[x + y * tiles_x as usize] = self.tiles[x + y * self.tiles_x as usize];
+            }
+        }
+
+        self.tiles = tiles;
+        self.tiles_x = tiles_x;
+        self.tiles_y = tiles_y;
+    }
+
+    /// The tile at `x`, `y` where `edge` decides the tiles outside the map.
+    pub fn tile_at(&self, x: i32, y: i32, edge: EdgeMode) -> Option<Tile> {
+        if self.tiles_x == 0 || self.tiles_y == 0 {
+            return None;
+        }
+        let (tiles_x, tiles_y) = (self.tiles_x as i32, self.tiles_y as i32);
+        match edge {
+            EdgeMode::Wrap => self.get(x.rem_euclid(tiles_x), y.rem_euclid(tiles_y)),
+            EdgeMode::Clamp => self.get(x.clamp(0, tiles_x - 1), y.clamp(0, tiles_y - 1)),
+            EdgeMode::Fill(tile) => Some(self.get(x, y).unwrap_or(tile)),
+            EdgeMode::Transparent => self.get(x, y),
+        }
+    }
+
+    /// Replaces the tile at `x`, `y` and every tile equal to it connected through its edges.
+    pub fn flood_fill(&mut self, x: i32, y: i32, tile: Tile) {
+        let Some(target) = self.get(x, y) else {
+            return;
+        };
+        if target == tile {
+            return;
+        }
+
+        let mut pending = vec![(x, y)];
+        while let Some((x, y)) = pending.pop() {
+            if self.get(x, y) != Some(target) {
+                continue;
+            }
+            self.set(x, y, tile);
+            pending.extend([(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]);
+        }
+    }
 }
 
 impl TileMapContext {
+    /// # Safety
+    /// `gl` must be the context this was created with and it can't be painted afterwards.
     pub unsafe fn destroy(&self, gl: &glow::Context) {
         gl.delete_vertex_array(self.vertex_array);
         gl.delete_buffer(self.buffer);
+        gl.delete_vertex_array(self.raster_vertex_array);
+        gl.delete_texture(self.tile_texture);
+        gl.delete_texture(self.scanline_texture);
+        gl.delete_texture(self.affine_texture);
     }
 
     pub fn new(
         gl: &glow::Context,
         resources: &mut ResourceManager,
-        texture: Texture,
+        texture: TextureHandle,
     ) -> Option<Self> {
         let buffer;
         unsafe {
@@ -109,31 +400,270 @@ impl TileMapContext {
                 .expect("Cannot create vertex array");
         }
 
+        let raster_program;
+        let raster_vertex_array;
+        let tile_texture;
+        let scanline_texture;
+        let affine_texture;
+        unsafe {
+            raster_program = resources.get_program(
+                gl,
+                "tilemap_raster",
+                &[
+                    (
+                        crate::resources::ProgramKind::Vertex,
+                        include_str!("../shaders/tilemap/raster.vert"),
+                    ),
+                    (
+                        crate::resources::ProgramKind::Fragment,
+                        include_str!("../shaders/tilemap/raster.frag"),
+                    ),
+                ],
+            )?;
+
+            raster_vertex_array = gl
+                .create_vertex_array()
+                .expect("Cannot create vertex array");
+            tile_texture = data_texture(gl);
+            scanline_texture = data_texture(gl);
+            affine_texture = data_texture(gl);
+        }
+
         let mut map = TileMap {
             tiles_x: 30,
             tiles_y: 26,
-            pan_x: 0,
-            pan_y: 0,
-            tiles: Vec::new(),
+            ..Default::default()
         };
         map.recalc();
         Some(TileMapContext {
             map,
+            scroll: Scroll::default(),
+            raster: Raster::default(),
+            animation_time: 0.0,
             program,
             vertex_array,
             buffer,
             texture,
             time_data: Vec::new(),
             last_buffer_size: 0,
+            raster_program,
+            raster_vertex_array,
+            tile_texture,
+            scanline_texture,
+            affine_texture,
         })
     }
 
-    pub fn paint(&mut self, gl: &glow::Context, screen: &ScreenContext) {
+    /// Moves the tileset's animations `dt` seconds forward.
+    pub fn advance(&mut self, dt: f64) {
+        self.animation_time += dt;
+    }
+
+    /// The frames its tileset's animated tiles are at.
+    pub fn animation_frames(&self, resources: &ResourceManager) -> AnimationFrames {
+        AnimationFrames::new(resources.tile_animations(self.texture), self.animation_time)
+    }
+
+    /// Tiles visible on screen, plus one for partially scrolled in tiles.
+    pub(crate) fn window(&self, screen: &ScreenContext) -> (i32, i32) {
+        let (tile_width, tile_height) = self.map.tile_size();
+        let vis_x = (screen.screen_px_x + tile_width - 1) / tile_width;
+        let vis_y = (screen.screen_px_y + tile_height - 1) / tile_height;
+        (vis_x, vis_y)
+    }
+
+    /// Uploads the visible tiles at their current animation frame and marks the priorities
+    /// they use.
+    pub fn prepare(
+        &mut self,
+        gl: &glow::Context,
+        screen: &ScreenContext,
+        resources: &ResourceManager,
+        priorities: &mut [bool; 256],
+    ) {
+        let frames = self.animation_frames(resources);
+        if self.raster.is_active() {
+            self.prepare_raster(gl, &frames, priorities);
+            return;
+        }
+        let (vis_x, vis_y) = self.window(screen);
+        let (tile_width, tile_height) = self.map.tile_size();
+        // the tile in the top left corner, it can be outside the map
+        let (left, top) = (
+            self.map.pan_x.div_euclid(tile_width),
+            self.map.pan_y.div_euclid(tile_height),
+        );
+        let mut outside = Tile::default();
+        outside.attributes.set(TileAttributes::HIDDEN, true);
+
+        self.time_data.clear();
+        for y in 0..=vis_y {
+            for x in 0..=vis_x {
+                let tile = self
+                    .map
+                    .tile_at(left + x, top + y, self.raster.edge)
+                    .unwrap_or(outside);
+                if !tile.attributes.get(TileAttributes::HIDDEN) {
+                    priorities[tile.layer as usize] = true;
+                }
+                self.time_data.push(frames.apply(tile));
+            }
+        }
+
+        unsafe {
+            gl.bind_vertex_array(Some(self.vertex_array));
+
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.buffer));
+            gl.enable_vertex_attrib_array(2);
+            {
+                let raw_data = std::slice::from_raw_parts(
+                    self.time_data.as_ptr().cast(),
+                    self.time_data.len() * std::mem::size_of::<Tile>(),
+                );
+                if raw_data.len() <= self.last_buffer_size {
+                    gl.buffer_sub_data_u8_slice(glow::ARRAY_BUFFER, 0, raw_data);
+                } else {
+                    gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, raw_data, glow::DYNAMIC_DRAW);
+                    self.last_buffer_size = raw_data.len();
+                }
+            }
+            gl.vertex_attrib_pointer_i32(2, 2, glow::INT, 2 * std::mem::size_of::<i32>() as i32, 0);
+            gl.vertex_attrib_divisor(2, 1);
+            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+        }
+    }
+
+    /// Uploads the whole map and the raster tables, any tile can end up on any line.
+    fn prepare_raster(
+        &mut self,
+        gl: &glow::Context,
+        frames: &AnimationFrames,
+        priorities: &mut [bool; 256],
+    ) {
+        let fill = match self.raster.edge {
+            EdgeMode::Fill(tile) => Some(tile),
+            _ => None,
+        };
+        for tile in self.map.tiles.iter().chain(&fill) {
+            if !tile.attributes.get(TileAttributes::HIDDEN) {
+                priorities[tile.layer as usize] = true;
+            }
+        }
+        self.time_data.clear();
+        self.time_data
+            .extend(self.map.tiles.iter().map(|&tile| frames.apply(tile)));
+        let scanlines: Vec<i32> = self
+            .raster
+            .scanlines
+            .iter()
+            .flat_map(|&(x, y)| [x, y])
+            .collect();
+        // the matrices on the first row and the centers below them
+        let lines = &self.raster.affine_scanlines;
+        let affine: Vec<f32> = lines
+            .iter()
+            .flat_map(|affine| affine.matrix)
+            .chain(
+                lines
+                    .iter()
+                    .flat_map(|affine| [affine.center[0], affine.center[1], 0.0, 0.0]),
+            )
+            .collect();
+
         unsafe {
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.tile_texture));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA16UI as i32,
+                self.map.tiles_x as i32,
+                self.map.tiles_y as i32,
+                0,
+                glow::RGBA_INTEGER,
+                glow::UNSIGNED_SHORT,
+                Some(std::slice::from_raw_parts(
+                    self.time_data.as_ptr().cast(),
+                    self.time_data.len() * std::mem::size_of::<Tile>(),
+                )),
+            );
+
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.scanline_texture));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RG32I as i32,
+                self.raster.scanlines.len() as i32,
+                1,
+                0,
+                glow::RG_INTEGER,
+                glow::INT,
+                Some(std::slice::from_raw_parts(
+                    scanlines.as_ptr().cast(),
+                    scanlines.len() * std::mem::size_of::<i32>(),
+                )),
+            );
+
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.affine_texture));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA32F as i32,
+                lines.len() as i32,
+                2,
+                0,
+                glow::RGBA,
+                glow::FLOAT,
+                Some(std::slice::from_raw_parts(
+                    affine.as_ptr().cast(),
+                    affine.len() * std::mem::size_of::<f32>(),
+                )),
+            );
+            gl.bind_texture(glow::TEXTURE_2D, None);
+        }
+    }
+
+    /// Draws the tiles whose layer is `priority`, [`TileMapContext::prepare`] has to run first.
+    pub fn paint(
+        &self,
+        gl: &glow::Context,
+        screen: &ScreenContext,
+        resources: &ResourceManager,
+        priority: u8,
+    ) {
+        let Some(texture) = resources.texture(self.texture) else {
+            return;
+        };
+        if self.raster.is_active() {
+            self.paint_raster(gl, screen, resources, texture, priority);
+            return;
+        }
+        let (vis_x, vis_y) = self.window(screen);
+        let (tile_width, tile_height) = self.map.tile_size();
+        // how far the top left tile is scrolled out, prepare picked the tiles
+        let (pan_x, pan_y) = (
+            self.map.pan_x.rem_euclid(tile_width),
+            self.map.pan_y.rem_euclid(tile_height),
+        );
+        unsafe {
+            gl.active_texture(glow::TEXTURE1);
+            gl.bind_texture(glow::TEXTURE_2D, resources.palette_texture());
             gl.active_texture(glow::TEXTURE0);
-            gl.bind_texture(glow::TEXTURE_2D, Some(self.texture.texture));
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture.texture));
 
             gl.use_program(Some(self.program));
+            gl.uniform_1_i32(gl.get_uniform_location(self.program, "tex").as_ref(), 0);
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "palettes").as_ref(),
+                1,
+            );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "indexed").as_ref(),
+                texture.indexed as i32,
+            );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "priority").as_ref(),
+                priority as i32,
+            );
             gl.uniform_1_f32(
                 gl.get_uniform_location(self.program, "zoom").as_ref(),
                 screen.zoom,
@@ -147,9 +677,27 @@ impl TileMapContext {
                 gl.get_uniform_location(self.program, "tiles_y").as_ref(),
                 self.map.tiles_y as i32,
             );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "tile_width").as_ref(),
+                tile_width,
+            );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "tile_height")
+                    .as_ref(),
+                tile_height,
+            );
+
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "screen_px_x")
+                    .as_ref(),
+                screen.screen_px_x,
+            );
+            gl.uniform_1_i32(
+                gl.get_uniform_location(self.program, "screen_px_y")
+                    .as_ref(),
+                screen.screen_px_y,
+            );
 
-            let vis_x = (screen.screen_px_x + 7) / 8;
-            let vis_y = (screen.screen_px_y + 7) / 8;
             gl.uniform_1_i32(
                 gl.get_uniform_location(self.program, "tiles_vis_x")
                     .as_ref(),
@@ -161,15 +709,6 @@ impl TileMapContext {
                 vis_y,
             );
 
-            let mut pan_x = self.map.pan_x;
-            if pan_x < 0 {
-                pan_x = self.map.tiles_x as i32 * 8 + pan_x % (self.map.tiles_x as i32 * 8);
-            }
-            let mut pan_y = self.map.pan_y;
-            if pan_y < 0 {
-                pan_y = self.map.tiles_y as i32 * 8 + pan_y % (self.map.tiles_y as i32 * 8);
-            }
-
             gl.uniform_1_i32(
                 gl.get_uniform_location(self.program, "pan_x").as_ref(),
                 pan_x,
@@ -181,46 +720,101 @@ impl TileMapContext {
 
             gl.uniform_1_i32(
                 gl.get_uniform_location(self.program, "map_width").as_ref(),
-                self.texture.width,
+                texture.width,
             );
             gl.uniform_1_i32(
                 gl.get_uniform_location(self.program, "map_height").as_ref(),
-                self.texture.height,
+                texture.height,
             );
 
             gl.bind_vertex_array(Some(self.vertex_array));
+            gl.draw_arrays_instanced(glow::TRIANGLES, 0, 6, (vis_x + 1) * (vis_y + 1));
+        }
+    }
 
-            gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.buffer));
-            gl.enable_vertex_attrib_array(2);
-
-            self.time_data.clear();
-            for y in 0..=vis_y {
-                let index = ((y + pan_y / 8) as isize % self.map.tiles_y as isize)
-                    * self.map.tiles_x as isize;
-
-                for x in 0..=vis_x {
-                    let index = index + (x + pan_x / 8) as isize % self.map.tiles_x as isize;
+    fn paint_raster(
+        &self,
+        gl: &glow::Context,
+        screen: &ScreenContext,
+        resources: &ResourceManager,
+        texture: Texture,
+        priority: u8,
+    ) {
+        if self.map.tiles_x == 0 || self.map.tiles_y == 0 {
+            return;
+        }
+        let (edge, fill) = match self.raster.edge {
+            EdgeMode::Wrap => (0, Tile::default()),
+            EdgeMode::Clamp => (1, Tile::default()),
+            EdgeMode::Fill(tile) => (2, self.animation_frames(resources).apply(tile)),
+            EdgeMode::Transparent => (3, Tile::default()),
+        };
+        let affine = self.raster.affine.unwrap_or(Affine::IDENTITY);
+        let (tile_width, tile_height) = self.map.tile_size();
+        let program = self.raster_program;
+        unsafe {
+            gl.active_texture(glow::TEXTURE4);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.affine_texture));
+            gl.active_texture(glow::TEXTURE3);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.scanline_texture));
+            gl.active_texture(glow::TEXTURE2);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.tile_texture));
+            gl.active_texture(glow::TEXTURE1);
+            gl.bind_texture(glow::TEXTURE_2D, resources.palette_texture());
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture.texture));
 
-                    self.time_data.push(self.map.tiles[index as usize]);
-                }
-            }
-            {
-                let raw_data = std::slice::from_raw_parts(
-                    self.time_data.as_ptr().cast(),
-                    self.time_data.len() * std::mem::size_of::<Tile>(),
-                );
-                if raw_data.len() <= self.last_buffer_size {
-                    gl.buffer_sub_data_u8_slice(glow::ARRAY_BUFFER, 0, raw_data);
-                } else {
-                    gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, raw_data, glow::DYNAMIC_DRAW);
-                    self.last_buffer_size = raw_data.len();
-                }
+            gl.use_program(Some(program));
+            for (name, value) in [
+                ("tex", 0),
+                ("palettes", 1),
+                ("tiles", 2),
+                ("scanlines", 3),
+                ("affine_lines", 4),
+                ("scanline_count", self.raster.scanlines.len() as i32),
+                ("affine_count", self.raster.affine_scanlines.len() as i32),
+                ("transformed", self.raster.affine.is_some() as i32),
+                ("edge", edge),
+                ("indexed", texture.indexed as i32),
+                ("priority", priority as i32),
+                ("tiles_x", self.map.tiles_x as i32),
+                ("tiles_y", self.map.tiles_y as i32),
+                ("tile_width", tile_width),
+                ("tile_height", tile_height),
+                ("screen_px_x", screen.screen_px_x),
+                ("screen_px_y", screen.screen_px_y),
+                ("pan_x", self.map.pan_x),
+                ("pan_y", self.map.pan_y),
+            ] {
+                gl.uniform_1_i32(gl.get_uniform_location(program, name).as_ref(), value);
             }
-            gl.vertex_attrib_pointer_i32(2, 2, glow::INT, 2 * std::mem::size_of::<i32>() as i32, 0);
-            gl.vertex_attrib_divisor(2, 1);
-            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+            gl.uniform_1_f32(
+                gl.get_uniform_location(program, "zoom").as_ref(),
+                screen.zoom,
+            );
+            let [a, b, c, d] = affine.matrix;
+            gl.uniform_4_f32(
+                gl.get_uniform_location(program, "affine_matrix").as_ref(),
+                a,
+                b,
+                c,
+                d,
+            );
+            gl.uniform_2_f32(
+                gl.get_uniform_location(program, "affine_center").as_ref(),
+                affine.center[0],
+                affine.center[1],
+            );
+            gl.uniform_4_u32(
+                gl.get_uniform_location(program, "fill_tile").as_ref(),
+                fill.x as u32,
+                fill.y as u32,
+                fill.layer as u32,
+                fill.attributes.bits() as u32,
+            );
 
-            gl.draw_arrays_instanced(glow::TRIANGLES, 0, 6, (vis_x + 1) * (vis_y + 1));
+            gl.bind_vertex_array(Some(self.raster_vertex_array));
+            gl.draw_arrays(glow::TRIANGLES, 0, 6);
         }
     }
 }
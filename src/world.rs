@@ -0,0 +1,289 @@
+//! Tilemaps too big to keep in memory at once, split into square chunks that are loaded as
+//! they come into view and saved and dropped once they're far away.
+//!
+//! Every chunk is a binary map file (see [`crate::map_file`]) named `{x}_{y}.tmap` after its
+//! chunk coordinates, all in one directory. Chunks without a file are empty.
+//!
+//! Only a window of the world a little bigger than the screen is handed to a
+//! [`TileMapContext`], wrapping around like the tile buffer of a console so scrolling only
+//! changes the rows and columns that come into view.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use crate::{
+    map_file::{MapFileError, TileMapFile},
+    tilemap::{Tile, TileAttributes, TileMap, TileMapContext},
+    Camera, ScreenContext,
+};
+
+/// Tiles along each side of a chunk.
+pub const CHUNK_TILES: u16 = 32;
+
+#[derive(Debug)]
+pub enum WorldError {
+    Io(std::io::Error),
+    MapFile((i32, i32), MapFileError),
+    /// A chunk file isn't [`CHUNK_TILES`] square or its tiles aren't the world's size.
+    BadChunk((i32, i32)),
+}
+
+impl std::fmt::Display for WorldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorldError::Io(err) => write!(f, "{err}"),
+            WorldError::MapFile((x, y), err) => write!(f, "chunk {x}, {y}: {err}"),
+            WorldError::BadChunk((x, y)) => write!(f, "chunk {x}, {y} doesn't fit the world"),
+        }
+    }
+}
+
+impl std::error::Error for WorldError {}
+
+impl From<std::io::Error> for WorldError {
+    fn from(err: std::io::Error) -> Self {
+        WorldError::Io(err)
+    }
+}
+
+struct Chunk {
+    map: TileMap,
+    /// Changed since it was last saved.
+    dirty: bool,
+}
+
+pub struct WorldMap {
+    /// Where chunks are loaded from and saved to. Without one chunks only live in memory and
+    /// are never unloaded by [`WorldMap::stream`].
+    pub directory: Option<PathBuf>,
+    /// Written into the chunk files.
+    pub tileset: String,
+    pub tile_width: u16,
+    pub tile_height: u16,
+    /// Chunks this far around the view are loaded ahead of time, the ones more than a chunk
+    /// further away are unloaded.
+    pub margin: i32,
+    chunks: HashMap<(i32, i32), Chunk>,
+}
+
+impl WorldMap {
+    pub fn new(tileset: impl Into<String>, directory: Option<PathBuf>) -> Self {
+        Self {
+            directory,
+            tileset: tileset.into(),
+            tile_width: 8,
+            tile_height: 8,
+            margin: 1,
+            chunks: HashMap::new(),
+        }
+    }
+
+    /// The chunk tile `x`, `y` is in.
+    pub fn chunk_of(x: i32, y: i32) -> (i32, i32) {
+        (
+            x.div_euclid(CHUNK_TILES as i32),
+            y.div_euclid(CHUNK_TILES as i32),
+        )
+    }
+
+    pub fn is_loaded(&self, chunk: (i32, i32)) -> bool {
+        self.chunks.contains_key(&chunk)
+    }
+
+    /// The chunks in memory, in no particular order.
+    pub fn loaded(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    /// The tile at `x`, `y`, none if its chunk isn't loaded.
+    pub fn get(&self, x: i32, y: i32) -> Option<Tile> {
+        let chunk = self.chunks.get(&Self::chunk_of(x, y))?;
+        chunk.map.get(
+            x.rem_euclid(CHUNK_TILES as i32),
+            y.rem_euclid(CHUNK_TILES as i32),
+        )
+    }
+
+    /// Replaces the tile at `x`, `y`, loading its chunk first if it isn't.
+    pub fn set(&mut self, x: i32, y: i32, tile: Tile) -> Result<(), WorldError> {
+        let chunk = self.load(Self::chunk_of(x, y))?;
+        chunk.map.set(
+            x.rem_euclid(CHUNK_TILES as i32),
+            y.rem_euclid(CHUNK_TILES as i32),
+            tile,
+        );
+        chunk.dirty = true;
+        Ok(())
+    }
+
+    fn path(&self, (x, y): (i32, i32)) -> Option<PathBuf> {
+        Some(self.directory.as_ref()?.join(format!("{x}_{y}.tmap")))
+    }
+
+    /// Reads `chunk` from its file unless it's loaded already, it's empty if there's no file.
+    fn load(&mut self, chunk: (i32, i32)) -> Result<&mut Chunk, WorldError> {
+        if !self.chunks.contains_key(&chunk) {
+            let map = match self.path(chunk).map(std::fs::read) {
+                Some(Ok(bytes)) => self.check(chunk, &bytes)?,
+                Some(Err(err)) if err.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(err.into())
+                }
+                _ => self.empty_chunk(),
+            };
+            self.chunks.insert(chunk, Chunk { map, dirty: false });
+        }
+        Ok(self.chunks.get_mut(&chunk).expect("Loaded above"))
+    }
+
+    fn check(&self, chunk: (i32, i32), bytes: &[u8]) -> Result<TileMap, WorldError> {
+        let file = TileMapFile::load(bytes).map_err(|err| WorldError::MapFile(chunk, err))?;
+        let map = file.map;
+        if map.tiles_x != CHUNK_TILES
+            || map.tiles_y != CHUNK_TILES
+            || (map.tile_width, map.tile_height) != (self.tile_width, self.tile_height)
+        {
+            return Err(WorldError::BadChunk(chunk));
+        }
+        Ok(map)
+    }
+
+    fn empty_chunk(&self) -> TileMap {
+        let mut empty = Tile::default();
+        empty.attributes.set(TileAttributes::HIDDEN, true);
+        TileMap {
+            tiles_x: CHUNK_TILES,
+            tiles_y: CHUNK_TILES,
+            tile_width: self.tile_width,
+            tile_height: self.tile_height,
+            tiles: vec![empty; CHUNK_TILES as usize * CHUNK_TILES as usize],
+            ..Default::default()
+        }
+    }
+
+    fn save_chunk(&mut self, chunk: (i32, i32)) -> Result<(), WorldError> {
+        let Some(path) = self.path(chunk) else {
+            return Ok(());
+        };
+        let Some(loaded) = self.chunks.get_mut(&chunk).filter(|loaded| loaded.dirty) else {
+            return Ok(());
+        };
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        let file = TileMapFile {
+            tileset: self.tileset.clone(),
+            map: loaded.map.clone(),
+        };
+        std::fs::write(path, file.to_binary())?;
+        loaded.dirty = false;
+        Ok(())
+    }
+
+    /// Writes every chunk that changed since it was last saved.
+    pub fn save(&mut self) -> Result<(), WorldError> {
+        let chunks: Vec<_> = self.loaded().collect();
+        for chunk in chunks {
+            self.save_chunk(chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Saves `chunk` if it changed and drops it from memory.
+    pub fn unload(&mut self, chunk: (i32, i32)) -> Result<(), WorldError> {
+        self.save_chunk(chunk)?;
+        self.chunks.remove(&chunk);
+        Ok(())
+    }
+
+    /// Loads the chunks around the `tiles_x` by `tiles_y` tiles from `left`, `top` on and
+    /// unloads the ones far away from them.
+    pub fn stream(
+        &mut self,
+        left: i32,
+        top: i32,
+        tiles_x: i32,
+        tiles_y: i32,
+    ) -> Result<(), WorldError> {
+        let margin = self.margin.max(0);
+        let (first_x, first_y) = Self::chunk_of(left, top);
+        let (last_x, last_y) = Self::chunk_of(left + tiles_x.max(1) - 1, top + tiles_y.max(1) - 1);
+        for y in first_y - margin..=last_y + margin {
+            for x in first_x - margin..=last_x + margin {
+                self.load((x, y))?;
+            }
+        }
+
+        if self.directory.is_none() {
+            return Ok(());
+        }
+        let keep = margin + 1;
+        let far: Vec<_> = self
+            .loaded()
+            .filter(|&(x, y)| {
+                x < first_x - keep || x > last_x + keep || y < first_y - keep || y > last_y + keep
+            })
+            .collect();
+        for chunk in far {
+            self.unload(chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Fills `map` with `tiles_x` by `tiles_y` tiles of the world starting at the tile in the
+    /// top left corner of its pan. World tile `x`, `y` goes to `x mod tiles_x`, `y mod tiles_y`
+    /// so drawn with [`EdgeMode::Wrap`] it shows the world, as long as it's bigger than the
+    /// screen by a tile. Tiles of chunks that aren't loaded are hidden.
+    ///
+    /// [`EdgeMode::Wrap`]: crate::tilemap::EdgeMode::Wrap
+    pub fn fill_window(&self, map: &mut TileMap, tiles_x: u16, tiles_y: u16) {
+        map.tile_width = self.tile_width;
+        map.tile_height = self.tile_height;
+        map.resize(tiles_x, tiles_y);
+        let (tile_width, tile_height) = map.tile_size();
+        let (left, top) = (
+            map.pan_x.div_euclid(tile_width),
+            map.pan_y.div_euclid(tile_height),
+        );
+        let (tiles_x, tiles_y) = (tiles_x as i32, tiles_y as i32);
+        let mut hidden = Tile::default();
+        hidden.attributes.set(TileAttributes::HIDDEN, true);
+        for y in top..top + tiles_y {
+            for x in left..left + tiles_x {
+                let tile = self.get(x, y).unwrap_or(hidden);
+                map.set(x.rem_euclid(tiles_x), y.rem_euclid(tiles_y), tile);
+            }
+        }
+    }
+
+    /// Streams the chunks around what `tilemap` shows with the camera at `camera` and fills
+    /// its map with the visible window, see [`WorldMap::fill_window`]. The window only lines
+    /// up with the world if `tilemap` keeps the default [`EdgeMode::Wrap`], other edge modes
+    /// are left alone and draw the window as it is. Raster effects only see the window.
+    ///
+    /// [`EdgeMode::Wrap`]: crate::tilemap::EdgeMode::Wrap
+    pub fn update(
+        &mut self,
+        tilemap: &mut TileMapContext,
+        screen: &ScreenContext,
+        camera: Camera,
+    ) -> Result<(), WorldError> {
+        (tilemap.map.pan_x, tilemap.map.pan_y) = tilemap.scroll.pan(camera);
+        tilemap.map.tile_width = self.tile_width;
+        tilemap.map.tile_height = self.tile_height;
+
+        let (tile_width, tile_height) = tilemap.map.tile_size();
+        let (vis_x, vis_y) = tilemap.window(screen);
+        let (tiles_x, tiles_y) = (vis_x + 1, vis_y + 1);
+        self.stream(
+            tilemap.map.pan_x.div_euclid(tile_width),
+            tilemap.map.pan_y.div_euclid(tile_height),
+            tiles_x,
+            tiles_y,
+        )?;
+        self.fill_window(
+            &mut tilemap.map,
+            tiles_x.min(u16::MAX as i32) as u16,
+            tiles_y.min(u16::MAX as i32) as u16,
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+use graphics_test::{
+    animation::{PlaybackMode, SpriteClip, SpriteFrame, SpritePlayback},
+    sprites::{Sprite, SpriteAttributes},
+    tilemap::{AnimationFrames, Tile, TileAnimation, TileFrame},
+};
+
+fn water() -> TileAnimation {
+    TileAnimation {
+        x: 2,
+        y: 1,
+        frames: vec![
+            TileFrame {
+                x: 2,
+                y: 1,
+                duration: 0.5,
+            },
+            TileFrame {
+                x: 3,
+                y: 1,
+                duration: 0.25,
+            },
+            TileFrame {
+                x: 0,
+                y: 4,
+                duration: 0.25,
+            },
+        ],
+    }
+}
+
+#[test]
+fn frames_loop_over_their_durations() {
+    let water = water();
+    assert_eq!(water.frame_at(0.0), (2, 1));
+    assert_eq!(water.frame_at(0.49), (2, 1));
+    assert_eq!(water.frame_at(0.5), (3, 1));
+    assert_eq!(water.frame_at(0.8), (0, 4));
+    assert_eq!(water.frame_at(1.1), (2, 1));
+    assert_eq!(water.frame_at(-0.1), (0, 4));
+
+    let still = TileAnimation {
+        frames: Vec::new(),
+        ..water
+    };
+    assert_eq!(still.frame_at(3.0), (2, 1));
+}
+
+#[test]
+fn only_animated_tiles_change() {
+    let frames = AnimationFrames::new(&[water()], 0.6);
+    let mut tile = Tile::default();
+    tile.x = 2;
+    tile.y = 1;
+    tile.layer = 9;
+    let animated = frames.apply(tile);
+    assert_eq!((animated.x, animated.y, animated.layer), (3, 1, 9));
+
+    tile.y = 2;
+    let still = frames.apply(tile);
+    assert_eq!((still.x, still.y), (2, 2));
+}
+
+/// Three frames a second long, each one tile further right and a pixel further down.
+fn walk() -> SpriteClip {
+    SpriteClip {
+        frames: (0..3)
+            .map(|frame| SpriteFrame {
+                tx: 4 + frame,
+                ty: 2,
+                x_size: 1,
+                flip_h: frame == 2,
+                duration: 1.0,
+                offset_y: frame as i32,
+                ..Default::default()
+            })
+            .collect(),
+    }
+}
+
+/// The frame after each second of playback.
+fn frames(mode: PlaybackMode, seconds: usize) -> Vec<usize> {
+    let clip = walk();
+    let mut playback = SpritePlayback::new("walk", mode);
+    (0..seconds)
+        .map(|_| {
+            playback.advance(&clip, 1.0);
+            playback.frame()
+        })
+        .collect()
+}
+
+#[test]
+fn playback_modes() {
+    assert_eq!(frames(PlaybackMode::Loop, 5), [1, 2, 0, 1, 2]);
+    assert_eq!(frames(PlaybackMode::PingPong, 6), [1, 2, 1, 0, 1, 2]);
+    assert_eq!(frames(PlaybackMode::Once, 4), [1, 2, 2, 2]);
+
+    let clip = walk();
+    let mut playback = SpritePlayback::new("walk", PlaybackMode::Once);
+    assert!(playback.advance(&clip, 2.5));
+    assert!(!playback.is_finished());
+    assert!(!playback.advance(&clip, 0.5));
+    assert!(playback.is_finished());
+}
+
+#[test]
+fn long_steps_skip_whole_cycles() {
+    let clip = walk();
+    let mut playback = SpritePlayback::new("walk", PlaybackMode::Loop);
+    playback.advance(&clip, 3e12 + 1.5);
+    assert_eq!(playback.frame(), 1);
+
+    // 0, 1, 2, 1 takes 4 seconds and keeps going the same way
+    let mut playback = SpritePlayback::new("walk", PlaybackMode::PingPong);
+    playback.advance(&clip, 4e12 + 2.5);
+    assert_eq!(playback.frame(), 2);
+    playback.advance(&clip, 4e12 + 1.0);
+    assert_eq!(playback.frame(), 1);
+    playback.advance(&clip, 1.0);
+    assert_eq!(playback.frame(), 0);
+
+    let mut playback = SpritePlayback::new("walk", PlaybackMode::Once);
+    assert!(playback.advance(&clip, 1e12));
+    assert!(playback.is_finished());
+    assert_eq!(playback.frame(), 2);
+
+    // would take a billion billion steps one frame at a time
+    let mut blink = clip.clone();
+    for frame in &mut blink.frames {
+        frame.duration = 1e-9;
+    }
+    for mode in [PlaybackMode::Loop, PlaybackMode::PingPong] {
+        let mut playback = SpritePlayback::new("blink", mode);
+        playback.advance(&blink, 1e9);
+        assert!(playback.frame() < 3);
+    }
+}
+
+#[test]
+fn frames_change_the_sprite_and_offset_it() {
+    let clip = walk();
+    let mut sprite = Sprite::default();
+    sprite.x = 20;
+    sprite.y = 30;
+    sprite.layer = 6;
+    sprite.palette = 3;
+
+    let mut playback = SpritePlayback::new("walk", PlaybackMode::Loop);
+    playback.advance(&clip, 2.0);
+    playback.apply(&clip, &mut sprite);
+    assert_eq!((sprite.tx, sprite.ty, sprite.x, sprite.y), (6, 2, 20, 32));
+    assert_eq!(sprite.size(), (16, 8));
+    assert!(sprite.attribute.get(SpriteAttributes::HORIZONTAL));
+    assert_eq!((sprite.palette, sprite.layer), (3, 6));
+
+    // offsets don't add up, they're from where the sprite started
+    playback.advance(&clip, 1.0);
+    playback.apply(&clip, &mut sprite);
+    assert_eq!((sprite.tx, sprite.x, sprite.y), (4, 20, 30));
+    assert!(!sprite.attribute.get(SpriteAttributes::HORIZONTAL));
+}
+
+#[test]
+fn frames_are_at_most_32_pixels() {
+    let json = r#"{ "frames": [{ "tx": 1, "x_size": 4, "duration": 0.1 }] }"#;
+    assert!(serde_json::from_str::<SpriteClip>(json).is_err());
+    let json = r#"{ "frames": [{ "tx": 1, "x_size": 3, "duration": 0.1 }] }"#;
+    assert!(serde_json::from_str::<SpriteClip>(json).is_ok());
+
+    let clip = SpriteClip {
+        frames: vec![SpriteFrame {
+            x_size: 9,
+            y_size: 1,
+            duration: 1.0,
+            ..Default::default()
+        }],
+    };
+    let mut sprite = Sprite::default();
+    SpritePlayback::new("big", PlaybackMode::Loop).apply(&clip, &mut sprite);
+    assert_eq!(sprite.size(), (32, 16));
+}
@@ -0,0 +1,119 @@
+use graphics_test::{
+    animation::{PlaybackMode, SpriteFrame},
+    aseprite::{AsepriteDirection, AsepriteError, AsepriteSheet},
+};
+
+/// Four 16x8 frames in a row, the last one trimmed by 8 pixels on the left.
+const HASH: &str = r##"{ "frames": {
+    "walk 10.aseprite": { "frame": { "x": 0, "y": 8, "w": 16, "h": 8 }, "rotated": false, "trimmed": false,
+        "spriteSourceSize": { "x": 0, "y": 0, "w": 16, "h": 8 }, "sourceSize": { "w": 16, "h": 8 }, "duration": 100 },
+    "walk 2.aseprite": { "frame": { "x": 16, "y": 8, "w": 16, "h": 8 }, "rotated": false, "trimmed": false,
+        "spriteSourceSize": { "x": 0, "y": 0, "w": 16, "h": 8 }, "sourceSize": { "w": 16, "h": 8 }, "duration": 150 },
+    "walk 3.aseprite": { "frame": { "x": 32, "y": 8, "w": 16, "h": 8 }, "rotated": false, "trimmed": false,
+        "spriteSourceSize": { "x": 0, "y": 0, "w": 16, "h": 8 }, "sourceSize": { "w": 16, "h": 8 }, "duration": 100 },
+    "walk 1.aseprite": { "frame": { "x": 48, "y": 8, "w": 8, "h": 8 }, "rotated": false, "trimmed": true,
+        "spriteSourceSize": { "x": 8, "y": 0, "w": 8, "h": 8 }, "sourceSize": { "w": 16, "h": 8 }, "duration": 250 }
+  },
+  "meta": {
+    "app": "https://www.aseprite.org/", "image": "art/walk.png", "format": "RGBA8888",
+    "size": { "w": 64, "h": 16 }, "scale": "1",
+    "frameTags": [
+      { "name": "walk", "from": 0, "to": 2, "direction": "pingpong", "color": "#000000ff" },
+      { "name": "turn", "from": 1, "to": 3, "direction": "reverse", "repeat": "1", "color": "#000000ff" }
+    ],
+    "layers": [{ "name": "Layer 1", "opacity": 255, "blendMode": "normal" }],
+    "slices": [
+      { "name": "hitbox", "color": "#0000ffff", "keys": [
+        { "frame": 0, "bounds": { "x": 2, "y": 1, "w": 12, "h": 7 } },
+        { "frame": 2, "bounds": { "x": 4, "y": 1, "w": 8, "h": 7 }, "pivot": { "x": 4, "y": 7 } }
+      ] }
+    ]
+  }
+}"##;
+
+#[test]
+fn hash_sheets_keep_frame_order() {
+    let sheet = AsepriteSheet::parse(HASH.as_bytes()).unwrap();
+    assert_eq!(sheet.image.as_deref(), Some("art/walk.png"));
+    let names: Vec<_> = sheet
+        .frames
+        .iter()
+        .map(|frame| frame.name.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "walk 10.aseprite",
+            "walk 2.aseprite",
+            "walk 3.aseprite",
+            "walk 1.aseprite"
+        ]
+    );
+
+    let frames = sheet.sprite_frames().unwrap();
+    assert_eq!(
+        frames[1],
+        SpriteFrame {
+            tx: 2,
+            ty: 1,
+            x_size: 1,
+            y_size: 0,
+            duration: 0.15,
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        (frames[3].tx, frames[3].x_size, frames[3].offset_x),
+        (6, 0, 8)
+    );
+
+    let hitbox = &sheet.slices[0];
+    assert_eq!(hitbox.key(1).map(|key| key.width), Some(12));
+    assert_eq!(hitbox.key(3).and_then(|key| key.pivot), Some((4, 7)));
+}
+
+#[test]
+fn tags_become_clips() {
+    let sheet = AsepriteSheet::parse(HASH.as_bytes()).unwrap();
+    let clips = sheet.clips().unwrap();
+    let tiles =
+        |name: &str| -> Vec<u16> { clips[name].frames.iter().map(|frame| frame.tx).collect() };
+    assert_eq!(tiles("walk"), [0, 2, 4]);
+    assert_eq!(tiles("turn"), [6, 4, 2]);
+
+    assert_eq!(sheet.tags[0].direction, AsepriteDirection::PingPong);
+    assert_eq!(sheet.tags[0].mode(), PlaybackMode::PingPong);
+    assert_eq!(sheet.tags[1].mode(), PlaybackMode::Once);
+}
+
+#[test]
+fn array_sheets() {
+    let json = r#"{ "frames": [
+        { "filename": "idle", "frame": { "x": 8, "y": 0, "w": 32, "h": 24 }, "duration": 500 }
+      ], "meta": {} }"#;
+    let sheet = AsepriteSheet::parse(json.as_bytes()).unwrap();
+    assert_eq!(sheet.frames[0].name, "idle");
+    let frame = sheet.sprite_frames().unwrap()[0];
+    assert_eq!((frame.tx, frame.x_size, frame.y_size), (1, 3, 2));
+    assert!(sheet.clips().unwrap().is_empty());
+}
+
+#[test]
+fn frames_off_the_grid_are_rejected() {
+    for rect in [
+        r#""x": 4, "y": 0, "w": 8, "h": 8"#,
+        r#""x": 0, "y": 0, "w": 12, "h": 8"#,
+        r#""x": 0, "y": 0, "w": 8, "h": 40"#,
+    ] {
+        let json = format!(r#"{{ "frames": [{{ "frame": {{ {rect} }}, "duration": 100 }}] }}"#);
+        let sheet = AsepriteSheet::parse(json.as_bytes()).unwrap();
+        assert!(matches!(
+            sheet.sprite_frames(),
+            Err(AsepriteError::BadFrame(_))
+        ));
+    }
+
+    let missing = HASH.replace(r#""to": 3"#, r#""to": 4"#);
+    let sheet = AsepriteSheet::parse(missing.as_bytes()).unwrap();
+    assert!(matches!(sheet.clips(), Err(AsepriteError::BadTag(name)) if name == "turn"));
+}
@@ -0,0 +1,110 @@
+use graphics_test::bitmap::{Bitmap, Color};
+
+const RED: Color = [255, 0, 0, 255];
+
+/// The coordinates of every pixel that isn't transparent, row by row.
+fn drawn(bitmap: &Bitmap) -> Vec<(i32, i32)> {
+    let mut drawn = Vec::new();
+    for y in 0..bitmap.height() {
+        for x in 0..bitmap.width() {
+            if bitmap.get_pixel(x, y) != Some([0; 4]) {
+                drawn.push((x, y));
+            }
+        }
+    }
+    drawn
+}
+
+#[test]
+fn lines_clip_to_the_edges() {
+    let mut bitmap = Bitmap::new(4, 3);
+    bitmap.line(-2, 1, 10, 1, RED);
+    assert_eq!(drawn(&bitmap), [(0, 1), (1, 1), (2, 1), (3, 1)]);
+
+    // steep, backwards and starting below the bitmap
+    let mut bitmap = Bitmap::new(4, 3);
+    bitmap.line(2, 5, 2, -5, RED);
+    assert_eq!(drawn(&bitmap), [(2, 0), (2, 1), (2, 2)]);
+
+    let mut bitmap = Bitmap::new(4, 3);
+    bitmap.line(-1, -1, 3, 3, RED);
+    assert_eq!(drawn(&bitmap), [(0, 0), (1, 1), (2, 2)]);
+
+    let mut bitmap = Bitmap::new(4, 3);
+    bitmap.line(-5, -5, -1, 10, RED);
+    assert!(drawn(&bitmap).is_empty());
+}
+
+#[test]
+fn rects_clip_to_the_edges() {
+    let mut bitmap = Bitmap::new(4, 4);
+    bitmap.rect(-1, 1, 3, 5, RED);
+    // the left and bottom sides are outside
+    assert_eq!(drawn(&bitmap), [(0, 1), (1, 1), (1, 2), (1, 3)]);
+
+    let mut bitmap = Bitmap::new(4, 4);
+    bitmap.rect(1, 1, 0, 2, RED);
+    bitmap.rect(1, 1, 2, -2, RED);
+    assert!(drawn(&bitmap).is_empty());
+
+    let mut bitmap = Bitmap::new(4, 4);
+    bitmap.fill_rect(2, -3, 10, 5, RED);
+    assert_eq!(drawn(&bitmap), [(2, 0), (3, 0), (2, 1), (3, 1)]);
+
+    let mut bitmap = Bitmap::new(4, 4);
+    bitmap.fill_rect(-10, 0, 10, 4, RED);
+    bitmap.fill_rect(0, 4, 4, 4, RED);
+    bitmap.fill_rect(1, 1, -1, 2, RED);
+    assert!(drawn(&bitmap).is_empty());
+}
+
+#[test]
+fn blits_clip_to_the_edges() {
+    let block: Vec<Color> = (0..9).map(|i| [i, 0, 0, 255]).collect();
+    let mut bitmap = Bitmap::new(4, 4);
+    bitmap.blit(-1, 2, 3, 3, &block);
+    assert_eq!(drawn(&bitmap), [(0, 2), (1, 2), (0, 3), (1, 3)]);
+    assert_eq!(bitmap.get_pixel(0, 2), Some([1, 0, 0, 255]));
+    assert_eq!(bitmap.get_pixel(1, 3), Some([5, 0, 0, 255]));
+
+    let mut bitmap = Bitmap::new(4, 4);
+    bitmap.blit(4, 0, 3, 3, &block);
+    bitmap.blit(0, -3, 3, 3, &block);
+    bitmap.blit(0, 0, 0, 3, &block);
+    assert!(drawn(&bitmap).is_empty());
+}
+
+#[test]
+fn short_blits_copy_what_is_there() {
+    let block: Vec<Color> = (0..4).map(|i| [i, 0, 0, 255]).collect();
+    let mut bitmap = Bitmap::new(4, 4);
+    // a row and a third of a 3x3 block
+    bitmap.blit(1, 1, 3, 3, &block);
+    assert_eq!(drawn(&bitmap), [(1, 1), (2, 1), (3, 1), (1, 2)]);
+    assert_eq!(bitmap.get_pixel(1, 2), Some([3, 0, 0, 255]));
+
+    let mut bitmap = Bitmap::new(4, 4);
+    bitmap.blit(0, 0, 2, 2, &[]);
+    assert!(drawn(&bitmap).is_empty());
+}
+
+#[test]
+fn resize_keeps_the_overlap() {
+    let mut bitmap = Bitmap::new(3, 3);
+    bitmap.put_pixel(0, 0, RED);
+    bitmap.put_pixel(1, 2, RED);
+    bitmap.put_pixel(2, 1, RED);
+
+    bitmap.resize(2, 5);
+    assert_eq!((bitmap.width(), bitmap.height()), (2, 5));
+    assert_eq!(bitmap.pixels().len(), 10);
+    assert_eq!(drawn(&bitmap), [(0, 0), (1, 2)]);
+
+    bitmap.resize(4, 1);
+    assert_eq!(drawn(&bitmap), [(0, 0)]);
+
+    bitmap.resize(-1, 3);
+    assert_eq!((bitmap.width(), bitmap.height()), (0, 3));
+    assert!(bitmap.pixels().is_empty());
+    assert_eq!(bitmap.get_pixel(0, 0), None);
+}
@@ -0,0 +1,116 @@
+use graphics_test::{
+    map_file::{MapFileError, TileMapFile},
+    tilemap::{Tile, TileAttributes, TileMap},
+};
+
+fn file() -> TileMapFile {
+    let mut map = TileMap {
+        tiles_x: 3,
+        tiles_y: 2,
+        tile_width: 16,
+        tile_height: 8,
+        ..Default::default()
+    };
+    map.recalc();
+    let mut tile = Tile::default();
+    tile.x = 7;
+    tile.y = 300;
+    tile.layer = 4;
+    tile.attributes
+        .set(TileAttributes::HORIZONTAL, true)
+        .set(TileAttributes::ROTATION, 3)
+        .set(TileAttributes::PALETTE, 15);
+    map.set(1, 1, tile);
+
+    TileMapFile {
+        tileset: "spritesheet".into(),
+        map,
+    }
+}
+
+#[test]
+fn binary_round_trip() {
+    let file = file();
+    let bytes = file.to_binary();
+    assert_eq!(bytes.len(), 16 + "spritesheet".len() + 6 * 8);
+    assert!(TileMapFile::load(&bytes).unwrap() == file);
+}
+
+#[test]
+fn json_round_trip() {
+    let file = file();
+    let json = file.to_json();
+    assert!(TileMapFile::from_json(&json).unwrap() == file);
+    assert!(TileMapFile::load(json.as_bytes()).unwrap() == file);
+}
+
+#[test]
+fn bad_files_are_rejected() {
+    let bytes = file().to_binary();
+    assert!(matches!(
+        TileMapFile::load(&bytes[..bytes.len() - 1]),
+        Err(MapFileError::Truncated)
+    ));
+
+    let json = r#"{ "version": 1, "tileset": "", "tiles_x": 2, "tiles_y": 2, "tiles": [] }"#;
+    assert!(matches!(
+        TileMapFile::from_json(json),
+        Err(MapFileError::WrongTileCount {
+            expected: 4,
+            found: 0
+        })
+    ));
+
+    let json = r#"{ "version": 2, "tileset": "", "tiles_x": 2, "tiles_y": 1, "tiles": [
+        { "x": 0, "y": 0, "layer": 0 }, { "x": 0, "y": 0, "layer": 0, "rotation": 5 } ] }"#;
+    assert!(matches!(
+        TileMapFile::from_json(json),
+        Err(MapFileError::BadTile {
+            index: 1,
+            field: "rotation"
+        })
+    ));
+
+    // a header claiming far more tiles than the file holds
+    let mut bytes = b"RTMP".to_vec();
+    for value in [2u16, u16::MAX, u16::MAX, 8, 8, 0] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    assert!(matches!(
+        TileMapFile::load(&bytes),
+        Err(MapFileError::Truncated)
+    ));
+
+    // what the JSON form can't hold, a reserved byte or unused attribute bits
+    for (tile, field) in [
+        ([0, 0, 0, 0, 0, 1, 0, 0], "reserved"),
+        ([0, 0, 0, 0, 0, 0, 0, 0x80], "attributes"),
+    ] {
+        let mut bytes = b"RTMP".to_vec();
+        for value in [2u16, 1, 1, 8, 8, 0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&tile);
+        assert!(matches!(
+            TileMapFile::load(&bytes),
+            Err(MapFileError::BadTile { index: 0, field: found }) if found == field
+        ));
+    }
+}
+
+#[test]
+fn version_1_files_have_8x8_tiles() {
+    let mut bytes = b"RTMP".to_vec();
+    for value in [1u16, 1, 1, 0, 5, 2] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes.extend_from_slice(&[7, 0, 0, 0]);
+    let file = TileMapFile::load(&bytes).unwrap();
+    assert_eq!((file.map.tile_width, file.map.tile_height), (8, 8));
+    assert_eq!((file.map.tiles[0].x, file.map.tiles[0].y), (5, 2));
+    assert_eq!(file.map.tiles[0].layer, 7);
+
+    let json = r#"{ "version": 1, "tileset": "", "tiles_x": 0, "tiles_y": 0, "tiles": [] }"#;
+    let file = TileMapFile::from_json(json).unwrap();
+    assert_eq!((file.map.tile_width, file.map.tile_height), (8, 8));
+}
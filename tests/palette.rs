@@ -0,0 +1,107 @@
+use graphics_test::{
+    bitmap::Color,
+    palette::{self, PALETTE_COUNT, PALETTE_SIZE},
+    resources::ResourceManager,
+};
+use image::{GrayImage, Rgba, RgbaImage};
+
+/// `colors` looked up through `indices`, what drawing an indexed sheet shows.
+fn expand(indices: &GrayImage, colors: &[Color]) -> RgbaImage {
+    RgbaImage::from_fn(indices.width(), indices.height(), |x, y| {
+        Rgba(colors[indices.get_pixel(x, y).0[0] as usize])
+    })
+}
+
+fn png(image: &RgbaImage) -> Vec<u8> {
+    let mut bytes = std::io::Cursor::new(Vec::new());
+    image.write_to(&mut bytes, image::ImageFormat::Png).unwrap();
+    bytes.into_inner()
+}
+
+/// `count` different opaque colors.
+fn colorful(count: u32) -> RgbaImage {
+    RgbaImage::from_fn(count, 1, |x, _| Rgba([x as u8, (x >> 8) as u8, 7, 255]))
+}
+
+#[test]
+fn quantize_round_trip() {
+    let red = Rgba([255, 0, 0, 255]);
+    let glass = Rgba([0, 0, 255, 128]);
+    let image = RgbaImage::from_fn(4, 2, |x, y| match (x + y) % 3 {
+        0 => Rgba([0; 4]),
+        1 => red,
+        _ => glass,
+    });
+    let (indices, colors) = palette::quantize(&image).unwrap();
+    assert_eq!(expand(&indices, &colors), image);
+    // transparent first, then in the order they show up
+    assert_eq!(colors, [[0; 4], red.0, glass.0]);
+    assert_eq!(indices.get_pixel(0, 0).0[0], 0);
+
+    // fully transparent pixels are all the same
+    let mut faded = image.clone();
+    faded.put_pixel(0, 0, Rgba([9, 9, 9, 0]));
+    assert_eq!(palette::quantize(&faded).unwrap().1.len(), 3);
+}
+
+#[test]
+fn too_many_colors() {
+    // index 0 is kept for transparency
+    let most = colorful(PALETTE_SIZE as u32 - 1);
+    let (indices, colors) = palette::quantize(&most).unwrap();
+    assert_eq!(colors.len(), PALETTE_SIZE);
+    assert_eq!(expand(&indices, &colors), most);
+
+    assert!(palette::quantize(&colorful(PALETTE_SIZE as u32)).is_none());
+    assert!(palette::decode_indexed(&png(&colorful(300))).is_err());
+}
+
+#[test]
+fn decode_round_trip() {
+    let image = RgbaImage::from_fn(5, 3, |x, y| Rgba([x as u8 * 40, y as u8 * 60, 0, 255]));
+    let (indices, colors) = palette::decode_indexed(&png(&image)).unwrap();
+    assert_eq!(expand(&indices, &colors), image);
+}
+
+#[test]
+fn indexed_pngs_keep_their_palette() {
+    // 4 bits per pixel, the palette deliberately not in the order colors are used
+    let palette = [0, 0, 0, 10, 20, 30, 200, 100, 50];
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut bytes, 3, 2);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Four);
+        encoder.set_palette(palette.to_vec());
+        encoder.set_trns(vec![0, 255, 128]);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[0x21, 0x00, 0x12, 0x20]).unwrap();
+    }
+    let (indices, colors) = palette::decode_indexed(&bytes).unwrap();
+    assert_eq!(
+        colors,
+        [[0, 0, 0, 0], [10, 20, 30, 255], [200, 100, 50, 128]]
+    );
+    let rows: Vec<_> = indices
+        .rows()
+        .map(|row| row.map(|p| p.0[0]).collect::<Vec<_>>())
+        .collect();
+    assert_eq!(rows, [[2, 1, 0], [1, 2, 2]]);
+}
+
+#[test]
+fn palette_indices_are_checked() {
+    let mut resources = ResourceManager::new();
+    assert!(resources.palette(PALETTE_COUNT - 1).is_some());
+    assert!(resources.palette(PALETTE_COUNT).is_none());
+    assert!(resources.palette_mut(PALETTE_COUNT).is_none());
+
+    assert!(!resources.set_palette(PALETTE_COUNT, &[[1, 2, 3, 255]]));
+    assert!(resources.set_palette(1, &[[1, 2, 3, 255]]));
+    assert_eq!(resources.palette(1).unwrap()[0], [1, 2, 3, 255]);
+
+    assert!(!resources.cycle_palette(PALETTE_COUNT, 0..2, 1));
+    assert!(!resources.cycle_palette(1, 0..PALETTE_SIZE + 1, 1));
+    assert!(resources.cycle_palette(1, 0..2, 1));
+    assert_eq!(resources.palette(1).unwrap()[1], [1, 2, 3, 255]);
+}
@@ -0,0 +1,223 @@
+use graphics_test::{
+    effect::Effect,
+    palette::{self, Palette, PALETTE_COUNT},
+    resources::TexturePixels,
+    software::{self, SoftwareLayer},
+    sprites::{Sprite, SpriteAttributes},
+    tilemap::{EdgeMode, Raster, Tile, TileMap},
+    ScreenContext,
+};
+use image::{Rgba, RgbaImage};
+
+const RED: [u8; 4] = [255, 0, 0, 255];
+const GREEN: [u8; 4] = [0, 255, 0, 255];
+const HALF_BLUE: [u8; 4] = [0, 0, 255, 128];
+
+static NO_RASTER: Raster = Raster {
+    scanlines: Vec::new(),
+    affine: None,
+    affine_scanlines: Vec::new(),
+    edge: EdgeMode::Wrap,
+};
+
+const SCREEN: ScreenContext = ScreenContext {
+    screen_px_x: 16,
+    screen_px_y: 8,
+    zoom: 1.0,
+};
+
+// 16 pixel wide blocks, tile or sprite 0 is red, 2 green and 4 translucent blue
+fn sheet() -> TexturePixels {
+    TexturePixels::Rgba(RgbaImage::from_fn(48, 8, |x, _| {
+        Rgba([RED, GREEN, HALF_BLUE][x as usize / 16])
+    }))
+}
+
+fn palettes() -> Vec<Palette> {
+    vec![palette::default_palette(); PALETTE_COUNT]
+}
+
+/// A 2x1 map, `tiles` are (sheet tile, layer).
+fn map(tiles: [(u16, u8); 2]) -> TileMap {
+    TileMap {
+        tiles_x: 2,
+        tiles_y: 1,
+        tiles: tiles
+            .iter()
+            .map(|&(x, layer)| {
+                let mut tile = Tile::default();
+                tile.x = x;
+                tile.layer = layer;
+                tile
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// A sprite covering the whole screen.
+fn sprite(tx: u16, layer: u8) -> Sprite {
+    let mut sprite = Sprite::default();
+    sprite.tx = tx;
+    sprite.layer = layer;
+    sprite.attribute.set(SpriteAttributes::XSIZE, 1);
+    sprite
+}
+
+fn sprites<'a>(sprites: &'a [Sprite], sheet: &'a TexturePixels) -> SoftwareLayer<'a> {
+    SoftwareLayer::Sprite {
+        sprites,
+        affine: &[],
+        lines: None,
+        pan_x: 0,
+        pan_y: 0,
+        sheet,
+    }
+}
+
+fn pixel(image: &RgbaImage, x: u32) -> [u8; 4] {
+    image.get_pixel(x, 4).0
+}
+
+#[test]
+fn sprites_go_between_tiles_by_priority() {
+    let sheet = sheet();
+    let map = map([(0, 1), (0, 9)]);
+    let thing = [sprite(2, 5)];
+
+    let image = software::render(
+        &SCREEN,
+        &palettes(),
+        &[
+            SoftwareLayer::TileMap {
+                map: &map,
+                raster: &NO_RASTER,
+                sheet: &sheet,
+            },
+            sprites(&thing, &sheet),
+        ],
+    );
+    assert_eq!(pixel(&image, 2), RED);
+    assert_eq!(pixel(&image, 10), GREEN);
+}
+
+#[test]
+fn equal_priorities_follow_the_layer_order() {
+    let sheet = sheet();
+    let map = map([(0, 5), (0, 5)]);
+    let thing = [sprite(2, 5)];
+    let tilemap = || SoftwareLayer::TileMap {
+        map: &map,
+        raster: &NO_RASTER,
+        sheet: &sheet,
+    };
+
+    let image = software::render(&SCREEN, &palettes(), &[sprites(&thing, &sheet), tilemap()]);
+    assert_eq!(pixel(&image, 2), GREEN);
+
+    let image = software::render(&SCREEN, &palettes(), &[tilemap(), sprites(&thing, &sheet)]);
+    assert_eq!(pixel(&image, 2), RED);
+}
+
+#[test]
+fn later_sprites_are_on_top() {
+    let sheet = sheet();
+    let thing = [sprite(2, 3), sprite(0, 3)];
+
+    let image = software::render(&SCREEN, &palettes(), &[sprites(&thing, &sheet)]);
+    assert_eq!(pixel(&image, 2), RED);
+
+    // priority wins over the instance order
+    let thing = [sprite(2, 2), sprite(0, 3)];
+    let image = software::render(&SCREEN, &palettes(), &[sprites(&thing, &sheet)]);
+    assert_eq!(pixel(&image, 2), GREEN);
+}
+
+#[test]
+fn translucent_pixels_blend_over_what_is_behind_them() {
+    let sheet = sheet();
+
+    // a translucent sprite in front of an opaque tile
+    let map_behind = map([(0, 9), (0, 9)]);
+    let thing = [sprite(4, 1)];
+    let image = software::render(
+        &SCREEN,
+        &palettes(),
+        &[
+            SoftwareLayer::TileMap {
+                map: &map_behind,
+                raster: &NO_RASTER,
+                sheet: &sheet,
+            },
+            sprites(&thing, &sheet),
+        ],
+    );
+    assert_eq!(pixel(&image, 2), [127, 0, 128, 255]);
+
+    // a translucent tile in front of an opaque sprite, the tile's layer comes first
+    let map_front = map([(4, 1), (0, 9)]);
+    let thing = [sprite(2, 5)];
+    let image = software::render(
+        &SCREEN,
+        &palettes(),
+        &[
+            SoftwareLayer::TileMap {
+                map: &map_front,
+                raster: &NO_RASTER,
+                sheet: &sheet,
+            },
+            sprites(&thing, &sheet),
+        ],
+    );
+    assert_eq!(pixel(&image, 2), [0, 127, 128, 255]);
+    assert_eq!(pixel(&image, 10), GREEN);
+}
+
+#[test]
+fn bitmaps_have_a_priority() {
+    let sheet = sheet();
+    let map = map([(0, 1), (0, 5)]);
+    let pixels = vec![GREEN; 16 * 8];
+
+    let image = software::render(
+        &SCREEN,
+        &palettes(),
+        &[
+            SoftwareLayer::TileMap {
+                map: &map,
+                raster: &NO_RASTER,
+                sheet: &sheet,
+            },
+            SoftwareLayer::Bitmap {
+                layer: 3,
+                width: 16,
+                height: 8,
+                pixels: &pixels,
+            },
+        ],
+    );
+    assert_eq!(pixel(&image, 2), RED);
+    assert_eq!(pixel(&image, 10), GREEN);
+}
+
+#[test]
+fn effects_separate_priorities() {
+    let sheet = sheet();
+    let map = map([(0, 200), (0, 200)]);
+    let thing = [sprite(2, 0)];
+
+    let image = software::render(
+        &SCREEN,
+        &palettes(),
+        &[
+            SoftwareLayer::TileMap {
+                map: &map,
+                raster: &NO_RASTER,
+                sheet: &sheet,
+            },
+            SoftwareLayer::Effect(Effect::None),
+            sprites(&thing, &sheet),
+        ],
+    );
+    assert_eq!(pixel(&image, 2), RED);
+}
@@ -0,0 +1,161 @@
+use graphics_test::{
+    palette::{self, PALETTE_COUNT},
+    resources::TexturePixels,
+    software,
+    tilemap::{Affine, EdgeMode, Raster, Tile, TileAttributes, TileMap},
+};
+use image::{Rgba, RgbaImage};
+
+/// Every pixel of the sheet is its own x and y, tile `i` of the map is tile `i` of the sheet.
+fn render(tiles_x: u16, pan_x: i32, raster: &Raster) -> RgbaImage {
+    let sheet = TexturePixels::Rgba(RgbaImage::from_fn(24, 8, |x, y| {
+        Rgba([x as u8, y as u8, 0, 255])
+    }));
+    let map = TileMap {
+        tiles_x,
+        tiles_y: 1,
+        pan_x,
+        tiles: (0..tiles_x).map(|x| tile(x, 0)).collect(),
+        ..Default::default()
+    };
+    let mut image = RgbaImage::new(8, 4);
+    let palettes = vec![palette::default_palette(); PALETTE_COUNT];
+    software::render_tilemap(&mut image, &map, raster, &sheet, &palettes, 0);
+    image
+}
+
+fn tile(x: u16, layer: u8) -> Tile {
+    let mut tile = Tile::default();
+    tile.x = x;
+    tile.layer = layer;
+    tile
+}
+
+fn texel(image: &RgbaImage, x: u32, y: u32) -> [u8; 2] {
+    let [u, v, ..] = image.get_pixel(x, y).0;
+    [u, v]
+}
+
+#[test]
+fn lines_are_offset_and_wrap() {
+    let raster = Raster {
+        scanlines: vec![(0, 0), (2, 0), (-3, 5)],
+        ..Default::default()
+    };
+    let image = render(1, 1, &raster);
+
+    assert_eq!(texel(&image, 0, 0), [1, 0]);
+    assert_eq!(texel(&image, 0, 1), [3, 1]);
+    assert_eq!(texel(&image, 0, 2), [6, 7]);
+    assert_eq!(texel(&image, 7, 2), [5, 7]);
+    // lines past the end of the table only have the map's pan
+    assert_eq!(texel(&image, 0, 3), [1, 3]);
+}
+
+#[test]
+fn an_empty_raster_changes_nothing() {
+    let zeros = Raster {
+        scanlines: vec![(0, 0); 4],
+        affine: Some(Affine::IDENTITY),
+        ..Default::default()
+    };
+    assert_eq!(render(2, 5, &Raster::default()), render(2, 5, &zeros));
+}
+
+#[test]
+fn affine_transforms_panned_pixels() {
+    // twice as big around the map's corner
+    let raster = Raster {
+        affine: Some(Affine::rotate_scale(0.0, 2.0, 2.0, [0.0, 0.0])),
+        ..Default::default()
+    };
+    let image = render(2, 0, &raster);
+    assert_eq!(texel(&image, 3, 1), [1, 0]);
+    assert_eq!(texel(&image, 7, 3), [3, 1]);
+
+    // the first lines are left alone, the last turns a quarter around (4, 2)
+    let quarter = Affine {
+        matrix: [0.0, 1.0, -1.0, 0.0],
+        center: [4.0, 2.0],
+    };
+    let raster = Raster {
+        affine: Some(quarter),
+        affine_scanlines: vec![Affine::IDENTITY; 3],
+        ..Default::default()
+    };
+    let image = render(2, 0, &raster);
+    assert_eq!(texel(&image, 6, 0), [6, 0]);
+    assert_eq!(texel(&image, 6, 3), [5, 0]);
+}
+
+#[test]
+fn edge_modes() {
+    // the first pixel is one tile left of the map
+    let edge = |edge| {
+        let image = render(
+            2,
+            -8,
+            &Raster {
+                edge,
+                ..Default::default()
+            },
+        );
+        image.get_pixel(0, 0).0
+    };
+    assert_eq!(edge(EdgeMode::Wrap), [8, 0, 0, 255]);
+    assert_eq!(edge(EdgeMode::Clamp), [0, 0, 0, 255]);
+    assert_eq!(edge(EdgeMode::Fill(tile(2, 0))), [16, 0, 0, 255]);
+    assert_eq!(edge(EdgeMode::Transparent), [0, 0, 0, 0]);
+}
+
+#[test]
+fn edges_are_drawn_per_tile() {
+    let raster = Raster {
+        edge: EdgeMode::Clamp,
+        ..Default::default()
+    };
+    assert!(!raster.is_active());
+
+    let map = TileMap {
+        tiles_x: 2,
+        tiles_y: 2,
+        tiles: (0..4).map(|x| tile(x, 0)).collect(),
+        ..Default::default()
+    };
+    let x = |tile: Option<Tile>| tile.map(|tile| tile.x);
+    assert_eq!(x(map.tile_at(-1, -3, EdgeMode::Wrap)), Some(3));
+    assert_eq!(x(map.tile_at(-1, -3, EdgeMode::Clamp)), Some(0));
+    assert_eq!(x(map.tile_at(5, 1, EdgeMode::Fill(tile(9, 0)))), Some(9));
+    assert_eq!(x(map.tile_at(-1, 0, EdgeMode::Transparent)), None);
+}
+
+#[test]
+fn tiles_can_be_any_size() {
+    let sheet = TexturePixels::Rgba(RgbaImage::from_fn(48, 16, |x, y| {
+        Rgba([x as u8, y as u8, 0, 255])
+    }));
+    let mut turned = tile(1, 0);
+    turned.attributes.set(TileAttributes::ROTATION, 1);
+    let map = TileMap {
+        tiles_x: 2,
+        tiles_y: 1,
+        pan_x: 4,
+        tile_width: 16,
+        tile_height: 8,
+        tiles: vec![tile(1, 0), turned],
+        ..Default::default()
+    };
+    let mut image = RgbaImage::new(32, 8);
+    let palettes = vec![palette::default_palette(); PALETTE_COUNT];
+    software::render_tilemap(&mut image, &map, &Raster::default(), &sheet, &palettes, 0);
+
+    // sheet tile 1 starts 16 pixels in
+    assert_eq!(texel(&image, 0, 3), [20, 3]);
+    assert_eq!(texel(&image, 11, 7), [31, 7]);
+    // turned a quarter it's drawn from an 8x16 area, its bottom left in the top left
+    assert_eq!(texel(&image, 12, 0), [16, 15]);
+    assert_eq!(texel(&image, 27, 0), [16, 0]);
+    assert_eq!(texel(&image, 12, 7), [23, 15]);
+    // and the map wraps after 32 pixels
+    assert_eq!(texel(&image, 28, 0), [16, 0]);
+}
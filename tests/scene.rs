@@ -0,0 +1,281 @@
+use graphics_test::{
+    animation::{PlaybackMode, SpriteClip, SpriteFrame, SpritePlayback},
+    effect::Effect,
+    scene::{JsonSprite, Scene, SceneCamera, SceneError, SceneLayer, SceneScreen},
+    sprites::{Sprite, SpriteAffine, SpriteAttributes, SpriteFormat, SpriteLimits},
+    tilemap::{Affine, EdgeMode, Raster, Tile},
+    Scroll,
+};
+
+fn sprite() -> Sprite {
+    let mut sprite = Sprite::default();
+    sprite.x = 300;
+    sprite.y = 12;
+    sprite.tx = 7;
+    sprite.ty = 9;
+    sprite.layer = 3;
+    sprite.palette = 12;
+    sprite.blend = 96;
+    sprite
+        .attribute
+        .set(SpriteAttributes::VERTICAL, true)
+        .set(SpriteAttributes::ROTATION, 2)
+        .set(SpriteAttributes::XSIZE, 3)
+        .set(SpriteAttributes::YSIZE, 1)
+        .set(SpriteAttributes::AFFINE, true)
+        .set(SpriteAttributes::DOUBLE_SIZE, true)
+        .set(SpriteAttributes::AFFINE_INDEX, 21);
+    sprite
+}
+
+fn raster() -> Raster {
+    let mut fill = Tile::default();
+    fill.x = 4;
+    fill.layer = 7;
+    Raster {
+        scanlines: vec![(1, -2), (0, 3)],
+        affine: Some(Affine::rotate_scale(0.5, 2.0, 1.0, [64.0, 32.5])),
+        affine_scanlines: vec![Affine::IDENTITY],
+        edge: EdgeMode::Fill(fill),
+    }
+}
+
+fn clip() -> SpriteClip {
+    SpriteClip {
+        frames: vec![SpriteFrame {
+            tx: 3,
+            y_size: 2,
+            flip_v: true,
+            duration: 0.1,
+            offset_x: -4,
+            ..Default::default()
+        }],
+    }
+}
+
+fn scene() -> Scene {
+    Scene {
+        version: graphics_test::scene::VERSION,
+        screen: SceneScreen {
+            width: 160,
+            height: 144,
+            zoom: 2.0,
+        },
+        camera: SceneCamera { x: 10, y: -3 },
+        palettes: Vec::new(),
+        textures: Vec::new(),
+        layers: vec![
+            SceneLayer::Sprite {
+                texture: "spritesheet".into(),
+                scroll: Scroll {
+                    x: -4,
+                    y: 8,
+                    parallax_x: 0.5,
+                    parallax_y: 1.0,
+                }
+                .into(),
+                sprites: vec![JsonSprite::from(&sprite())],
+                affine: vec![SpriteAffine {
+                    angle: 1.0,
+                    scale_x: 2.0,
+                    scale_y: 0.5,
+                }],
+                clips: [("walk".into(), clip())].into(),
+                playback: vec![
+                    None,
+                    Some(SpritePlayback::new("walk", PlaybackMode::PingPong)),
+                ],
+                limits: Some(SpriteLimits {
+                    flicker: true,
+                    ..SpriteLimits::SNES
+                }),
+                format: SpriteFormat::Compact,
+            },
+            SceneLayer::TileMap {
+                texture: "tiles".into(),
+                scroll: Scroll::default().into(),
+                tiles_x: 0,
+                tiles_y: 0,
+                tile_width: 16,
+                tile_height: 32,
+                tiles: Vec::new(),
+                raster: raster(),
+                animation_time: 2.5,
+            },
+            SceneLayer::Effect {
+                effect: Effect::Tint {
+                    color: [1.0, 0.5, 0.25, 0.5],
+                },
+                shader: None,
+            },
+            SceneLayer::Effect {
+                effect: Effect::Custom {
+                    params: [0.0, 1.0, 0.0, 0.0],
+                },
+                shader: Some(("invert".into(), "void main() {}".into())),
+            },
+        ],
+    }
+}
+
+#[test]
+fn sprites_keep_their_attributes() {
+    assert!(Sprite::try_from(JsonSprite::from(&sprite())).unwrap() == sprite());
+}
+
+#[test]
+fn json_round_trip() {
+    let json = scene().to_json();
+    let scene = Scene::from_json(json.as_bytes()).unwrap();
+    assert_eq!(scene.to_json(), json);
+
+    assert_eq!((scene.screen.width, scene.screen.height), (160, 144));
+    assert_eq!((scene.camera.x, scene.camera.y), (10, -3));
+    let SceneLayer::Sprite {
+        texture,
+        scroll,
+        sprites,
+        affine,
+        clips,
+        playback,
+        limits,
+        format,
+    } = &scene.layers[0]
+    else {
+        panic!("expected a sprite layer");
+    };
+    assert_eq!(texture, "spritesheet");
+    assert_eq!(
+        Scroll::from(*scroll),
+        Scroll {
+            x: -4,
+            y: 8,
+            parallax_x: 0.5,
+            parallax_y: 1.0,
+        }
+    );
+    assert!(Sprite::try_from(sprites[0]).unwrap() == sprite());
+    assert_eq!(affine[0].scale_y, 0.5);
+    assert_eq!(clips["walk"], clip());
+    assert_eq!(playback[1].as_ref().unwrap().mode, PlaybackMode::PingPong);
+    assert!(limits.is_some_and(|limits| limits.flicker && limits.sprites == 32));
+    assert_eq!(*format, SpriteFormat::Compact);
+    assert!(matches!(
+        &scene.layers[1],
+        SceneLayer::TileMap {
+            tile_width: 16,
+            tile_height: 32,
+            raster: r,
+            ..
+        } if *r == raster()
+    ));
+    assert!(matches!(
+        &scene.layers[3],
+        SceneLayer::Effect {
+            effect: Effect::Custom { .. },
+            shader: Some((name, _)),
+        } if name == "invert"
+    ));
+}
+
+#[test]
+fn other_versions_are_rejected() {
+    let mut scene = scene();
+    scene.version += 1;
+    assert!(matches!(
+        Scene::from_json(scene.to_json().as_bytes()),
+        Err(SceneError::UnsupportedVersion(_))
+    ));
+}
+
+#[test]
+fn fields_too_big_for_their_bits_are_rejected() {
+    let json = |layer: &str| {
+        format!(
+            r#"{{ "version": 1, "screen": {{ "width": 16, "height": 8, "zoom": 1.0 }},
+                "palettes": [], "textures": [], "layers": [{{ "kind": "Effect",
+                "effect": "None" }}, {layer}] }}"#
+        )
+    };
+    let sprite = json(
+        r#"{ "kind": "Sprite", "texture": "sheet", "pan_x": 0, "pan_y": 0,
+            "sprites": [{ "x": 0, "y": 0, "tx": 0, "ty": 0, "layer": 0 },
+                { "x": 0, "y": 0, "tx": 0, "ty": 0, "layer": 0, "x_size": 9 }] }"#,
+    );
+    assert!(matches!(
+        Scene::from_json(sprite.as_bytes()),
+        Err(SceneError::BadSprite {
+            layer: 1,
+            index: 1,
+            field: "x_size"
+        })
+    ));
+
+    let tile = json(
+        r#"{ "kind": "TileMap", "texture": "tiles", "pan_x": 0, "pan_y": 0, "tiles_x": 1,
+            "tiles_y": 1, "tiles": [{ "x": 0, "y": 0, "layer": 0, "palette": 16 }] }"#,
+    );
+    assert!(matches!(
+        Scene::from_json(tile.as_bytes()),
+        Err(SceneError::BadTile {
+            layer: 1,
+            index: 0,
+            field: "palette"
+        })
+    ));
+}
+
+#[test]
+fn bitmaps_bigger_than_the_screen_are_rejected() {
+    let json = |width: i32| {
+        format!(
+            r#"{{ "version": 1, "screen": {{ "width": 16, "height": 8, "zoom": 1.0 }},
+                "palettes": [], "textures": [], "layers": [{{ "kind": "Bitmap", "layer": 0,
+                "width": {width}, "height": 8, "pixels": "" }}] }}"#
+        )
+    };
+    assert!(Scene::from_json(json(16).as_bytes()).is_ok());
+    assert!(matches!(
+        Scene::from_json(json(17).as_bytes()),
+        Err(SceneError::BadData(_))
+    ));
+    assert!(matches!(
+        Scene::from_json(json(i32::MAX).as_bytes()),
+        Err(SceneError::BadData(_))
+    ));
+}
+
+#[test]
+fn scenes_without_parallax_move_with_the_camera() {
+    let json = r#"{
+        "version": 1,
+        "screen": { "width": 16, "height": 8, "zoom": 1.0 },
+        "palettes": [],
+        "textures": [],
+        "layers": [{ "kind": "Sprite", "texture": "sheet", "pan_x": 3, "pan_y": 4,
+            "sprites": [{ "x": 5, "y": 6, "tx": 1, "ty": 2, "layer": 0, "palette": 4 }] }]
+    }"#;
+    let scene = Scene::from_json(json.as_bytes()).unwrap();
+    assert_eq!((scene.camera.x, scene.camera.y), (0, 0));
+    let SceneLayer::Sprite {
+        scroll,
+        sprites,
+        format,
+        ..
+    } = &scene.layers[0]
+    else {
+        panic!("expected a sprite layer");
+    };
+    // from before sprites had their own palette and blend
+    let sprite = Sprite::try_from(sprites[0]).unwrap();
+    assert_eq!((sprite.palette, sprite.blend), (4, 255));
+    assert_eq!(*format, SpriteFormat::Extended);
+    assert_eq!(
+        Scroll::from(*scroll),
+        Scroll {
+            x: 3,
+            y: 4,
+            ..Default::default()
+        }
+    );
+}
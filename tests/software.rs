@@ -0,0 +1,232 @@
+use graphics_test::{
+    palette::{self, Palette, PALETTE_COUNT},
+    resources::TexturePixels,
+    software,
+    sprites::{Sprite, SpriteAttributes},
+    tilemap::{Raster, Tile, TileAttributes, TileMap},
+};
+use image::{GrayImage, Luma, Rgba, RgbaImage};
+
+/// Every pixel of the sheet is its own x and y.
+fn sheet() -> TexturePixels {
+    TexturePixels::Rgba(RgbaImage::from_fn(32, 32, |x, y| {
+        Rgba([x as u8, y as u8, 0, 255])
+    }))
+}
+
+fn palettes() -> Vec<Palette> {
+    vec![palette::default_palette(); PALETTE_COUNT]
+}
+
+fn texel(image: &RgbaImage, x: u32, y: u32) -> [u8; 2] {
+    let [u, v, ..] = image.get_pixel(x, y).0;
+    [u, v]
+}
+
+fn tile(x: u16, flip_h: bool, flip_v: bool, rotation: u16) -> Tile {
+    let mut tile = Tile::default();
+    tile.x = x;
+    tile.attributes
+        .set(TileAttributes::HORIZONTAL, flip_h)
+        .set(TileAttributes::VERTICAL, flip_v)
+        .set(TileAttributes::ROTATION, rotation);
+    tile
+}
+
+fn render_map(map: &TileMap, width: u32, height: u32) -> RgbaImage {
+    let mut image = RgbaImage::new(width, height);
+    software::render_tilemap(
+        &mut image,
+        map,
+        &Raster::default(),
+        &sheet(),
+        &palettes(),
+        0,
+    );
+    image
+}
+
+fn render_sprite(sprite: Sprite, pan_x: i32, pan_y: i32) -> RgbaImage {
+    let mut image = RgbaImage::new(40, 40);
+    software::render_sprites(
+        &mut image,
+        &[sprite],
+        &[],
+        None,
+        pan_x,
+        pan_y,
+        &sheet(),
+        &palettes(),
+        0,
+    );
+    image
+}
+
+// pixel 1, 2 of a tile, turned clockwise and mirrored like the shaders do
+const TRANSFORMS: [(bool, bool, u16, [u8; 2]); 6] = [
+    (false, false, 0, [1, 2]),
+    (true, false, 0, [6, 2]),
+    (false, true, 0, [1, 5]),
+    (false, false, 1, [2, 6]),
+    (false, false, 2, [6, 5]),
+    (false, false, 3, [5, 1]),
+];
+
+#[test]
+fn tiles_flip_and_rotate() {
+    for (flip_h, flip_v, rotation, expected) in TRANSFORMS {
+        let map = TileMap {
+            tiles_x: 1,
+            tiles_y: 1,
+            tiles: vec![tile(0, flip_h, flip_v, rotation)],
+            ..Default::default()
+        };
+        let image = render_map(&map, 8, 8);
+        assert_eq!(
+            texel(&image, 1, 2),
+            expected,
+            "flip {flip_h} {flip_v} rotation {rotation}"
+        );
+    }
+}
+
+#[test]
+fn sprites_flip_and_rotate() {
+    for (flip_h, flip_v, rotation, expected) in TRANSFORMS {
+        let mut sprite = Sprite::default();
+        sprite
+            .attribute
+            .set(SpriteAttributes::HORIZONTAL, flip_h)
+            .set(SpriteAttributes::VERTICAL, flip_v)
+            .set(SpriteAttributes::ROTATION, rotation as u32);
+        let image = render_sprite(sprite, 0, 0);
+        assert_eq!(
+            texel(&image, 1, 2),
+            expected,
+            "flip {flip_h} {flip_v} rotation {rotation}"
+        );
+    }
+
+    // a quarter turn stands a 16x8 sprite on its side
+    let mut sprite = Sprite::default();
+    sprite
+        .attribute
+        .set(SpriteAttributes::XSIZE, 1)
+        .set(SpriteAttributes::ROTATION, 1);
+    assert_eq!(sprite.size(), (8, 16));
+    let image = render_sprite(sprite, 0, 0);
+    assert_eq!(texel(&image, 0, 15), [15, 7]);
+    assert_eq!(image.get_pixel(8, 0).0[3], 0);
+}
+
+#[test]
+fn sprites_are_8_to_32_pixels() {
+    for x_size in 0..4 {
+        for y_size in 0..4 {
+            let mut sprite = Sprite::default();
+            sprite
+                .attribute
+                .set(SpriteAttributes::XSIZE, x_size)
+                .set(SpriteAttributes::YSIZE, y_size);
+            let (width, height) = (x_size * 8 + 8, y_size * 8 + 8);
+            assert_eq!(sprite.size(), (width as i32, height as i32));
+
+            let image = render_sprite(sprite, 0, 0);
+            let last = [width as u8 - 1, height as u8 - 1];
+            assert_eq!(texel(&image, width - 1, height - 1), last);
+            assert_eq!(image.get_pixel(width, 0).0[3], 0);
+            assert_eq!(image.get_pixel(0, height).0[3], 0);
+        }
+    }
+}
+
+#[test]
+fn pans_wrap_around_the_map() {
+    let map = |pan_x, pan_y| TileMap {
+        tiles_x: 2,
+        tiles_y: 1,
+        pan_x,
+        pan_y,
+        tiles: vec![tile(0, false, false, 0), tile(1, false, false, 0)],
+        ..Default::default()
+    };
+    // 3 pixels left of the map is 5 pixels into its last tile
+    let image = render_map(&map(-3, -1), 4, 2);
+    assert_eq!(texel(&image, 0, 0), [13, 7]);
+    assert_eq!(texel(&image, 3, 1), [0, 0]);
+    // a whole map further is the same
+    assert_eq!(render_map(&map(13, 7), 4, 2), image);
+    assert_eq!(render_map(&map(-19, -9), 4, 2), image);
+}
+
+#[test]
+fn sprites_move_against_the_pan() {
+    let mut sprite = Sprite::default();
+    sprite.x = -4;
+    sprite.y = 2;
+    // cut off on the left
+    let image = render_sprite(sprite, 0, 0);
+    assert_eq!(texel(&image, 0, 2), [4, 0]);
+    assert_eq!(image.get_pixel(4, 2).0[3], 0);
+
+    // a negative pan moves it right and down
+    let image = render_sprite(sprite, -10, -3);
+    assert_eq!(texel(&image, 6, 5), [0, 0]);
+    assert_eq!(image.get_pixel(5, 5).0[3], 0);
+}
+
+#[test]
+fn indexed_sheets_use_their_palette() {
+    let sheet = TexturePixels::Indexed(GrayImage::from_pixel(8, 8, Luma([3])));
+    let mut palettes = palettes();
+    palettes[2][3] = [10, 20, 30, 255];
+
+    let mut tile = tile(0, false, false, 0);
+    tile.attributes.set(TileAttributes::PALETTE, 2);
+    let map = TileMap {
+        tiles_x: 1,
+        tiles_y: 1,
+        tiles: vec![tile],
+        ..Default::default()
+    };
+    let mut image = RgbaImage::new(8, 8);
+    software::render_tilemap(&mut image, &map, &Raster::default(), &sheet, &palettes, 0);
+    assert_eq!(image.get_pixel(0, 0).0, [10, 20, 30, 255]);
+
+    let mut sprite = Sprite::default();
+    sprite.palette = 0;
+    let mut image = RgbaImage::new(8, 8);
+    software::render_sprites(&mut image, &[sprite], &[], None, 0, 0, &sheet, &palettes, 0);
+    assert_eq!(image.get_pixel(0, 0).0, [3, 3, 3, 255]);
+}
+
+#[test]
+fn palettes_wrap_around_the_ones_there_are() {
+    let sheet = TexturePixels::Indexed(GrayImage::from_pixel(8, 8, Luma([3])));
+    let mut tile = tile(0, false, false, 0);
+    tile.attributes.set(TileAttributes::PALETTE, 5);
+    let map = TileMap {
+        tiles_x: 1,
+        tiles_y: 1,
+        tiles: vec![tile],
+        ..Default::default()
+    };
+    let mut sprite = Sprite::default();
+    sprite.palette = 7;
+
+    let mut red = palette::default_palette();
+    red[3] = [255, 0, 0, 255];
+    // palette 5 and 7 of two are the second one, none at all is the default palette
+    for (palettes, color) in [
+        (vec![palette::default_palette(), red], [255, 0, 0, 255]),
+        (Vec::new(), [3, 3, 3, 255]),
+    ] {
+        let mut image = RgbaImage::new(8, 8);
+        software::render_tilemap(&mut image, &map, &Raster::default(), &sheet, &palettes, 0);
+        assert_eq!(image.get_pixel(0, 0).0, color);
+
+        let mut image = RgbaImage::new(8, 8);
+        software::render_sprites(&mut image, &[sprite], &[], None, 0, 0, &sheet, &palettes, 0);
+        assert_eq!(image.get_pixel(0, 0).0, color);
+    }
+}
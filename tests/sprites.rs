@@ -0,0 +1,255 @@
+use std::f32::consts::FRAC_PI_2;
+
+use graphics_test::{
+    palette::{self, PALETTE_COUNT},
+    resources::TexturePixels,
+    software,
+    sprites::{CompactSprite, Sprite, SpriteAffine, SpriteAttributes, SpriteLimits, SpriteLines},
+    OutOfRange,
+};
+use image::{Rgba, RgbaImage};
+
+/// Draws an 8x8 sprite whose pixels are their own x and y at 4, 4 with the first set.
+fn render(affine: SpriteAffine, double_size: bool) -> RgbaImage {
+    let sheet = TexturePixels::Rgba(RgbaImage::from_fn(8, 8, |x, y| {
+        Rgba([x as u8, y as u8, 0, 255])
+    }));
+    let mut sprite = Sprite::default();
+    sprite.x = 4;
+    sprite.y = 4;
+    sprite
+        .attribute
+        .set(SpriteAttributes::AFFINE, true)
+        .set(SpriteAttributes::DOUBLE_SIZE, double_size);
+
+    let mut image = RgbaImage::new(24, 24);
+    let palettes = vec![palette::default_palette(); PALETTE_COUNT];
+    software::render_sprites(
+        &mut image,
+        &[sprite],
+        &[affine],
+        None,
+        0,
+        0,
+        &sheet,
+        &palettes,
+        0,
+    );
+    image
+}
+
+fn texel(image: &RgbaImage, x: u32, y: u32) -> Option<[u8; 2]> {
+    let [u, v, _, a] = image.get_pixel(x, y).0;
+    (a != 0).then_some([u, v])
+}
+
+#[test]
+fn untransformed_sets_draw_like_normal_sprites() {
+    let image = render(SpriteAffine::default(), false);
+    assert_eq!(texel(&image, 4, 4), Some([0, 0]));
+    assert_eq!(texel(&image, 11, 6), Some([7, 2]));
+    assert_eq!(texel(&image, 12, 4), None);
+
+    // twice the bounds with the sprite in the middle
+    let image = render(SpriteAffine::default(), true);
+    assert_eq!(texel(&image, 7, 7), None);
+    assert_eq!(texel(&image, 8, 8), Some([0, 0]));
+    assert_eq!(texel(&image, 15, 15), Some([7, 7]));
+}
+
+#[test]
+fn sprites_turn_clockwise_and_scale() {
+    let quarter = SpriteAffine {
+        angle: FRAC_PI_2,
+        ..Default::default()
+    };
+    let image = render(quarter, false);
+    assert_eq!(texel(&image, 11, 4), Some([0, 0]));
+    assert_eq!(texel(&image, 4, 4), Some([0, 7]));
+
+    // twice as big fills the double size bounds
+    let double = SpriteAffine {
+        scale_x: 2.0,
+        scale_y: 2.0,
+        ..Default::default()
+    };
+    let image = render(double, true);
+    assert_eq!(texel(&image, 4, 4), Some([0, 0]));
+    assert_eq!(texel(&image, 19, 19), Some([7, 7]));
+    // without it the corners are cut off
+    let image = render(double, false);
+    assert_eq!(texel(&image, 4, 4), Some([2, 2]));
+}
+
+#[test]
+fn sprites_hang_off_the_top_left() {
+    let sheet = TexturePixels::Rgba(RgbaImage::from_fn(8, 8, |x, y| {
+        Rgba([x as u8, y as u8, 0, 255])
+    }));
+    let mut sprite = Sprite::default();
+    sprite.x = -3;
+    sprite.y = -5;
+    let palettes = vec![palette::default_palette(); PALETTE_COUNT];
+    let render = |sprite: Sprite| {
+        let mut image = RgbaImage::new(8, 8);
+        software::render_sprites(&mut image, &[sprite], &[], None, 0, 0, &sheet, &palettes, 0);
+        image
+    };
+
+    let image = render(sprite);
+    assert_eq!(image.get_pixel(0, 0).0, [3, 5, 0, 255]);
+    assert_eq!(image.get_pixel(4, 2).0, [7, 7, 0, 255]);
+    assert_eq!(image.get_pixel(5, 3).0[3], 0);
+
+    sprite.blend = 128;
+    assert_eq!(render(sprite).get_pixel(4, 2).0[3], 128);
+}
+
+#[test]
+fn compact_sprites_lose_what_does_not_fit() {
+    // the packed record sprites had before the extended one
+    assert_eq!(std::mem::size_of::<CompactSprite>(), 8);
+
+    let mut sprite = Sprite::default();
+    sprite.x = 65535;
+    sprite.y = 3;
+    sprite.tx = 255;
+    sprite.ty = 2;
+    sprite.palette = 18;
+    sprite.blend = 100;
+    sprite
+        .attribute
+        .set(SpriteAttributes::XSIZE, 3)
+        .set(SpriteAttributes::ROTATION, 1)
+        .set(SpriteAttributes::AFFINE, true);
+
+    let compact = CompactSprite::try_from(&sprite).unwrap();
+    assert_eq!((compact.x, compact.y, compact.tx), (65535, 3, 255));
+    assert_eq!(compact.attribute.bits(), 0b0011_0100);
+
+    let back = Sprite::from(compact);
+    assert_eq!((back.x, back.tx, back.ty), (65535, 255, 2));
+    assert_eq!((back.palette, back.blend), (0, 255));
+    assert!(!back.attribute.get(SpriteAttributes::AFFINE));
+    assert_eq!(back.size(), (8, 32));
+}
+
+#[test]
+fn compact_sprites_reject_what_does_not_fit() {
+    for (field, change) in [
+        (
+            "x",
+            (|sprite: &mut Sprite| sprite.x = -3) as fn(&mut Sprite),
+        ),
+        ("y", |sprite| sprite.y = 65536),
+        ("tx", |sprite| sprite.tx = 256),
+        ("ty", |sprite| sprite.ty = 300),
+    ] {
+        let mut sprite = Sprite::default();
+        change(&mut sprite);
+        assert_eq!(
+            CompactSprite::try_from(&sprite).err(),
+            Some(OutOfRange(field))
+        );
+    }
+}
+
+/// Sprites on the same lines, sprite `i` is 8 pixels wide, at layer `layers[i]` and `i` lines
+/// down.
+fn row(layers: &[u8]) -> Vec<Sprite> {
+    layers
+        .iter()
+        .enumerate()
+        .map(|(index, &layer)| {
+            let mut sprite = Sprite::default();
+            sprite.x = index as i32 * 8;
+            sprite.y = index as i32;
+            sprite.layer = layer;
+            sprite
+        })
+        .collect()
+}
+
+#[test]
+fn sprites_past_the_limit_are_dropped_from_the_back() {
+    let limits = SpriteLimits {
+        sprites: 2,
+        pixels: 256,
+        flicker: false,
+    };
+    // in front to back order: 2, 3, 0, 1
+    let sprites = row(&[1, 5, 0, 1]);
+    let lines = SpriteLines::new(&sprites, 0, 16, limits, 0);
+    // line 3 has all of them
+    assert!(lines.is_drawn(2, 3) && lines.is_drawn(3, 3));
+    assert!(!lines.is_drawn(0, 3) && !lines.is_drawn(1, 3));
+    // sprite 3 doesn't start until line 3
+    assert!(lines.is_drawn(0, 2) && !lines.is_drawn(1, 2));
+    assert_eq!(lines.dropped[..10], [0, 0, 1, 2, 2, 2, 2, 2, 1, 0]);
+
+    // a panned screen, line 0 is line 3 of the sprites
+    let lines = SpriteLines::new(&sprites, 3, 16, limits, 0);
+    assert_eq!(lines.dropped[0], 2);
+    assert!(!lines.is_drawn(0, -1));
+}
+
+#[test]
+fn wide_sprites_use_up_the_pixels() {
+    let mut sprites = row(&[0, 0, 0]);
+    sprites[2].attribute.set(SpriteAttributes::XSIZE, 3);
+    let limits = SpriteLimits {
+        sprites: 8,
+        pixels: 24,
+        flicker: false,
+    };
+    let lines = SpriteLines::new(&sprites, 0, 16, limits, 0);
+    // the later, 32 pixel sprite is in front and doesn't fit
+    assert!(!lines.is_drawn(2, 4));
+    assert!(lines.is_drawn(1, 4) && lines.is_drawn(0, 4));
+}
+
+#[test]
+fn flicker_rotates_the_dropped_sprites() {
+    let sprites = row(&[0, 0, 0]);
+    let limits = SpriteLimits {
+        sprites: 2,
+        pixels: 256,
+        flicker: true,
+    };
+    let dropped: Vec<_> = (0..3)
+        .map(|phase| {
+            let lines = SpriteLines::new(&sprites, 0, 8, limits, phase);
+            (0..3).find(|&sprite| !lines.is_drawn(sprite, 4))
+        })
+        .collect();
+    // every sprite takes a turn
+    assert_eq!(dropped, [Some(0), Some(2), Some(1)]);
+}
+
+#[test]
+fn dropped_lines_are_not_drawn() {
+    let sheet = TexturePixels::Rgba(RgbaImage::from_pixel(8, 8, Rgba([255; 4])));
+    let palettes = vec![palette::default_palette(); PALETTE_COUNT];
+    let sprites = row(&[0, 0]);
+    let limits = SpriteLimits {
+        sprites: 1,
+        ..SpriteLimits::NES
+    };
+    let lines = SpriteLines::new(&sprites, 0, 16, limits, 0);
+    let mut image = RgbaImage::new(16, 16);
+    software::render_sprites(
+        &mut image,
+        &sprites,
+        &[],
+        Some(&lines),
+        0,
+        0,
+        &sheet,
+        &palettes,
+        0,
+    );
+    // the later sprite is in front, the first one only shows on its line above it
+    assert_eq!(image.get_pixel(0, 0).0[3], 255);
+    assert_eq!(image.get_pixel(0, 1).0[3], 0);
+    assert_eq!(image.get_pixel(8, 1).0[3], 255);
+}
@@ -0,0 +1,160 @@
+use graphics_test::{
+    palette::{self, PALETTE_COUNT},
+    resources::TexturePixels,
+    software,
+    tiled::{self, TiledMap},
+    tilemap::{Raster, Tile, TileMap},
+    Scroll,
+};
+use image::{Rgba, RgbaImage};
+
+const GIDS: [u32; 4] = [1, 0, 2 | 1 << 31, 3 | 1 << 30 | 1 << 29];
+
+fn tmx(data: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" width="2" height="2" tilewidth="8" tileheight="8" infinite="0">
+ <tileset firstgid="1" name="tiles" tilewidth="8" tileheight="8" columns="2">
+  <image source="../art/spritesheet.png" width="16" height="16"/>
+ </tileset>
+ <tileset firstgid="5" source="other.tsx"/>
+ <group name="group">
+  <layer id="1" name="ground" width="2" height="2">
+   {data}
+  </layer>
+ </group>
+ <objectgroup id="2" name="objects"/>
+</map>"#
+    )
+}
+
+#[test]
+fn tmx_layer_encodings() {
+    for data in [
+        "<data encoding=\"csv\">\n1,0,\n2147483650,1610612739\n</data>".to_owned(),
+        "<data><tile gid=\"1\"/><tile/><tile gid=\"2147483650\"/><tile gid=\"1610612739\"/></data>"
+            .to_owned(),
+        "<data encoding=\"base64\">\n   AQAAAAAAAAACAACAAwAAYA==\n  </data>".to_owned(),
+        "<data encoding=\"base64\" compression=\"zlib\">eJxjZIAAJgaGBmYGhgQAAxwA5w==</data>"
+            .to_owned(),
+        "<data encoding=\"base64\" compression=\"gzip\">H4sIAAAAAAACA2NkgAAmBoYGZgaGBACdoxfuEAAAAA==</data>"
+            .to_owned(),
+    ] {
+        let map = TiledMap::parse(tmx(&data).as_bytes()).unwrap();
+        assert_eq!((map.tile_width, map.tile_height), (8, 8));
+        assert_eq!(map.layers.len(), 1);
+        assert_eq!(map.layers[0].name, "ground");
+        assert_eq!(map.layers[0].gids, GIDS);
+
+        assert_eq!(map.tilesets.len(), 2);
+        assert_eq!(map.tilesets[0].columns, Some(2));
+        assert_eq!(
+            map.tilesets[0].image.as_deref(),
+            Some("../art/spritesheet.png")
+        );
+        assert_eq!(map.tilesets[1].source.as_deref(), Some("other.tsx"));
+        assert_eq!(map.tileset(GIDS[3]).unwrap().first_gid, 1);
+        assert_eq!(map.tileset(6).unwrap().first_gid, 5);
+    }
+}
+
+#[test]
+fn tmj_layer_encodings() {
+    for data in [
+        r#""data": [1, 0, 2147483650, 1610612739]"#,
+        r#""data": "eJxjZIAAJgaGBmYGhgQAAxwA5w==", "encoding": "base64", "compression": "zlib""#,
+    ] {
+        let json = format!(
+            r#"{{
+                "width": 2, "height": 2, "tilewidth": 8, "tileheight": 8, "infinite": false,
+                "tilesets": [{{ "firstgid": 1, "name": "tiles", "image": "spritesheet.png", "columns": 2 }}],
+                "layers": [
+                    {{ "type": "group", "name": "group", "layers": [
+                        {{ "type": "tilelayer", "name": "ground", "width": 2, "height": 2, {data} }}
+                    ] }},
+                    {{ "type": "objectgroup", "name": "objects", "objects": [] }}
+                ]
+            }}"#
+        );
+        let map = TiledMap::parse(json.as_bytes()).unwrap();
+        assert_eq!(map.layers.len(), 1);
+        assert_eq!(map.layers[0].gids, GIDS);
+        assert_eq!(map.tilesets[0].image.as_deref(), Some("spritesheet.png"));
+    }
+}
+
+#[test]
+fn infinite_maps_are_rejected() {
+    let xml = tmx("").replace("infinite=\"0\"", "infinite=\"1\"");
+    assert!(TiledMap::parse(xml.as_bytes()).is_err());
+}
+
+/// Every combination of Tiled's flags draws the tile the way Tiled does: diagonal flip
+/// (swapping x and y) first, then horizontal, then vertical.
+#[test]
+fn flip_flags_match_tiled() {
+    let sheet = TexturePixels::Rgba(RgbaImage::from_fn(8, 8, |x, y| {
+        Rgba([x as u8 * 30, y as u8 * 30, 0, 255])
+    }));
+    let palettes = vec![palette::default_palette(); PALETTE_COUNT];
+
+    for flags in 0..8u32 {
+        let (horizontal, vertical, diagonal) = (flags & 4 != 0, flags & 2 != 0, flags & 1 != 0);
+        let mut tile = Tile::default();
+        tiled::apply_flip_flags(&mut tile, 1 | flags << 29);
+        let map = TileMap {
+            tiles_x: 1,
+            tiles_y: 1,
+            tiles: vec![tile],
+            ..Default::default()
+        };
+        let mut image = RgbaImage::new(8, 8);
+        software::render_tilemap(&mut image, &map, &Raster::default(), &sheet, &palettes, 0);
+
+        for y in 0..8 {
+            for x in 0..8 {
+                let (mut u, mut v) = (x, y);
+                if vertical {
+                    v = 7 - v;
+                }
+                if horizontal {
+                    u = 7 - u;
+                }
+                if diagonal {
+                    (u, v) = (v, u);
+                }
+                let TexturePixels::Rgba(sheet) = &sheet else {
+                    unreachable!()
+                };
+                assert_eq!(
+                    image.get_pixel(x, y),
+                    sheet.get_pixel(u, v),
+                    "flags {flags:03b} at {x}, {y}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn offsets_and_parallax_nest() {
+    let xml = tmx("<data encoding=\"csv\">1,0,0,0</data>")
+        .replace(
+            "<group name=\"group\">",
+            "<group name=\"group\" offsetx=\"8\" offsety=\"-2\" parallaxx=\"0.5\">",
+        )
+        .replace(
+            "name=\"ground\"",
+            "name=\"ground\" offsetx=\"4.4\" parallaxx=\"0.5\" parallaxy=\"0\"",
+        );
+    let map = TiledMap::parse(xml.as_bytes()).unwrap();
+    assert_eq!(
+        map.layers[0].scroll,
+        Scroll {
+            x: -12,
+            y: 2,
+            parallax_x: 0.25,
+            parallax_y: 0.0,
+        }
+    );
+}
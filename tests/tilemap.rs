@@ -0,0 +1,99 @@
+use graphics_test::tilemap::{Tile, TileAttributes, TileMap};
+
+fn tile(x: u16) -> Tile {
+    let mut tile = Tile::default();
+    tile.x = x;
+    tile
+}
+
+/// A map with one row per string and one tile per digit, the digit being the tile's x.
+fn map(rows: &[&str]) -> TileMap {
+    TileMap {
+        tiles_x: rows[0].len() as u16,
+        tiles_y: rows.len() as u16,
+        tiles: rows
+            .iter()
+            .flat_map(|row| row.bytes().map(|digit| tile((digit - b'0') as u16)))
+            .collect(),
+        ..Default::default()
+    }
+}
+
+fn rows(map: &TileMap) -> Vec<String> {
+    map.tiles
+        .chunks(map.tiles_x as usize)
+        .map(|row| row.iter().map(|tile| tile.x.to_string()).collect())
+        .collect()
+}
+
+#[test]
+fn resize_keeps_the_overlap() {
+    let mut tiles = map(&["123", "456", "789"]);
+    tiles.resize(2, 2);
+    assert_eq!(rows(&tiles), ["12", "45"]);
+
+    // new tiles are the default one
+    tiles.resize(3, 4);
+    assert_eq!(tiles.tiles.len(), 12);
+    assert_eq!(rows(&tiles), ["120", "450", "000", "000"]);
+
+    tiles.resize(4, 1);
+    assert_eq!(rows(&tiles), ["1200"]);
+
+    tiles.resize(0, 3);
+    assert!(tiles.tiles.is_empty());
+    assert_eq!(tiles.get(0, 0), None);
+}
+
+#[test]
+fn flood_fill_stays_inside_the_walls() {
+    let mut tiles = map(&[
+        "00000", //
+        "01110", //
+        "01010", //
+        "01110", //
+        "00000",
+    ]);
+    tiles.flood_fill(2, 2, tile(2));
+    assert_eq!(rows(&tiles), ["00000", "01110", "01210", "01110", "00000"]);
+
+    // diagonals don't connect, the outside reaches around the walls
+    let mut tiles = map(&[
+        "010", //
+        "101", //
+        "010",
+    ]);
+    tiles.flood_fill(0, 0, tile(3));
+    assert_eq!(rows(&tiles), ["310", "101", "010"]);
+    tiles.flood_fill(1, 0, tile(3));
+    assert_eq!(rows(&tiles), ["330", "101", "010"]);
+}
+
+#[test]
+fn flood_fill_with_the_same_tile_changes_nothing() {
+    let before = map(&["110", "010"]);
+    let mut tiles = before.clone();
+    tiles.flood_fill(0, 0, tile(1));
+    assert!(tiles == before);
+
+    // a tile that only differs in its attributes is a different one
+    let mut flipped = tile(1);
+    flipped.attributes.set(TileAttributes::HORIZONTAL, true);
+    tiles.flood_fill(0, 0, flipped);
+    assert_eq!(tiles.get(1, 1), Some(flipped));
+    assert_eq!(tiles.get(2, 0), Some(tile(0)));
+}
+
+#[test]
+fn flood_fill_outside_the_map_changes_nothing() {
+    let before = map(&["00", "00"]);
+    let mut tiles = before.clone();
+    for (x, y) in [(-1, 0), (0, -1), (2, 0), (0, 2)] {
+        tiles.flood_fill(x, y, tile(5));
+    }
+    assert!(tiles == before);
+
+    let mut empty = TileMap::default();
+    empty.flood_fill(0, 0, tile(5));
+    assert!(empty.tiles.is_empty());
+}
@@ -0,0 +1,101 @@
+use std::path::PathBuf;
+
+use graphics_test::{
+    palette::{self, PALETTE_COUNT},
+    resources::TexturePixels,
+    software,
+    tilemap::{Raster, Tile, TileAttributes, TileMap},
+    world::{WorldError, WorldMap, CHUNK_TILES},
+};
+use image::{Rgba, RgbaImage};
+
+fn tile(x: u16) -> Tile {
+    let mut tile = Tile::default();
+    tile.x = x;
+    tile
+}
+
+/// An empty directory of its own for every test.
+fn directory(name: &str) -> PathBuf {
+    let directory =
+        std::env::temp_dir().join(format!("graphics_test-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    directory
+}
+
+#[test]
+fn tiles_go_into_chunks() {
+    let mut world = WorldMap::new("tiles", None);
+    let size = CHUNK_TILES as i32;
+    assert_eq!(WorldMap::chunk_of(-1, size), (-1, 1));
+    assert_eq!(world.get(-1, size), None);
+
+    world.set(-1, size, tile(3)).unwrap();
+    assert!(world.is_loaded((-1, 1)));
+    assert_eq!(world.get(-1, size), Some(tile(3)));
+    // the rest of a new chunk is empty
+    assert!(world
+        .get(-2, size)
+        .is_some_and(|tile| tile.attributes.get(TileAttributes::HIDDEN)));
+
+    // nowhere to save them, so chunks in memory stay
+    world.stream(1000, 1000, 4, 4).unwrap();
+    assert_eq!(world.get(-1, size), Some(tile(3)));
+}
+
+#[test]
+fn far_chunks_are_saved_and_loaded_again() {
+    let directory = directory("stream");
+    let mut world = WorldMap::new("tiles", Some(directory.clone()));
+    world.set(5, -3, tile(7)).unwrap();
+
+    world.stream(0, 0, 10, 10).unwrap();
+    assert!(world.is_loaded((1, 1)));
+    assert!(world.is_loaded((-1, -1)));
+
+    world.stream(CHUNK_TILES as i32 * 10, 0, 10, 10).unwrap();
+    assert!(!world.is_loaded((0, -1)));
+    assert!(directory.join("0_-1.tmap").exists());
+    // untouched chunks aren't written
+    assert!(!directory.join("1_1.tmap").exists());
+
+    world.stream(0, 0, 10, 10).unwrap();
+    assert_eq!(world.get(5, -3), Some(tile(7)));
+
+    // chunks have to match the world
+    let mut other = WorldMap::new("tiles", Some(directory.clone()));
+    other.tile_width = 16;
+    assert!(matches!(
+        other.stream(0, -1, 1, 1),
+        Err(WorldError::BadChunk((0, -1)))
+    ));
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn the_window_wraps_around() {
+    let mut world = WorldMap::new("tiles", None);
+    for x in -2..2 {
+        world.set(x, 0, tile((x + 2) as u16)).unwrap();
+    }
+    // starts half way into tile -2
+    let mut map = TileMap {
+        pan_x: -12,
+        ..Default::default()
+    };
+    world.fill_window(&mut map, 3, 1);
+    assert_eq!((map.tiles_x, map.tiles_y), (3, 1));
+    // -2 mod 3 is 1
+    assert_eq!(map.get(1, 0), Some(tile(0)));
+    assert_eq!(map.get(0, 0), Some(tile(2)));
+
+    let sheet = TexturePixels::Rgba(RgbaImage::from_fn(32, 8, |x, y| {
+        Rgba([x as u8, y as u8, 0, 255])
+    }));
+    let mut image = RgbaImage::new(16, 1);
+    let palettes = vec![palette::default_palette(); PALETTE_COUNT];
+    software::render_tilemap(&mut image, &map, &Raster::default(), &sheet, &palettes, 0);
+    for (x, u) in [(0, 4), (4, 8), (15, 19)] {
+        assert_eq!(image.get_pixel(x, 0).0[0], u);
+    }
+}